@@ -0,0 +1,386 @@
+//! Code actions for the diagnostics the client shows in a range: fixes for
+//! the problems they report, and suppressions. Selections also get refactorings.
+
+use {
+    crate::{
+        bashisms::bashisms,
+        diagnostics::{block_opened_at, opener, SOURCE},
+        lints::{BASHISM, BASH_SYNTAX, GLOBAL_SET_IN_FUNCTION, UNCLOSED_BLOCK, UNKNOWN_COMMAND},
+        parser::{command_name, Node, SyntaxKind, SyntaxTree, TextRange},
+        server::State,
+        suppressions::suppress_actions,
+        translate::translate_action,
+    },
+    std::path::PathBuf,
+    tower_lsp::lsp_types::*,
+};
+
+pub fn code_actions(state: &State, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+    let uri = &params.text_document.uri;
+    let Some(document) = state.documents.get(uri) else {
+        return Vec::new();
+    };
+    let wanted = |kind: &CodeActionKind| {
+        params.context.only.as_ref().is_none_or(|only| {
+            only.iter()
+                .any(|only| kind.as_str().starts_with(only.as_str()))
+        })
+    };
+
+    let mut actions = Vec::new();
+    if wanted(&CodeActionKind::QUICKFIX) {
+        let ours = params
+            .context
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.source.as_deref() == Some(SOURCE));
+        for diagnostic in ours {
+            actions.extend(quick_fixes(state, uri, &document.tree, diagnostic));
+            actions.extend(suppress_actions(uri, &document.tree, diagnostic));
+        }
+    }
+    if wanted(&CodeActionKind::REFACTOR_REWRITE) {
+        actions.extend(translate_action(uri, &document.tree, params.range));
+    }
+    actions
+        .into_iter()
+        .map(CodeActionOrCommand::CodeAction)
+        .collect()
+}
+
+/// Fixes for the problem `diagnostic` reports, by its code.
+fn quick_fixes(
+    state: &State,
+    uri: &Url,
+    tree: &SyntaxTree,
+    diagnostic: &Diagnostic,
+) -> Vec<CodeAction> {
+    let Some(NumberOrString::String(code)) = &diagnostic.code else {
+        return Vec::new();
+    };
+    let offset = tree.line_index().offset(diagnostic.range.start);
+    let fix = |title: String, edit: WorkspaceEdit| CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(edit),
+        is_preferred: Some(true),
+        ..Default::default()
+    };
+    let replace = |range: TextRange, new_text: String| {
+        let edit = TextEdit {
+            range: tree.lsp_range(range),
+            new_text,
+        };
+        WorkspaceEdit {
+            changes: Some([(uri.clone(), vec![edit])].into_iter().collect()),
+            ..Default::default()
+        }
+    };
+
+    match code.as_str() {
+        code if code == UNCLOSED_BLOCK.code => {
+            let Some(block) = block_opened_at(tree, offset) else {
+                return Vec::new();
+            };
+            let src = tree.text();
+            // An `end` for this block alone would close the innermost block
+            // left open inside it, so those get theirs first.
+            let mut blocks: Vec<&Node> = block
+                .descendants()
+                .filter(|node| node.kind.is_block() && node.end_keyword(src).is_none())
+                .collect();
+            blocks.reverse();
+            blocks.push(block);
+            let edits = blocks
+                .into_iter()
+                .map(|block| {
+                    let opener = opener(block).start;
+                    let line = tree
+                        .line_index()
+                        .line_range(tree.line_index().line_of(opener));
+                    let line_text = &src[line.start..line.end];
+                    let indent = &line_text[..line_text.len() - line_text.trim_start().len()];
+                    let end = block.range.end;
+                    let new_text = if end == 0 || src[..end].ends_with('\n') {
+                        format!("{indent}end\n")
+                    } else {
+                        format!("\n{indent}end")
+                    };
+                    TextEdit {
+                        range: tree.lsp_range(TextRange::empty(end)),
+                        new_text,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let title = match edits.len() {
+                1 => "Insert the missing `end`".to_string(),
+                count => format!("Insert the {count} missing `end`s"),
+            };
+            let edit = WorkspaceEdit {
+                changes: Some([(uri.clone(), edits)].into_iter().collect()),
+                ..Default::default()
+            };
+            vec![fix(title, edit)]
+        }
+        code if code == GLOBAL_SET_IN_FUNCTION.code => {
+            let command = tree
+                .root()
+                .path_at(offset)
+                .into_iter()
+                .rev()
+                .find(|node| node.kind == SyntaxKind::Command);
+            let Some(set) = command.and_then(command_name) else {
+                return Vec::new();
+            };
+            let at = TextRange::empty(set.range.end);
+            vec![
+                fix(
+                    "Make it local with `set -l`".into(),
+                    replace(at, " -l".into()),
+                ),
+                CodeAction {
+                    is_preferred: None,
+                    ..fix(
+                        "Keep it global with `set -g`".into(),
+                        replace(at, " -g".into()),
+                    )
+                },
+            ]
+        }
+        code if code == UNKNOWN_COMMAND.code => {
+            let range = tree.line_index().text_range(diagnostic.range);
+            let name = &tree.text()[range.start..range.end];
+            if !state.supports_resource_operation(ResourceOperationKind::Create) {
+                return Vec::new();
+            }
+            // The stub would be inserted at the top of an existing file.
+            let Some(file) = function_file(state, uri, name).filter(|file| !file.exists()) else {
+                return Vec::new();
+            };
+            let Ok(file_uri) = Url::from_file_path(&file) else {
+                return Vec::new();
+            };
+            let create = DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: file_uri.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(false),
+                }),
+                annotation_id: None,
+            }));
+            let stub = DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: file_uri,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(TextEdit {
+                    range: Range::default(),
+                    new_text: format!("function {name}\nend\n"),
+                })],
+            });
+            let edit = WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Operations(vec![create, stub])),
+                ..Default::default()
+            };
+            let folder = file.parent().and_then(|dir| dir.file_name());
+            let title = format!(
+                "Create `{}/{name}.fish`",
+                folder.map_or("functions".into(), |dir| dir.to_string_lossy())
+            );
+            vec![CodeAction {
+                is_preferred: None,
+                ..fix(title, edit)
+            }]
+        }
+        code if code == BASHISM.code || code == BASH_SYNTAX.code => {
+            let range = tree.line_index().text_range(diagnostic.range);
+            bashisms(tree, state.fish_version)
+                .into_iter()
+                .filter(|bashism| bashism.lint.code == code && bashism.range == range)
+                .filter_map(|bashism| {
+                    let (range, replacement) = bashism.replacement?;
+                    let title = if replacement.is_empty() {
+                        format!(
+                            "Remove `{}`",
+                            &tree.text()[bashism.range.start..bashism.range.end]
+                        )
+                    } else if replacement.len() <= 40 && !replacement.contains('\n') {
+                        format!("Replace with `{replacement}`")
+                    } else {
+                        "Replace with fish syntax".to_string()
+                    };
+                    Some(fix(title, replace(range, replacement)))
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Where an autoloaded function `name` belongs: the `functions` directory
+/// next to the document's own fish configuration, or that of the workspace
+/// folder holding it.
+fn function_file(state: &State, uri: &Url, name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(['/', '$', '(', '{', '\'', '"']) {
+        return None;
+    }
+    let path = uri.to_file_path().ok()?;
+    let dir = path.parent()?;
+    let functions = if dir.ends_with("functions") {
+        dir.to_path_buf()
+    } else if dir.ends_with("conf.d") || dir.ends_with("completions") {
+        dir.parent()?.join("functions")
+    } else if dir.join("functions").is_dir() || path.ends_with("config.fish") {
+        dir.join("functions")
+    } else {
+        let folder = state
+            .workspace_folders
+            .iter()
+            .find(|folder| path.starts_with(folder))?;
+        folder.join("functions")
+    };
+    Some(functions.join(format!("{name}.fish")))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{diagnostics::diagnostics, document::Document},
+        std::path::Path,
+    };
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/quick_fixes");
+
+    fn state_with(uri: &Url, text: &str) -> State {
+        let mut state = State::default();
+        state
+            .documents
+            .insert(uri.clone(), Document::new(text.to_string(), 1));
+        state
+    }
+
+    fn actions_for(state: &State, uri: &Url, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: diagnostic.range,
+            context: CodeActionContext {
+                diagnostics: vec![diagnostic.clone()],
+                only: Some(vec![CodeActionKind::QUICKFIX]),
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        code_actions(state, &params)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => Some(action),
+                CodeActionOrCommand::Command(_) => None,
+            })
+            .filter(|action| !action.title.starts_with("Suppress"))
+            .collect()
+    }
+
+    /// Apply the preferred fix for the first diagnostic of `codes` that has
+    /// one, again and again until none is left.
+    fn fix_all(text: &str, codes: &[&str]) -> String {
+        let uri = Url::parse("file:///fixtures/script.fish").unwrap();
+        let mut text = text.to_string();
+        for _ in 0..50 {
+            let state = state_with(&uri, &text);
+            let tree = &state.documents[&uri].tree;
+            let fix = diagnostics(&state, &uri, tree)
+                .iter()
+                .filter(|diagnostic| {
+                    codes.iter().any(|code| {
+                        diagnostic.code == Some(NumberOrString::String(code.to_string()))
+                    })
+                })
+                .find_map(|diagnostic| {
+                    actions_for(&state, &uri, diagnostic)
+                        .into_iter()
+                        .find(|action| action.is_preferred == Some(true))
+                });
+            let Some(fix) = fix else {
+                return text;
+            };
+            let mut edits = fix.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+            // Inserts at one position land in order, so apply the last first.
+            edits.sort_by_key(|edit| edit.range.start);
+            for edit in edits.into_iter().rev() {
+                let range = tree.line_index().text_range(edit.range);
+                text.replace_range(range.start..range.end, &edit.new_text);
+            }
+        }
+        panic!("fixes did not converge:\n{text}");
+    }
+
+    fn check_fixture(name: &str, codes: &[&str]) {
+        let read = |file: String| std::fs::read_to_string(Path::new(FIXTURES).join(file)).unwrap();
+        let fixed = fix_all(&read(format!("{name}.fish")), codes);
+        assert_eq!(fixed, read(format!("{name}.fixed.fish")), "{name}");
+    }
+
+    #[test]
+    fn unclosed_blocks() {
+        check_fixture("unclosed_block", &["UNCLOSED_BLOCK"]);
+    }
+
+    #[test]
+    fn global_set_in_function() {
+        check_fixture("global_set", &["GLOBAL_SET_IN_FUNCTION"]);
+    }
+
+    #[test]
+    fn bash_syntax() {
+        check_fixture("bash", &["BASHISM", "BASH_SYNTAX"]);
+    }
+
+    #[test]
+    fn unknown_command_creates_function_file() {
+        let dir = std::env::temp_dir().join(format!("fish-lsp-actions-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("functions")).unwrap();
+        let uri = Url::from_file_path(dir.join("config.fish")).unwrap();
+        let mut state = state_with(&uri, "greet\n");
+        let diagnostic = diagnostics(&state, &uri, &state.documents[&uri].tree)
+            .into_iter()
+            .find(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("UNKNOWN_COMMAND".into()))
+            })
+            .unwrap();
+        let create = |state: &State| {
+            actions_for(state, &uri, &diagnostic)
+                .into_iter()
+                .find(|action| action.title.starts_with("Create"))
+        };
+
+        // Only offered to clients that can create files.
+        assert!(create(&state).is_none());
+        state.client_capabilities.workspace = Some(WorkspaceClientCapabilities {
+            workspace_edit: Some(WorkspaceEditClientCapabilities {
+                document_changes: Some(true),
+                resource_operations: Some(vec![ResourceOperationKind::Create]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let action = create(&state).unwrap();
+        assert_eq!(action.title, "Create `functions/greet.fish`");
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("no document changes");
+        };
+        assert!(matches!(
+            &operations[0],
+            DocumentChangeOperation::Op(ResourceOp::Create(create))
+                if create.uri.path().ends_with("functions/greet.fish")
+        ));
+
+        // Never offered over an existing file.
+        std::fs::write(dir.join("functions/greet.fish"), "# elsewhere\n").unwrap();
+        assert!(create(&state).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
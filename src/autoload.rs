@@ -0,0 +1,277 @@
+//! Fish loads functions and completions lazily from the directories in
+//! `$fish_function_path` and `$fish_complete_path`: the first directory
+//! holding `NAME.fish` defines `NAME`. This models those paths for the
+//! workspace being edited, along with the executables on `$PATH`.
+
+use {
+    crate::{config::Config, definition::expand_path},
+    std::{
+        collections::HashSet,
+        env,
+        path::{Path, PathBuf},
+    },
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct AutoloadPaths {
+    pub function_path: Vec<PathBuf>,
+    pub complete_path: Vec<PathBuf>,
+}
+
+impl AutoloadPaths {
+    /// The search paths in the order fish uses them, preceded by the
+    /// workspace's own directories and those set in the client's options.
+    pub fn new(workspace_folders: &[PathBuf], config: &Config) -> Self {
+        let mut paths = AutoloadPaths::default();
+
+        for folder in workspace_folders {
+            for fish_dir in workspace_fish_dirs(folder) {
+                paths.function_path.push(fish_dir.join("functions"));
+                paths.complete_path.push(fish_dir.join("completions"));
+            }
+        }
+
+        let configured = |dirs: &[String]| -> Vec<PathBuf> {
+            dirs.iter()
+                .filter_map(|dir| expand_path(dir, None))
+                .collect()
+        };
+        paths
+            .function_path
+            .extend(configured(&config.fish_function_path));
+        paths
+            .complete_path
+            .extend(configured(&config.fish_complete_path));
+
+        let config_dir = config_home().map(|dir| dir.join("fish"));
+        let user_data_dir = data_home().map(|dir| dir.join("fish"));
+        let sysconf_dir = PathBuf::from("/etc/fish");
+        let data_dirs: Vec<PathBuf> = data_dirs().iter().map(|dir| dir.join("fish")).collect();
+
+        paths
+            .function_path
+            .extend(config_dir.iter().map(|dir| dir.join("functions")));
+        paths.function_path.push(sysconf_dir.join("functions"));
+        paths.function_path.extend(
+            user_data_dir
+                .iter()
+                .chain(&data_dirs)
+                .map(|dir| dir.join("vendor_functions.d")),
+        );
+        paths
+            .function_path
+            .extend(data_dirs.iter().map(|dir| dir.join("functions")));
+
+        paths
+            .complete_path
+            .extend(config_dir.iter().map(|dir| dir.join("completions")));
+        paths.complete_path.push(sysconf_dir.join("completions"));
+        paths.complete_path.extend(
+            user_data_dir
+                .iter()
+                .chain(&data_dirs)
+                .map(|dir| dir.join("vendor_completions.d")),
+        );
+        paths
+            .complete_path
+            .extend(data_dirs.iter().map(|dir| dir.join("completions")));
+        paths.complete_path.extend(
+            user_data_dir
+                .iter()
+                .map(|dir| dir.join("generated_completions")),
+        );
+
+        dedup(&mut paths.function_path);
+        dedup(&mut paths.complete_path);
+        paths
+    }
+
+    /// The file fish would autoload the function `name` from.
+    pub fn function_file(&self, name: &str) -> Option<PathBuf> {
+        find(&self.function_path, name)
+    }
+
+    /// The file fish would load completions for the command `name` from.
+    pub fn completion_file(&self, name: &str) -> Option<PathBuf> {
+        find(&self.complete_path, name)
+    }
+
+    /// Whether `path` is inside one of the workspace folders, as opposed to
+    /// the user's or the system's fish directories.
+    pub fn in_workspace(workspace_folders: &[PathBuf], path: &Path) -> bool {
+        workspace_folders
+            .iter()
+            .any(|folder| path.starts_with(folder))
+    }
+}
+
+fn find(path: &[PathBuf], name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let file_name = format!("{name}.fish");
+    path.iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|file| file.is_file())
+}
+
+/// Directories in a workspace laid out like a fish config directory: the
+/// folder itself, `.config/fish`, `fish` and any direct subdirectory with
+/// a `functions` or `completions` directory, such as a plugin checkout.
+fn workspace_fish_dirs(folder: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![
+        folder.to_path_buf(),
+        folder.join(".config/fish"),
+        folder.join("fish"),
+    ];
+    if let Ok(entries) = std::fs::read_dir(folder) {
+        let mut children: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.join("functions").is_dir() || path.join("completions").is_dir())
+            .collect();
+        children.sort();
+        dirs.extend(children);
+    }
+    dirs
+}
+
+fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))
+}
+
+fn data_home() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".local/share")))
+}
+
+fn data_dirs() -> Vec<PathBuf> {
+    let dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn dedup(paths: &mut Vec<PathBuf>) {
+    let mut seen = Vec::new();
+    paths.retain(|path| {
+        let new = !seen.contains(path);
+        if new {
+            seen.push(path.clone());
+        }
+        new
+    });
+}
+
+/// The names of all executables in the directories of `$PATH`.
+pub fn path_executables() -> HashSet<String> {
+    let mut executables = HashSet::new();
+    let Some(path) = env::var_os("PATH") else {
+        return executables;
+    };
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(metadata) = std::fs::metadata(entry.path()) else {
+                continue;
+            };
+            if metadata.is_file() && is_executable(&metadata) {
+                if let Some(name) = entry.file_name().to_str() {
+                    executables.insert(name.to_string());
+                }
+            }
+        }
+    }
+    executables
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_and_configured_paths() {
+        let dir = env::temp_dir().join(format!("fish-lsp-autoload-{}", std::process::id()));
+        for path in [
+            "functions/a.fish",
+            "functions/shared.fish",
+            ".config/fish/functions/b.fish",
+            "plugin/functions/c.fish",
+            "plugin/completions/c.fish",
+            "extra/d.fish",
+            "extra/shared.fish",
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let config = Config {
+            fish_function_path: vec![dir.join("extra").display().to_string()],
+            ..Default::default()
+        };
+        let paths = AutoloadPaths::new(std::slice::from_ref(&dir), &config);
+
+        let function = |name| {
+            paths
+                .function_file(name)
+                .map(|path| path.strip_prefix(&dir).unwrap().to_path_buf())
+        };
+        assert_eq!(function("a"), Some("functions/a.fish".into()));
+        assert_eq!(function("b"), Some(".config/fish/functions/b.fish".into()));
+        assert_eq!(function("c"), Some("plugin/functions/c.fish".into()));
+        assert_eq!(function("d"), Some("extra/d.fish".into()));
+        // The workspace comes before the configured directories.
+        assert_eq!(function("shared"), Some("functions/shared.fish".into()));
+        assert_eq!(function("functions/a"), None);
+        assert_eq!(function("missing"), None);
+        assert_eq!(
+            paths.completion_file("c"),
+            Some(dir.join("plugin/completions/c.fish"))
+        );
+
+        // The system directories come last.
+        let extra = paths
+            .function_path
+            .iter()
+            .position(|path| *path == dir.join("extra"))
+            .unwrap();
+        let system = paths
+            .function_path
+            .iter()
+            .position(|path| path == Path::new("/etc/fish/functions"))
+            .unwrap();
+        assert!(extra < system);
+        assert!(AutoloadPaths::in_workspace(
+            std::slice::from_ref(&dir),
+            &dir.join("functions/a.fish")
+        ));
+        assert!(!AutoloadPaths::in_workspace(
+            std::slice::from_ref(&dir),
+            Path::new("/etc/fish/functions/a.fish")
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,609 @@
+//! Bash syntax in fish scripts, with the fish spelling to replace it by.
+//!
+//! Constructs fish cannot parse are reported as [`BASH_SYNTAX`] in place of
+//! the parse errors and unknown commands they would otherwise cause. Those
+//! fish runs, but not as bash would, are [`BASHISM`]s.
+
+use {
+    crate::{
+        config::{Config, Severity},
+        diagnostics::opener,
+        fish::Version,
+        lints::{Lint, BASHISM, BASH_SYNTAX},
+        parser::{bash, command_args, command_name, Node, SyntaxKind, SyntaxTree, TextRange},
+        translate::{expansion, single_quoted, translate},
+    },
+    tower_lsp::lsp_types::Diagnostic,
+};
+
+/// The first release that parses `$(...)`.
+const DOLLAR_SUBSTITUTION: Version = Version(3, 4);
+/// The first release that parses `NAME=value command`.
+const ASSIGNMENT_PREFIX: Version = Version(3, 1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bashism {
+    pub lint: Lint,
+    pub range: TextRange,
+    pub message: String,
+    /// Fish text and the range it replaces, usually `range`, when there is a
+    /// direct equivalent.
+    pub replacement: Option<(TextRange, String)>,
+    /// Where the diagnostics this bash-ism causes start, such as the parse
+    /// errors of `${name}` or the unclosed `if` of a `fi`. They are left out
+    /// in favour of the bash-ism.
+    pub explains: Vec<TextRange>,
+}
+
+impl Bashism {
+    fn new(lint: Lint, range: TextRange, message: impl Into<String>) -> Self {
+        let explains = if lint == BASH_SYNTAX {
+            vec![range]
+        } else {
+            Vec::new()
+        };
+        Self {
+            lint,
+            range,
+            message: message.into(),
+            replacement: None,
+            explains,
+        }
+    }
+
+    fn replace(self, range: TextRange, text: impl Into<String>) -> Self {
+        Self {
+            replacement: Some((range, text.into())),
+            ..self
+        }
+    }
+}
+
+/// Add the bash-isms in `tree` to `diagnostics`, dropping the diagnostics
+/// they explain.
+pub fn bashism_diagnostics(
+    config: &Config,
+    version: Option<Version>,
+    tree: &SyntaxTree,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let bashisms: Vec<Bashism> = bashisms(tree, version)
+        .into_iter()
+        .filter(|bashism| config.severity(&bashism.lint) != Severity::Off)
+        .collect();
+    let lines = tree.line_index();
+    diagnostics.retain(|diagnostic| {
+        let start = lines.offset(diagnostic.range.start);
+        !bashisms
+            .iter()
+            .flat_map(|bashism| &bashism.explains)
+            .any(|range| range.start <= start && start < range.end.max(range.start + 1))
+    });
+    diagnostics.extend(bashisms.into_iter().map(|bashism| {
+        bashism
+            .lint
+            .diagnostic(tree.lsp_range(bashism.range), bashism.message)
+    }));
+}
+
+/// The bash-isms in `tree`, for scripts run by fish `version`. Syntax that
+/// only old releases lack is not reported when the version is unknown.
+pub fn bashisms(tree: &SyntaxTree, version: Option<Version>) -> Vec<Bashism> {
+    let src = tree.text();
+    let older = |release: Version| version.is_some_and(|version| version < release);
+    let quoted: Vec<TextRange> = tree
+        .root()
+        .descendants()
+        .filter(|node| node.kind == SyntaxKind::DoubleQuotedString)
+        .map(|node| node.range)
+        .collect();
+    let in_quotes = |offset: usize| quoted.iter().any(|range| range.contains(offset));
+    let mut found = Vec::new();
+
+    for node in tree.root().descendants() {
+        match node.kind {
+            SyntaxKind::CommandSubstitution
+                if node.first_token(SyntaxKind::Dollar).is_some() && older(DOLLAR_SUBSTITUTION) =>
+            {
+                let text = &node.text(src)[1..];
+                let replacement = match in_quotes(node.range.start) {
+                    true => format!("\"{text}\""),
+                    false => text.to_string(),
+                };
+                found.push(Bashism {
+                    explains: Vec::new(),
+                    ..Bashism::new(
+                        BASH_SYNTAX,
+                        node.range,
+                        "`$(...)` needs fish 3.4 or later; `(...)` works in every version",
+                    )
+                    .replace(node.range, replacement)
+                });
+            }
+            SyntaxKind::Command => {
+                found.extend(command_bashisms(tree, node, older(ASSIGNMENT_PREFIX)))
+            }
+            SyntaxKind::ErrorNode => found.extend(bash_case(tree, node)),
+            _ => {}
+        }
+    }
+    found.extend(expansions(tree, &in_quotes));
+
+    // Leave out bash-isms inside others, such as the text of a here document.
+    let explained: Vec<TextRange> = found
+        .iter()
+        .flat_map(|bashism| bashism.explains.iter().copied())
+        .collect();
+    found.retain(|bashism| {
+        let start = bashism.range.start;
+        !explained
+            .iter()
+            .any(|range| range.start < start && start < range.end)
+    });
+    found.sort_by_key(|bashism| bashism.range.start);
+    found
+}
+
+fn command_bashisms(tree: &SyntaxTree, command: &Node, old_assignments: bool) -> Vec<Bashism> {
+    let src = tree.text();
+    let Some(name) = command_name(command) else {
+        return Vec::new();
+    };
+    let args: Vec<_> = command_args(command).collect();
+    let mut found = Vec::new();
+
+    match name.text(src) {
+        "[[" => {
+            let close = args.last().filter(|last| last.text(src) == "]]");
+            let replacement = close.and_then(|close| {
+                let inner = &args[..args.len() - 1];
+                if inner.iter().any(|word| word.text(src) == "=~") {
+                    return None;
+                }
+                let words: Vec<&str> = inner
+                    .iter()
+                    .map(|word| match word.text(src) {
+                        "==" => "=",
+                        text => text,
+                    })
+                    .collect();
+                Some(format!("test {}", words.join(" ")).trim_end().to_string())
+            });
+            let range = match close {
+                Some(close) => name.range.cover(close.range),
+                None => name.range,
+            };
+            let bashism = Bashism::new(BASHISM, range, "fish has no `[[ ]]`; use `test` or `[ ]`");
+            found.push(match replacement {
+                Some(replacement) => bashism.replace(range, replacement),
+                None => bashism,
+            });
+        }
+        "export"
+            if !args.is_empty() && !args.iter().any(|word| word.text(src).starts_with('-')) =>
+        {
+            let assignments: Vec<String> = args
+                .iter()
+                .map(|word| {
+                    let text = word.text(src);
+                    match text.split_once('=') {
+                        Some((name, value)) => format!("set -gx {name} {value}"),
+                        None => format!("set -gx {text} ${text}"),
+                    }
+                })
+                .collect();
+            found.push(
+                Bashism::new(
+                    BASHISM,
+                    command.range,
+                    "`export` is bash; fish exports variables with `set -gx`",
+                )
+                .replace(command.range, assignments.join("; ")),
+            );
+        }
+        keyword @ ("then" | "do") => {
+            let message = match keyword {
+                "then" => {
+                    "fish has no `then`; the body of an `if` starts right after its condition"
+                }
+                _ => "fish has no `do`; the body of a loop starts right after its header",
+            };
+            let removed = match args.first() {
+                Some(arg) => TextRange::new(name.range.start, arg.range.start),
+                None => keyword_line(tree, name),
+            };
+            found.push(Bashism::new(BASH_SYNTAX, name.range, message).replace(removed, ""));
+        }
+        keyword @ ("fi" | "done" | "esac") => {
+            let mut bashism = Bashism::new(
+                BASH_SYNTAX,
+                name.range,
+                format!("fish ends every block with `end`, not `{keyword}`"),
+            )
+            .replace(name.range, "end");
+            // The block the keyword was meant to close is left open.
+            let kinds: &[SyntaxKind] = match keyword {
+                "fi" => &[SyntaxKind::IfStatement],
+                "done" => &[SyntaxKind::ForLoop, SyntaxKind::WhileLoop],
+                _ => &[],
+            };
+            let block = tree
+                .root()
+                .path_at(name.range.start)
+                .into_iter()
+                .rev()
+                .find(|node| kinds.contains(&node.kind) && node.end_keyword(src).is_none());
+            bashism.explains.extend(block.map(opener));
+            found.push(bashism);
+        }
+        "elif" => found.push(
+            Bashism::new(BASH_SYNTAX, name.range, "fish writes `else if`, not `elif`")
+                .replace(name.range, "else if"),
+        ),
+        _ => {}
+    }
+
+    let words: Vec<&Node> = command
+        .child_nodes()
+        .filter(|node| node.kind == SyntaxKind::Word)
+        .collect();
+    let assignments = words
+        .iter()
+        .take_while(|word| bash::is_assignment(word.text(src)))
+        .count();
+    if assignments == words.len() && assignments > 0 {
+        let text = command.text(src);
+        let translation = translate(text);
+        let bashism = Bashism::new(
+            BASH_SYNTAX,
+            command.range,
+            "fish sets variables with `set`, not `NAME=value`",
+        );
+        found.push(
+            match translation.untranslated.is_empty() && !translation.text.contains('\n') {
+                true => bashism.replace(command.range, translation.text),
+                false => bashism,
+            },
+        );
+    } else if assignments > 0 && old_assignments {
+        let range = words[0].range.cover(words[assignments - 1].range);
+        found.push(Bashism {
+            explains: Vec::new(),
+            ..Bashism::new(
+                BASH_SYNTAX,
+                range,
+                "`NAME=value command` needs fish 3.1 or later; use `env NAME=value command`",
+            )
+            .replace(TextRange::empty(range.start), "env ")
+        });
+    }
+
+    found.extend(here_document(tree, command));
+    found
+}
+
+/// The range to remove for a `then` or `do` with nothing after it: the whole
+/// line when it stands alone, or the word and the `;` before it.
+fn keyword_line(tree: &SyntaxTree, keyword: &Node) -> TextRange {
+    let src = tree.text();
+    let lines = tree.line_index();
+    let line = lines.line_of(keyword.range.start);
+    let line_range = lines.line_range(line);
+    if src[line_range.start..line_range.end].trim() == keyword.text(src) {
+        let end = match line + 1 < lines.line_count() {
+            true => lines.line_range(line + 1).start,
+            false => line_range.end,
+        };
+        return TextRange::new(line_range.start, end);
+    }
+    let before = src[..keyword.range.start].trim_end_matches([' ', '\t']);
+    let start = match before.strip_suffix(';') {
+        Some(before) => before.trim_end_matches([' ', '\t']).len(),
+        None => keyword.range.start,
+    };
+    TextRange::new(start, keyword.range.end)
+}
+
+/// `<<DELIMITER` and `<<<`, which fish reads as two or three redirections.
+fn here_document(tree: &SyntaxTree, command: &Node) -> Option<Bashism> {
+    let src = tree.text();
+    let redirects: Vec<&Node> = command
+        .child_nodes()
+        .filter(|node| node.kind == SyntaxKind::Redirect)
+        .collect();
+    let is_input = |redirect: &Node| {
+        redirect
+            .first_token(SyntaxKind::RedirectOp)
+            .is_some_and(|op| op.text(src) == "<")
+    };
+    fn target(redirect: &Node) -> Option<&Node> {
+        redirect.first_child(SyntaxKind::Word)
+    }
+    let start = redirects
+        .iter()
+        .position(|redirect| is_input(redirect) && target(redirect).is_none())?;
+    let follows = |index: usize| {
+        redirects
+            .get(index)
+            .filter(|redirect| redirect.range.start == redirects[index - 1].range.end)
+            .filter(|redirect| is_input(redirect))
+            .copied()
+    };
+    let first = redirects[start];
+    let second = follows(start + 1)?;
+    // The command with the here document or string taken out.
+    let without = |last: &Node| {
+        format!(
+            "{}{}",
+            src[command.range.start..first.range.start].trim_end(),
+            &src[last.range.end..command.range.end]
+        )
+    };
+
+    if let Some(third) = follows(start + 2).filter(|_| target(second).is_none()) {
+        let range = first.range.cover(third.range);
+        let bashism = Bashism::new(
+            BASH_SYNTAX,
+            range,
+            "fish has no here strings; pipe the text in with `printf`",
+        );
+        return Some(match target(third) {
+            Some(word) => {
+                let text = format!("printf '%s\\n' {} | {}", word.text(src), without(third));
+                bashism.replace(command.range, text)
+            }
+            None => bashism,
+        });
+    }
+
+    let delimiter = target(second)?.text(src);
+    let (delimiter, strip_tabs) = match delimiter.strip_prefix('-') {
+        Some(delimiter) => (delimiter, true),
+        None => (delimiter, false),
+    };
+    let literal = delimiter.contains(['\'', '"', '\\']);
+    let delimiter = delimiter.replace(['\'', '"', '\\'], "");
+
+    let lines = tree.line_index();
+    let line = lines.line_of(command.range.end);
+    let mut body = Vec::new();
+    let mut end = None;
+    for line in line + 1..lines.line_count() {
+        let range = lines.line_range(line);
+        let text = &src[range.start..range.end];
+        let text = match strip_tabs {
+            true => text.trim_start_matches('\t'),
+            false => text,
+        };
+        if text.trim_end() == delimiter {
+            end = Some(range.end);
+            break;
+        }
+        body.push(text);
+    }
+
+    let range = first.range.cover(second.range);
+    let mut bashism = Bashism::new(
+        BASH_SYNTAX,
+        range,
+        "fish has no here documents; pipe the text in with `printf`",
+    );
+    bashism.explains = vec![TextRange::new(range.start, end.unwrap_or(src.len()))];
+    let Some(end) = end else {
+        return Some(bashism);
+    };
+
+    let line_range = lines.line_range(line);
+    let line_text = &src[line_range.start..line_range.end];
+    let indent = &line_text[..line_text.len() - line_text.trim_start().len()];
+    let body: Vec<String> = body
+        .into_iter()
+        .map(|line| match literal {
+            true => single_quoted(line),
+            false => format!("\"{}\"", line.replace('"', "\\\"").replace("\\`", "`")),
+        })
+        .collect();
+    let printf = match body.is_empty() {
+        true => "printf ''".to_string(),
+        false => format!(
+            "printf '%s\\n' {}",
+            body.join(&format!(" \\\n{indent}    "))
+        ),
+    };
+    let text = format!(
+        "{printf} | {}{}",
+        without(second),
+        &src[command.range.end..line_range.end]
+    );
+    Some(bashism.replace(TextRange::new(command.range.start, end), text))
+}
+
+/// Bash's `case WORD in`, which fish reads as a `case` outside a `switch`.
+fn bash_case(tree: &SyntaxTree, node: &Node) -> Option<Bashism> {
+    let src = tree.text();
+    let keyword = node
+        .keyword()
+        .filter(|keyword| keyword.text(src) == "case")?;
+    let words: Vec<&str> = node
+        .child_nodes()
+        .filter(|node| node.kind == SyntaxKind::Word)
+        .map(|word| word.text(src))
+        .collect();
+    let [word, "in"] = words.as_slice() else {
+        return None;
+    };
+    if keyword.range.start != node.range.start {
+        return None;
+    }
+    let mut bashism = Bashism::new(
+        BASH_SYNTAX,
+        node.range,
+        "bash's `case ... in` is `switch ...` in fish, with a `case` for each pattern",
+    )
+    .replace(node.range, format!("switch {word}"));
+
+    // The `pattern)` of each arm, up to the `esac`.
+    let lines = tree.line_index();
+    for line in lines.line_of(node.range.end) + 1..lines.line_count() {
+        let range = lines.line_range(line);
+        let text = &src[range.start..range.end];
+        let pattern = text.trim_start();
+        if pattern.trim_end() == "esac" {
+            break;
+        }
+        let start = range.start + text.len() - pattern.len();
+        match pattern.find(')') {
+            Some(end) if end > 0 && !pattern[..end].contains(['(', ' ', '\t']) => {
+                bashism
+                    .explains
+                    .push(TextRange::new(start, start + end + 1));
+            }
+            _ => {}
+        }
+    }
+    Some(bashism)
+}
+
+/// Bash expansions fish lacks, like `$?`, `$1` and `${name}`.
+fn expansions(tree: &SyntaxTree, in_quotes: &dyn Fn(usize) -> bool) -> Vec<Bashism> {
+    let src = tree.text();
+    let mut found = Vec::new();
+    for token in tree.root().tokens() {
+        // Inside quotes fish leaves a `$` it cannot expand as text.
+        let dollar = match token.kind {
+            SyntaxKind::Dollar => true,
+            SyntaxKind::StringText => token.text(src) == "$",
+            _ => false,
+        };
+        if !dollar {
+            continue;
+        }
+        let start = token.range.start;
+        let rest = &src[start..];
+        let is_name = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+        let len = match rest.as_bytes().get(1) {
+            Some(b'{') => {
+                let len = 1 + bash::matching(&rest[1..]);
+                if rest[..len].ends_with('}') {
+                    len
+                } else {
+                    2
+                }
+            }
+            Some(b'?' | b'#' | b'@' | b'*' | b'!' | b'-' | b'0'..=b'9') => 2,
+            Some(b'$') if !rest.as_bytes().get(2).copied().is_some_and(is_name) => 2,
+            _ => continue,
+        };
+        let text = &rest[..len];
+        let range = TextRange::new(start, start + len);
+        let quoted = in_quotes(start);
+
+        let spelling = expansion(text, quoted).map(|mut spelling| {
+            // `"${name}s"` cannot become `"$names"`.
+            let next = src.as_bytes().get(range.end).copied();
+            if quoted && next.is_some_and(is_name) && spelling.bytes().last().is_some_and(is_name) {
+                spelling.push_str("\"\"");
+            }
+            spelling
+        });
+        let message = match text {
+            "$?" => "fish keeps the exit status in `$status`, not `$?`".to_string(),
+            "$#" => "fish counts the arguments with `count $argv`, not `$#`".to_string(),
+            "$@" | "$*" => format!("fish keeps the arguments in `$argv`, not `{text}`"),
+            "$$" => "fish keeps its process ID in `$fish_pid`, not `$$`".to_string(),
+            "$!" => "fish keeps the last background process ID in `$last_pid`, not `$!`".into(),
+            "$0" => "fish gives the script's path with `status filename`, not `$0`".to_string(),
+            "$-" => "fish has no `$-`; `status` tells how fish is running".to_string(),
+            _ if text.as_bytes()[1].is_ascii_digit() => {
+                format!(
+                    "fish keeps the arguments in `$argv`; `{text}` is `$argv[{}]`",
+                    &text[1..]
+                )
+            }
+            "${" => "`${` is not closed; fish writes `{$name}` for bash's `${name}`".to_string(),
+            _ if bash::is_name(&text[2..len - 1]) => match &spelling {
+                Some(spelling) => format!("fish has no `{text}`; write `{spelling}`"),
+                None => format!("fish has no `{text}`"),
+            },
+            _ => "fish has no `${...}` parameter expansion; use `string` or `set -q`".to_string(),
+        };
+        let bashism = Bashism::new(BASH_SYNTAX, range, message);
+        found.push(match spelling {
+            Some(spelling) => bashism.replace(range, spelling),
+            None => bashism,
+        });
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    fn found(src: &str) -> Vec<(&'static str, String, Option<String>)> {
+        let tree = parse(src);
+        bashisms(&tree, None)
+            .into_iter()
+            .map(|bashism| {
+                let range = bashism.range;
+                let replacement = bashism.replacement.map(|(_, text)| text);
+                (
+                    bashism.lint.code,
+                    src[range.start..range.end].to_string(),
+                    replacement,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unclosed_brace_expansion() {
+        for src in ["echo ${foo\n", "echo ${", "echo \"${foo\"\n", "${"] {
+            let found = found(src);
+            assert!(
+                found.iter().any(|(_, text, _)| text == "${"),
+                "{src:?}: {found:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn expansions() {
+        assert_eq!(
+            found("echo ${HOME} $? $1\n"),
+            [
+                ("BASH_SYNTAX", "${HOME}".into(), Some("{$HOME}".into())),
+                ("BASH_SYNTAX", "$?".into(), Some("$status".into())),
+                ("BASH_SYNTAX", "$1".into(), Some("$argv[1]".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn closers_explain_unclosed_blocks() {
+        let tree = parse("if true; then\n    echo\nfi\n");
+        let found = bashisms(&tree, None);
+        let fi = found
+            .iter()
+            .find(|bashism| bashism.message.contains("`fi`"));
+        assert!(fi.is_some_and(|fi| fi.explains.contains(&TextRange::new(0, 2))));
+    }
+
+    #[test]
+    fn version_gated() {
+        let tree = parse("echo $(pwd)\nFOO=1 cmd\n");
+        assert!(bashisms(&tree, None).is_empty());
+        assert!(bashisms(&tree, Some(Version(3, 4))).is_empty());
+        assert_eq!(bashisms(&tree, Some(Version(3, 0))).len(), 2);
+    }
+
+    #[test]
+    fn here_document() {
+        let found = found("cat <<EOF\nhello $USER\nEOF\n");
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(
+            found[0].2.as_deref(),
+            Some("printf '%s\\n' \"hello $USER\" | cat")
+        );
+    }
+}
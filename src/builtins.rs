@@ -0,0 +1,487 @@
+//! Static knowledge about fish itself: its builtin commands, their options and
+//! the variables fish sets on its own.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub synopsis: &'static str,
+    pub description: &'static str,
+    pub options: &'static [&'static str],
+}
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    builtin(name).is_some()
+}
+
+pub fn special_variable(name: &str) -> Option<&'static str> {
+    SPECIAL_VARIABLES
+        .iter()
+        .find(|(variable, _)| *variable == name)
+        .map(|(_, description)| *description)
+}
+
+pub fn is_special_variable(name: &str) -> bool {
+    special_variable(name).is_some()
+}
+
+macro_rules! builtins {
+    ($($name:literal {
+        synopsis: $synopsis:literal,
+        description: $description:literal,
+        options: [$($option:literal),* $(,)?],
+    }),* $(,)?) => {
+        pub const BUILTINS: &[Builtin] = &[
+            $(Builtin {
+                name: $name,
+                synopsis: $synopsis,
+                description: $description,
+                options: &[$($option),*],
+            }),*
+        ];
+    };
+}
+
+builtins! {
+    "." {
+        synopsis: ". FILE [ARGUMENTS ...]",
+        description: "Evaluate the contents of FILE in the current shell. The same as `source`.",
+        options: [],
+    },
+    "[" {
+        synopsis: "[ [EXPRESSION] ]",
+        description: "Evaluate a conditional expression on files, strings or numbers. The same as `test`.",
+        options: ["-n", "-z", "-e", "-f", "-d", "-L", "-r", "-w", "-x", "-s", "-eq", "-ne", "-gt", "-ge", "-lt", "-le", "-a", "-o"],
+    },
+    "_" {
+        synopsis: "_ STRING",
+        description: "Translate STRING into the current language using gettext.",
+        options: [],
+    },
+    "abbr" {
+        synopsis: "abbr --add NAME [--position command | anywhere] [--regex PATTERN] [--function FUNCTION | EXPANSION]",
+        description: "Manage abbreviations, words that are expanded into longer text as they are typed.",
+        options: ["-a", "--add", "-e", "--erase", "-l", "--list", "-s", "--show", "-q", "--query", "-r", "--rename", "--position", "--regex", "--set-cursor", "--function"],
+    },
+    "and" {
+        synopsis: "PREVIOUS; and COMMAND",
+        description: "Run COMMAND only if the previous command succeeded.",
+        options: [],
+    },
+    "argparse" {
+        synopsis: "argparse [OPTIONS] OPTION_SPEC ... -- [ARG ...]",
+        description: "Parse the options in ARG according to OPTION_SPEC, setting `_flag_NAME` variables and leaving the remaining arguments in `$argv`.",
+        options: ["-n", "--name", "-x", "--exclusive", "-N", "--min-args", "-X", "--max-args", "-i", "--ignore-unknown", "-s", "--stop-nonopt"],
+    },
+    "begin" {
+        synopsis: "begin; [COMMANDS ...]; end",
+        description: "Start a new block of code, which also introduces a new local variable scope.",
+        options: [],
+    },
+    "bg" {
+        synopsis: "bg [PID ...]",
+        description: "Send jobs to the background, continuing them if they were stopped.",
+        options: [],
+    },
+    "bind" {
+        synopsis: "bind [(-M | --mode) MODE] [(-m | --sets-mode) NEW_MODE] [--preset | --user] [-s | --silent] KEYS COMMAND ...",
+        description: "Handle fish key bindings.",
+        options: ["-M", "--mode", "-m", "--sets-mode", "-e", "--erase", "-a", "--all", "-K", "--key-names", "-f", "--function-names", "-s", "--silent", "--preset", "--user"],
+    },
+    "block" {
+        synopsis: "block [(--local | --global)] | block --erase",
+        description: "Temporarily block the delivery of events.",
+        options: ["-l", "--local", "-g", "--global", "-e", "--erase"],
+    },
+    "break" {
+        synopsis: "LOOP_CONSTRUCT; [COMMANDS ...;] break; [COMMANDS ...;] end",
+        description: "Stop the current inner loop.",
+        options: [],
+    },
+    "breakpoint" {
+        synopsis: "breakpoint",
+        description: "Launch a debug session at this point in the script.",
+        options: [],
+    },
+    "builtin" {
+        synopsis: "builtin [OPTIONS] BUILTINNAME [ARGS ...]",
+        description: "Run a builtin command, even if a function of the same name exists.",
+        options: ["-n", "--names", "-q", "--query"],
+    },
+    "case" {
+        synopsis: "switch VALUE; [case [WILDCARD ...]; [COMMANDS ...]; ...] end",
+        description: "Conditionally execute a block of commands when VALUE matches one of the wildcards.",
+        options: [],
+    },
+    "cd" {
+        synopsis: "cd [DIRECTORY]",
+        description: "Change the current working directory.",
+        options: [],
+    },
+    "command" {
+        synopsis: "command [OPTIONS] [COMMANDNAME [ARG ...]]",
+        description: "Run an external program, ignoring any function or builtin of the same name.",
+        options: ["-a", "--all", "-q", "--query", "-s", "--search", "-v"],
+    },
+    "commandline" {
+        synopsis: "commandline [OPTIONS] [CMD]",
+        description: "Set or get the contents of the command line buffer.",
+        options: ["-a", "--append", "-i", "--insert", "-r", "--replace", "-t", "--current-token", "-j", "--current-job", "-p", "--current-process", "-s", "--current-selection", "-b", "--current-buffer", "-o", "--tokenize", "-C", "--cursor", "-f", "--function", "-P", "--paging-mode"],
+    },
+    "complete" {
+        synopsis: "complete ((-c | --command) | (-p | --path)) COMMAND [OPTIONS]",
+        description: "Define how the arguments of COMMAND are completed.",
+        options: ["-c", "--command", "-p", "--path", "-e", "--erase", "-s", "--short-option", "-l", "--long-option", "-o", "--old-option", "-a", "--arguments", "-f", "--no-files", "-F", "--force-files", "-r", "--require-parameter", "-x", "--exclusive", "-d", "--description", "-w", "--wraps", "-n", "--condition", "-k", "--keep-order", "-C", "--do-complete"],
+    },
+    "contains" {
+        synopsis: "contains [OPTIONS] KEY [VALUES ...]",
+        description: "Test if KEY is one of VALUES.",
+        options: ["-i", "--index"],
+    },
+    "continue" {
+        synopsis: "LOOP_CONSTRUCT; [COMMANDS ...;] continue; [COMMANDS ...;] end",
+        description: "Skip the remainder of the current iteration of the inner loop.",
+        options: [],
+    },
+    "count" {
+        synopsis: "count STRING1 STRING2 ...",
+        description: "Print the number of arguments, and succeed only if there was at least one.",
+        options: [],
+    },
+    "dirh" {
+        synopsis: "dirh",
+        description: "Print the directory history.",
+        options: [],
+    },
+    "disown" {
+        synopsis: "disown [PID ...]",
+        description: "Remove a process from the list of jobs.",
+        options: [],
+    },
+    "echo" {
+        synopsis: "echo [OPTIONS] [STRING ...]",
+        description: "Write each argument to standard output, separated by spaces.",
+        options: ["-n", "-s", "-e", "-E"],
+    },
+    "else" {
+        synopsis: "if CONDITION; COMMANDS_TRUE ...; [else; COMMANDS_FALSE ...;] end",
+        description: "Run commands when the condition of an `if` block fails.",
+        options: [],
+    },
+    "emit" {
+        synopsis: "emit EVENT_NAME [ARGUMENTS ...]",
+        description: "Fire a generic event, running every function defined with `--on-event EVENT_NAME`.",
+        options: [],
+    },
+    "end" {
+        synopsis: "begin | function | if | switch | while | for ...; end",
+        description: "End a block of commands.",
+        options: [],
+    },
+    "eval" {
+        synopsis: "eval [COMMANDS ...]",
+        description: "Evaluate the arguments as fish commands.",
+        options: [],
+    },
+    "exec" {
+        synopsis: "exec COMMAND",
+        description: "Replace the running shell with COMMAND.",
+        options: [],
+    },
+    "exit" {
+        synopsis: "exit [CODE]",
+        description: "Exit the shell, or the script being sourced, with an optional status.",
+        options: [],
+    },
+    "false" {
+        synopsis: "false",
+        description: "Return an unsuccessful result.",
+        options: [],
+    },
+    "fg" {
+        synopsis: "fg [PID]",
+        description: "Bring a job to the foreground.",
+        options: [],
+    },
+    "for" {
+        synopsis: "for VARNAME in [VALUES ...]; COMMANDS ...; end",
+        description: "Run COMMANDS once for each value, with VARNAME set to that value.",
+        options: [],
+    },
+    "function" {
+        synopsis: "function NAME [OPTIONS]; BODY; end",
+        description: "Create a function.",
+        options: ["-a", "--argument-names", "-d", "--description", "-w", "--wraps", "-e", "--on-event", "-v", "--on-variable", "-j", "--on-job-exit", "-p", "--on-process-exit", "-s", "--on-signal", "-S", "--no-scope-shadowing", "-V", "--inherit-variable"],
+    },
+    "functions" {
+        synopsis: "functions [-a | --all] [-n | --names] | functions [-D | --details] FUNCTION | functions -e FUNCTIONS ...",
+        description: "Print or erase functions.",
+        options: ["-a", "--all", "-c", "--copy", "-d", "--description", "-e", "--erase", "-D", "--details", "-H", "--handlers", "-n", "--names", "-q", "--query", "-v", "--verbose"],
+    },
+    "history" {
+        synopsis: "history [search | delete | merge | save | clear] [OPTIONS] [SEARCH_STRING ...]",
+        description: "Show and manipulate the command history.",
+        options: ["search", "delete", "merge", "save", "clear", "--prefix", "--contains", "--exact", "--case-sensitive", "--max", "--null", "--reverse", "--show-time"],
+    },
+    "if" {
+        synopsis: "if CONDITION; COMMANDS_TRUE ...; [else if CONDITION2; COMMANDS_TRUE2 ...;] [else; COMMANDS_FALSE ...;] end",
+        description: "Run commands if the exit status of CONDITION is zero.",
+        options: [],
+    },
+    "jobs" {
+        synopsis: "jobs [OPTIONS] [PID | %JOBID]",
+        description: "Print the currently running jobs.",
+        options: ["-c", "--command", "-g", "--group", "-l", "--last", "-p", "--pid", "-q", "--query"],
+    },
+    "math" {
+        synopsis: "math [(-s | --scale) N] [(-b | --base) BASE] [(-m | --scale-mode) MODE] EXPRESSION ...",
+        description: "Evaluate a mathematical expression and print the result.",
+        options: ["-s", "--scale", "-b", "--base", "-m", "--scale-mode"],
+    },
+    "not" {
+        synopsis: "not COMMAND [OPTIONS ...]",
+        description: "Negate the exit status of COMMAND.",
+        options: [],
+    },
+    "or" {
+        synopsis: "PREVIOUS; or COMMAND",
+        description: "Run COMMAND only if the previous command failed.",
+        options: [],
+    },
+    "path" {
+        synopsis: "path SUBCOMMAND [OPTIONS] [PATH ...]",
+        description: "Manipulate and filter paths.",
+        options: ["basename", "dirname", "extension", "filter", "is", "mtime", "normalize", "resolve", "change-extension", "sort", "-q", "--quiet", "-z", "--null-in", "-Z", "--null-out", "-f", "-d", "-l", "-r", "-w", "-x", "-v", "--invert", "--type", "--perm"],
+    },
+    "printf" {
+        synopsis: "printf FORMAT [ARGUMENT ...]",
+        description: "Display text according to a format string.",
+        options: [],
+    },
+    "pwd" {
+        synopsis: "pwd [-P | --physical] [-L | --logical]",
+        description: "Print the current working directory.",
+        options: ["-L", "--logical", "-P", "--physical"],
+    },
+    "random" {
+        synopsis: "random [SEED | START END | START STEP END | choice [ITEMS ...]]",
+        description: "Generate a random number or pick a random item.",
+        options: ["choice"],
+    },
+    "read" {
+        synopsis: "read [OPTIONS] [VARIABLE ...]",
+        description: "Read a line of input into variables.",
+        options: ["-c", "--command", "-d", "--delimiter", "-g", "--global", "-l", "--local", "-U", "--universal", "-f", "--function", "-x", "--export", "-u", "--unexport", "-a", "--list", "-n", "--nchars", "-P", "--prompt-str", "-p", "--prompt", "-R", "--right-prompt", "-s", "--silent", "-S", "--shell", "-t", "--tokenize", "-z", "--null", "-L", "--line"],
+    },
+    "realpath" {
+        synopsis: "realpath [OPTIONS] PATH",
+        description: "Convert a path to an absolute path without symlinks.",
+        options: ["-s", "--no-symlinks"],
+    },
+    "return" {
+        synopsis: "return [N]",
+        description: "Stop the current function, returning status N.",
+        options: [],
+    },
+    "set" {
+        synopsis: "set [SCOPE_OPTIONS] [(-a | --append) | (-p | --prepend)] NAME [VALUE ...]\nset [SCOPE_OPTIONS] NAME[INDEX ...] VALUE ...\nset (-q | --query) [SCOPE_OPTIONS] [NAME ...]\nset (-e | --erase) [SCOPE_OPTIONS] [NAME ...]",
+        description: "Display and change shell variables. Scope options are `-l`/`--local`, `-f`/`--function`, `-g`/`--global` and `-U`/`--universal`; `-x`/`--export` and `-u`/`--unexport` control whether the variable is passed to child processes.",
+        options: ["-a", "--append", "-p", "--prepend", "-e", "--erase", "-q", "--query", "-l", "--local", "-f", "--function", "-g", "--global", "-U", "--universal", "-x", "--export", "-u", "--unexport", "--path", "--unpath", "-n", "--names", "-S", "--show", "-L", "--long"],
+    },
+    "set_color" {
+        synopsis: "set_color [OPTIONS] VALUE",
+        description: "Set the terminal color.",
+        options: ["-b", "--background", "-o", "--bold", "-d", "--dim", "-i", "--italics", "-r", "--reverse", "-u", "--underline", "-c", "--print-colors"],
+    },
+    "source" {
+        synopsis: "source FILE [ARGUMENTS ...]",
+        description: "Evaluate the contents of FILE in the current shell.",
+        options: [],
+    },
+    "status" {
+        synopsis: "status [SUBCOMMAND]",
+        description: "Query fish runtime information.",
+        options: ["is-interactive", "is-login", "is-block", "is-command-substitution", "is-no-job-control", "is-full-job-control", "is-interactive-job-control", "current-command", "current-commandline", "filename", "basename", "dirname", "fish-path", "function", "line-number", "stack-trace", "job-control", "features", "test-feature", "buildinfo"],
+    },
+    "string" {
+        synopsis: "string SUBCOMMAND [OPTIONS] [STRING ...]",
+        description: "Manipulate strings: `collect`, `escape`, `join`, `length`, `lower`, `match`, `pad`, `repeat`, `replace`, `shorten`, `split`, `sub`, `trim`, `unescape` and `upper`.",
+        options: ["collect", "escape", "join", "join0", "length", "lower", "match", "pad", "repeat", "replace", "shorten", "split", "split0", "sub", "trim", "unescape", "upper"],
+    },
+    "switch" {
+        synopsis: "switch VALUE; [case [WILDCARD ...]; [COMMANDS ...]; ...] end",
+        description: "Conditionally execute a block of commands depending on the value of VALUE.",
+        options: [],
+    },
+    "test" {
+        synopsis: "test [EXPRESSION]\n[ [EXPRESSION] ]",
+        description: "Evaluate a conditional expression on files, strings or numbers.",
+        options: ["-n", "-z", "-e", "-f", "-d", "-L", "-r", "-w", "-x", "-s", "-eq", "-ne", "-gt", "-ge", "-lt", "-le", "-a", "-o"],
+    },
+    "time" {
+        synopsis: "time COMMAND",
+        description: "Measure how long a command or block takes.",
+        options: [],
+    },
+    "true" {
+        synopsis: "true",
+        description: "Return a successful result.",
+        options: [],
+    },
+    "type" {
+        synopsis: "type [OPTIONS] NAME [...]",
+        description: "Locate a command and describe its type.",
+        options: ["-a", "--all", "-s", "--short", "-f", "--no-functions", "-t", "--type", "-p", "--path", "-P", "--force-path", "-q", "--query"],
+    },
+    "ulimit" {
+        synopsis: "ulimit [OPTIONS] [LIMIT]",
+        description: "Set or get resource usage limits.",
+        options: ["-S", "--soft", "-H", "--hard", "-a", "--all", "-c", "-d", "-f", "-l", "-m", "-n", "-s", "-t", "-u", "-v"],
+    },
+    "wait" {
+        synopsis: "wait [-n | --any] [PID | PROCESS_NAME] ...",
+        description: "Wait for jobs to complete.",
+        options: ["-n", "--any"],
+    },
+    "while" {
+        synopsis: "while CONDITION; COMMANDS ...; end",
+        description: "Run COMMANDS repeatedly as long as CONDITION succeeds.",
+        options: [],
+    },
+}
+
+/// Functions that ship with fish and are always found on its function path,
+/// even when fish's own data directory is not available to the server.
+pub const FUNCTIONS: &[&str] = &[
+    "alias",
+    "cdh",
+    "contains_seq",
+    "delete-or-exit",
+    "dirs",
+    "down-or-search",
+    "edit_command_buffer",
+    "export",
+    "fish_add_path",
+    "fish_breakpoint_prompt",
+    "fish_clipboard_copy",
+    "fish_clipboard_paste",
+    "fish_command_not_found",
+    "fish_config",
+    "fish_default_key_bindings",
+    "fish_default_mode_prompt",
+    "fish_git_prompt",
+    "fish_greeting",
+    "fish_hg_prompt",
+    "fish_hybrid_key_bindings",
+    "fish_is_root_user",
+    "fish_mode_prompt",
+    "fish_opt",
+    "fish_prompt",
+    "fish_right_prompt",
+    "fish_status_to_signal",
+    "fish_svn_prompt",
+    "fish_title",
+    "fish_update_completions",
+    "fish_vcs_prompt",
+    "fish_vi_cursor",
+    "fish_vi_key_bindings",
+    "funced",
+    "funcsave",
+    "help",
+    "isatty",
+    "la",
+    "ll",
+    "nextd",
+    "open",
+    "popd",
+    "prevd",
+    "prompt_hostname",
+    "prompt_login",
+    "prompt_pwd",
+    "psub",
+    "pushd",
+    "setenv",
+    "suspend",
+    "trap",
+    "umask",
+    "up-or-search",
+    "vared",
+];
+
+/// Whether `name` is a function that comes with fish, including its private
+/// `__fish_*` helpers.
+pub fn is_fish_function(name: &str) -> bool {
+    FUNCTIONS.contains(&name) || name.starts_with("__fish_")
+}
+
+/// Variables that fish defines itself and that scripts may read without
+/// setting them first.
+pub const SPECIAL_VARIABLES: &[(&str, &str)] = &[
+    (
+        "argv",
+        "The arguments given to the current function or script.",
+    ),
+    (
+        "argv_opts",
+        "Options parsed by `argparse` that were not consumed.",
+    ),
+    (
+        "CMD_DURATION",
+        "Runtime of the last command in milliseconds.",
+    ),
+    ("COLUMNS", "The width of the terminal in characters."),
+    ("LINES", "The height of the terminal in lines."),
+    ("fish_bind_mode", "The current key binding mode."),
+    (
+        "fish_complete_path",
+        "Directories searched for completion files.",
+    ),
+    (
+        "fish_function_path",
+        "Directories searched for function files.",
+    ),
+    (
+        "fish_greeting",
+        "The greeting shown when fish starts interactively.",
+    ),
+    (
+        "fish_kill_signal",
+        "The signal that terminated the last foreground job, or 0.",
+    ),
+    ("fish_killring", "Entries in the kill ring."),
+    ("fish_pid", "The process ID of the current fish shell."),
+    (
+        "fish_user_paths",
+        "Directories prepended to `$PATH`, usually set universally.",
+    ),
+    ("FISH_VERSION", "The version of the running fish."),
+    ("history", "The interactive command history, newest first."),
+    ("HOME", "The user's home directory."),
+    ("hostname", "The machine's host name."),
+    ("IFS", "Separators used by `read` to split input."),
+    ("last_pid", "The process ID of the last background process."),
+    (
+        "LANG",
+        "The locale used for messages and character classification.",
+    ),
+    ("PATH", "Directories searched for external commands."),
+    (
+        "pipestatus",
+        "Exit statuses of every process in the last pipeline.",
+    ),
+    ("PWD", "The current working directory."),
+    ("SHLVL", "How deeply nested the current shell is."),
+    ("status", "Exit status of the last command."),
+    (
+        "status_generation",
+        "Incremented each time `$status` is set by a foreground job.",
+    ),
+    ("TERM", "The type of the current terminal."),
+    ("umask", "The current file creation mask."),
+    ("USER", "The name of the current user."),
+    ("version", "The version of the running fish."),
+    ("_", "The name of the command currently running."),
+];
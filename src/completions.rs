@@ -1,12 +1,365 @@
-use crate::server::State;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tower_lsp::lsp_types::*;
+use {
+    crate::{
+        builtins::{builtin, BUILTINS, SPECIAL_VARIABLES},
+        document::Document,
+        parser::{command_name, word_value, Node, SyntaxKind, SyntaxTree, Token},
+        server::State,
+    },
+    std::{collections::HashSet, path::PathBuf, sync::Arc},
+    tokio::sync::RwLock,
+    tower_lsp::lsp_types::*,
+};
 
-pub async fn get_completions(state: Arc<RwLock<State>>) -> Vec<CompletionItem> {
+/// What kind of completion makes sense at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionContext {
+    /// Inside a comment or a string, where nothing should be offered.
+    Nothing,
+    /// Where a command name goes.
+    Command,
+    /// An argument of `command`, with the part of the word typed so far.
+    Argument {
+        command: Option<String>,
+        prefix: String,
+    },
+    /// Right after a `$`.
+    Variable,
+}
+
+pub async fn get_completions(
+    state: Arc<RwLock<State>>,
+    params: &CompletionParams,
+) -> Vec<CompletionItem> {
     let state = state.read().await;
+    let uri = &params.text_document_position.text_document.uri;
+    let Some(document) = state.documents.get(uri) else {
+        return static_completions();
+    };
+
+    let offset = document
+        .tree
+        .line_index()
+        .offset(params.text_document_position.position);
+    let context = completion_context(&document.tree, offset);
+
+    let mut completions = match &context {
+        CompletionContext::Nothing => Vec::new(),
+        CompletionContext::Command => command_completions(&state),
+        CompletionContext::Argument { command, prefix } => {
+            let mut items = option_completions(command.as_deref(), prefix);
+            if !prefix.starts_with('-') {
+                items.extend(file_completions(uri, document, prefix, offset));
+                items.extend(variable_completions(&state, uri, true));
+            }
+            items
+        }
+        CompletionContext::Variable => variable_completions(&state, uri, false),
+    };
+
+    let mut seen = HashSet::new();
+    completions.retain(|item| seen.insert(item.label.clone()));
+    completions
+}
+
+/// Classify the cursor position by looking at the syntax just before it.
+pub fn completion_context(tree: &SyntaxTree, offset: usize) -> CompletionContext {
+    let src = tree.text();
+    let tokens = tree.root().tokens();
+    let current = tokens
+        .iter()
+        .find(|token| token.range.start < offset && offset <= token.range.end);
+
+    if let Some(token) = current {
+        match token.kind {
+            SyntaxKind::Comment => return CompletionContext::Nothing,
+            SyntaxKind::Dollar | SyntaxKind::VariableName => return CompletionContext::Variable,
+            SyntaxKind::SingleQuoted => {
+                let text = token.text(src);
+                let closed = text.len() > 1 && text.ends_with('\'');
+                if offset < token.range.end || !closed {
+                    return CompletionContext::Nothing;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let path = left_path(tree.root(), offset);
+    let innermost = path.iter().rposition(|node| {
+        matches!(
+            node.kind,
+            SyntaxKind::Word | SyntaxKind::DoubleQuotedString | SyntaxKind::CommandSubstitution
+        )
+    });
+    if let Some(index) = innermost {
+        let node = path[index];
+        match node.kind {
+            SyntaxKind::DoubleQuotedString => return CompletionContext::Nothing,
+            SyntaxKind::Word => {
+                let parent = index.checked_sub(1).map(|i| path[i]);
+                let is_name = parent.is_some_and(|command| {
+                    command.kind == SyntaxKind::Command
+                        && command_name(command).is_some_and(|name| name.range == node.range)
+                });
+                if is_name {
+                    return CompletionContext::Command;
+                }
+                return CompletionContext::Argument {
+                    command: enclosing_command_name(&path, src),
+                    prefix: src[node.range.start..offset].to_string(),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let previous = tokens
+        .iter()
+        .rev()
+        .filter(|token| token.range.end <= offset)
+        .find(|token| {
+            !matches!(
+                token.kind,
+                SyntaxKind::Whitespace | SyntaxKind::LineContinuation
+            )
+        });
+    match previous {
+        None => CompletionContext::Command,
+        Some(token) if starts_command(token, src) => CompletionContext::Command,
+        Some(token) => {
+            let path = left_path(tree.root(), token.range.end);
+            let command = enclosing_command_name(&path, src);
+            // A lone `if` or `not` parses as a command until its condition is typed.
+            let lone_keyword = path
+                .iter()
+                .rev()
+                .find(|node| node.kind == SyntaxKind::Command)
+                .is_some_and(|node| node.child_nodes().count() == 1);
+            match command {
+                Some(name) if lone_keyword && COMMAND_PREFIXES.contains(&name.as_str()) => {
+                    CompletionContext::Command
+                }
+                command => CompletionContext::Argument {
+                    command,
+                    prefix: String::new(),
+                },
+            }
+        }
+    }
+}
+
+/// Keywords that are followed by another command.
+const COMMAND_PREFIXES: &[&str] = &[
+    "if", "while", "and", "or", "not", "!", "begin", "else", "time", "command", "builtin", "exec",
+];
+
+/// Whether a command name is expected after `token`.
+fn starts_command(token: &Token, src: &str) -> bool {
+    match token.kind {
+        SyntaxKind::Newline
+        | SyntaxKind::Semicolon
+        | SyntaxKind::Pipe
+        | SyntaxKind::AndAnd
+        | SyntaxKind::OrOr
+        | SyntaxKind::Ampersand
+        | SyntaxKind::LParen
+        | SyntaxKind::Comment => true,
+        SyntaxKind::Keyword => COMMAND_PREFIXES.contains(&token.text(src)),
+        _ => false,
+    }
+}
+
+/// Nodes containing the character just before `offset`, outermost first.
+fn left_path(root: &Node, offset: usize) -> Vec<&Node> {
+    let mut path = vec![root];
+    let mut current = root;
+    while let Some(child) = current
+        .child_nodes()
+        .find(|child| child.range.start < offset && offset <= child.range.end)
+    {
+        path.push(child);
+        current = child;
+    }
+    path
+}
+
+fn enclosing_command_name(path: &[&Node], src: &str) -> Option<String> {
+    path.iter()
+        .rev()
+        .find(|node| node.kind == SyntaxKind::Command)
+        .and_then(|command| command_name(command))
+        .and_then(|word| word_value(word, src))
+}
+
+fn command_completions(state: &State) -> Vec<CompletionItem> {
+    let mut completions: Vec<CompletionItem> = static_completions()
+        .into_iter()
+        .filter(|item| item.kind != Some(CompletionItemKind::VARIABLE))
+        .collect();
+
+    for builtin in BUILTINS {
+        completions.push(CompletionItem {
+            label: builtin.name.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        });
+    }
+
+    let mut functions: Vec<&str> = state
+        .documents
+        .values()
+        .flat_map(|document| &document.symbols.functions)
+        .map(|function| function.name.as_str())
+        .collect();
+    functions.sort_unstable();
+    functions.dedup();
+
+    for function in functions {
+        completions.push(CompletionItem {
+            label: function.to_string(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            insert_text: Some(function.to_string()),
+            ..Default::default()
+        });
+    }
+
+    completions
+}
+
+fn option_completions(command: Option<&str>, prefix: &str) -> Vec<CompletionItem> {
+    let Some(builtin) = command.and_then(builtin) else {
+        return Vec::new();
+    };
+
+    builtin
+        .options
+        .iter()
+        .filter(|option| option.starts_with('-') || !prefix.starts_with('-'))
+        .map(|option| CompletionItem {
+            label: option.to_string(),
+            kind: Some(if option.starts_with('-') {
+                CompletionItemKind::PROPERTY
+            } else {
+                CompletionItemKind::METHOD
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
 
-    let mut completions = vec![
+/// Variables visible from the document at `uri`. With `with_dollar` the
+/// inserted text includes the `$`, for use outside of an expansion.
+fn variable_completions(state: &State, uri: &Url, with_dollar: bool) -> Vec<CompletionItem> {
+    let mut variables: Vec<&str> = state
+        .documents
+        .get(uri)
+        .into_iter()
+        .flat_map(|document| &document.symbols.variables)
+        .map(|variable| variable.name.as_str())
+        .chain(SPECIAL_VARIABLES.iter().map(|(name, _)| *name))
+        .collect();
+    variables.sort_unstable();
+    variables.dedup();
+
+    let mut completions: Vec<CompletionItem> = variables
+        .into_iter()
+        .map(|variable| CompletionItem {
+            label: variable.to_string(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            insert_text: Some(if with_dollar {
+                format!("\\${}", variable)
+            } else {
+                variable.to_string()
+            }),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        })
+        .collect();
+
+    if !with_dollar {
+        completions.retain(|item| item.label != "argv");
+        completions.extend(
+            static_completions()
+                .into_iter()
+                .filter(|item| item.kind == Some(CompletionItemKind::VARIABLE)),
+        );
+    }
+
+    completions
+}
+
+/// Entries of the directory named by the path typed so far, resolved against
+/// the document's own directory.
+fn file_completions(
+    uri: &Url,
+    document: &Document,
+    prefix: &str,
+    offset: usize,
+) -> Vec<CompletionItem> {
+    let (dir_part, name_part) = match prefix.rfind('/') {
+        Some(slash) => (&prefix[..=slash], &prefix[slash + 1..]),
+        None => ("", prefix),
+    };
+
+    let base = if let Some(rest) = dir_part.strip_prefix('~') {
+        let Some(home) = std::env::var_os("HOME") else {
+            return Vec::new();
+        };
+        PathBuf::from(home).join(rest.trim_start_matches('/'))
+    } else if dir_part.starts_with('/') {
+        PathBuf::from(dir_part)
+    } else {
+        let Some(dir) = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+        else {
+            return Vec::new();
+        };
+        dir.join(dir_part)
+    };
+
+    let Ok(entries) = std::fs::read_dir(&base) else {
+        return Vec::new();
+    };
+
+    let line_index = document.tree.line_index();
+    let replace = Range {
+        start: line_index.position(offset - name_part.len()),
+        end: line_index.position(offset),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with('.') && !name_part.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            let new_text = if is_dir { format!("{name}/") } else { name };
+            Some(CompletionItem {
+                label: new_text.clone(),
+                kind: Some(if is_dir {
+                    CompletionItemKind::FOLDER
+                } else {
+                    CompletionItemKind::FILE
+                }),
+                filter_text: Some(new_text.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range: replace,
+                    new_text,
+                })),
+                ..Default::default()
+            })
+        })
+        .take(200)
+        .collect()
+}
+
+/// Snippets for common commands and keywords.
+fn static_completions() -> Vec<CompletionItem> {
+    vec![
         CompletionItem {
             label: "echo".to_string(),
             kind: Some(CompletionItemKind::FUNCTION),
@@ -527,27 +880,108 @@ pub async fn get_completions(state: Arc<RwLock<State>>) -> Vec<CompletionItem> {
             insert_text_format: Some(InsertTextFormat::SNIPPET),
             ..Default::default()
         },
-    ];
+    ]
+}
 
-    for function in &state.custom_funcs {
-        completions.push(CompletionItem {
-            label: function.clone(),
-            kind: Some(CompletionItemKind::KEYWORD),
-            insert_text: Some(format!("{}", function.to_string())),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
-            ..Default::default()
-        });
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    /// The context at the `‸` in `text`.
+    fn context(text: &str) -> CompletionContext {
+        let offset = text.find('‸').unwrap();
+        let text = text.replacen('‸', "", 1);
+        completion_context(&parse(&text), offset)
     }
 
-    for variable in &state.custom_vars {
-        completions.push(CompletionItem {
-            label: variable.clone(),
-            kind: Some(CompletionItemKind::VARIABLE),
-            insert_text: Some(format!("\\${}", variable.to_string())),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
-            ..Default::default()
-        });
+    fn argument(command: &str, prefix: &str) -> CompletionContext {
+        CompletionContext::Argument {
+            command: Some(command.into()),
+            prefix: prefix.into(),
+        }
     }
 
-    completions
+    /// The labels and kinds offered at the `‸` in the document `path`.
+    async fn complete(path: &std::path::Path, text: &str) -> Vec<(String, CompletionItemKind)> {
+        let offset = text.find('‸').unwrap();
+        let text = text.replacen('‸', "", 1);
+        let uri = Url::from_file_path(path).unwrap();
+        let document = Document::new(text, 1);
+        let position = document.tree.line_index().position(offset);
+        let mut state = State::default();
+        state.documents.insert(uri.clone(), document);
+
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri),
+                position,
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        get_completions(Arc::new(RwLock::new(state)), &params)
+            .await
+            .into_iter()
+            .map(|item| (item.label, item.kind.unwrap()))
+            .collect()
+    }
+
+    fn offers(items: &[(String, CompletionItemKind)], label: &str) -> bool {
+        items.iter().any(|(item, _)| item == label)
+    }
+
+    #[test]
+    fn contexts() {
+        assert_eq!(context("ec‸"), CompletionContext::Command);
+        assert_eq!(context("echo hi; ‸"), CompletionContext::Command);
+        assert_eq!(context("if ‸"), CompletionContext::Command);
+        assert_eq!(context("cat file | gr‸"), CompletionContext::Command);
+        assert_eq!(context("echo (ls‸"), CompletionContext::Command);
+        assert_eq!(context("set -‸"), argument("set", "-"));
+        assert_eq!(context("set name ‸"), argument("set", ""));
+        assert_eq!(context("echo $HO‸"), CompletionContext::Variable);
+        assert_eq!(context("# com‸ment"), CompletionContext::Nothing);
+        assert_eq!(context("echo \"str‸\""), CompletionContext::Nothing);
+        assert_eq!(context("echo 'str‸'"), CompletionContext::Nothing);
+    }
+
+    #[tokio::test]
+    async fn commands_options_and_variables() {
+        let path = std::path::Path::new("/project/config.fish");
+        let script = "set -g my_var 1\nfunction helper\nend\n";
+
+        let commands = complete(path, &format!("{script}‸")).await;
+        assert!(offers(&commands, "helper"));
+        assert!(offers(&commands, "set"));
+        assert!(!offers(&commands, "my_var"));
+
+        let options = complete(path, &format!("{script}set --gl‸")).await;
+        assert!(offers(&options, "--global"));
+        assert!(!offers(&options, "helper"));
+        assert!(!offers(&options, "my_var"));
+
+        let variables = complete(path, &format!("{script}echo $‸")).await;
+        assert!(offers(&variables, "my_var"));
+        assert!(offers(&variables, "FISH_VERSION"));
+        assert!(!offers(&variables, "helper"));
+
+        assert_eq!(complete(path, &format!("{script}# ‸")).await, []);
+    }
+
+    #[tokio::test]
+    async fn files() {
+        let dir = std::env::temp_dir().join(format!("fish-lsp-completions-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("scripts")).unwrap();
+        std::fs::write(dir.join("scripts/setup.fish"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+
+        let items = complete(&dir.join("config.fish"), "source scr‸").await;
+        assert!(items.contains(&("scripts/".into(), CompletionItemKind::FOLDER)));
+        assert!(!offers(&items, ".hidden"));
+        let items = complete(&dir.join("config.fish"), "source scripts/‸").await;
+        assert!(items.contains(&("setup.fish".into(), CompletionItemKind::FILE)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
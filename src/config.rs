@@ -0,0 +1,200 @@
+use {
+    crate::lints::{Lint, UNKNOWN_COMMAND},
+    serde::Deserialize,
+    serde_json::{Map, Value},
+    std::{collections::HashMap, path::PathBuf},
+    tower_lsp::lsp_types::DiagnosticSeverity,
+};
+
+/// A file in a workspace folder with settings shared by everyone working on
+/// the project, in the same shape as the client's settings.
+pub const PROJECT_FILE: &str = ".fish-lsp.json";
+
+/// Settings the client passes in `initializationOptions` or
+/// `workspace/didChangeConfiguration`, combined with the project files.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// Extra directories searched for autoloaded functions, ahead of the
+    /// user and system directories, like entries of `$fish_function_path`.
+    pub fish_function_path: Vec<String>,
+    /// Extra directories searched for completions, like entries of
+    /// `$fish_complete_path`.
+    pub fish_complete_path: Vec<String>,
+    /// How calls to unknown commands are reported. Superseded by the
+    /// `UNKNOWN_COMMAND` entry of `lints`.
+    pub unknown_command_severity: Option<Severity>,
+    /// The fish binary used for checking, instead of `fish` on `$PATH`.
+    pub fish_path: Option<String>,
+    /// The fish release scripts must run on, such as `3.3`, for lints about
+    /// syntax older releases lack. Defaults to the version of the fish binary.
+    pub fish_version: Option<String>,
+    /// Lints by code, each set to a severity, `off`, or `true`/`false` to
+    /// turn it on at its default severity or off.
+    pub lints: HashMap<String, LintSetting>,
+    /// Where the lint documentation lives, instead of the published
+    /// `docs/lints.md`. Diagnostics link to `<url>#<code>`.
+    pub lint_documentation_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum LintSetting {
+    Enabled(bool),
+    Severity(Severity),
+}
+
+impl Config {
+    /// Read the options, keeping every setting and `lints` entry that is
+    /// valid. Returns a description of each one left out.
+    pub fn from_options(options: Option<Value>) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let options = match options {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(options)) => options,
+            Some(other) => {
+                problems.push(format!("Ignoring settings that are not an object: {other}"));
+                Map::new()
+            }
+        };
+
+        let mut valid = Map::new();
+        for (key, mut value) in options {
+            if let ("lints", Value::Object(lints)) = (key.as_str(), &mut value) {
+                lints.retain(|code, setting| {
+                    let ok = serde_json::from_value::<LintSetting>(setting.clone()).is_ok();
+                    if !ok {
+                        problems.push(format!(
+                            "Ignoring lint setting `{code}`: expected a severity, \"off\", true or false, found {setting}"
+                        ));
+                    }
+                    ok
+                });
+            }
+            let single = Map::from_iter([(key.clone(), value.clone())]);
+            match serde_json::from_value::<Config>(Value::Object(single)) {
+                Ok(_) => {
+                    valid.insert(key, value);
+                }
+                Err(error) => problems.push(format!("Ignoring setting `{key}`: {error}")),
+            }
+        }
+        let config = serde_json::from_value(Value::Object(valid)).unwrap_or_default();
+        (config, problems)
+    }
+
+    /// The client's settings with those of the project files in `folders`
+    /// laid over them. Project files replace single settings and add to the
+    /// `lints` table. Returns a description of each invalid setting.
+    pub fn load(settings: Option<&Value>, folders: &[PathBuf]) -> (Self, Vec<String>) {
+        let mut merged = match settings {
+            Some(Value::Object(settings)) => settings.clone(),
+            _ => Default::default(),
+        };
+        for folder in folders {
+            let project = std::fs::read_to_string(folder.join(PROJECT_FILE))
+                .ok()
+                .and_then(|text| serde_json::from_str::<Value>(&text).ok());
+            let Some(Value::Object(project)) = project else {
+                continue;
+            };
+            for (key, value) in project {
+                match (merged.get_mut(&key), value) {
+                    (Some(Value::Object(existing)), Value::Object(value)) if key == "lints" => {
+                        existing.extend(value)
+                    }
+                    (_, value) => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+        Self::from_options(Some(Value::Object(merged)))
+    }
+
+    /// The severity `lint` is reported at.
+    pub fn severity(&self, lint: &Lint) -> Severity {
+        let setting = self
+            .lints
+            .iter()
+            .find(|(code, _)| code.eq_ignore_ascii_case(lint.code))
+            .map(|(_, setting)| *setting);
+        match setting {
+            Some(LintSetting::Severity(severity)) => severity,
+            Some(LintSetting::Enabled(true)) => lint.severity,
+            Some(LintSetting::Enabled(false)) => Severity::Off,
+            None if lint.code == UNKNOWN_COMMAND.code => {
+                self.unknown_command_severity.unwrap_or(lint.severity)
+            }
+            None => lint.severity,
+        }
+    }
+}
+
+/// A diagnostic severity as written in the options, or `off` to turn the
+/// check off.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    #[default]
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+    Off,
+}
+
+impl Severity {
+    pub fn to_lsp(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Severity::Error => Some(DiagnosticSeverity::ERROR),
+            Severity::Warning => Some(DiagnosticSeverity::WARNING),
+            Severity::Information => Some(DiagnosticSeverity::INFORMATION),
+            Severity::Hint => Some(DiagnosticSeverity::HINT),
+            Severity::Off => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::lints::UNUSED_VARIABLE, serde_json::json};
+
+    #[test]
+    fn keeps_valid_settings() {
+        let (config, problems) = Config::from_options(Some(json!({
+            "fishPath": "/usr/bin/fish",
+            "fishVersion": 3,
+            "fishFunctionPath": ["~/functions"],
+            "lints": {
+                "UNUSED_VARIABLE": "hint",
+                "UNKNOWN_COMMAND": false,
+                "UNDEFINED_VARIABLE": "loud",
+            },
+            "unknownOption": true,
+        })));
+        assert_eq!(config.fish_path.as_deref(), Some("/usr/bin/fish"));
+        assert_eq!(config.fish_version, None);
+        assert_eq!(config.fish_function_path, ["~/functions"]);
+        assert_eq!(config.severity(&UNUSED_VARIABLE), Severity::Hint);
+        assert_eq!(config.severity(&UNKNOWN_COMMAND), Severity::Off);
+        assert_eq!(config.lints.len(), 2);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].contains("`fishVersion`"), "{problems:?}");
+        assert!(problems[1].contains("`UNDEFINED_VARIABLE`"), "{problems:?}");
+    }
+
+    #[test]
+    fn invalid_shapes() {
+        let (config, problems) = Config::from_options(Some(json!({"lints": ["UNKNOWN_COMMAND"]})));
+        assert!(config.lints.is_empty());
+        assert_eq!(problems.len(), 1);
+
+        let (_, problems) = Config::from_options(Some(json!("verbose")));
+        assert_eq!(problems.len(), 1);
+
+        let (_, problems) = Config::from_options(None);
+        assert_eq!(problems, Vec::<String>::new());
+    }
+}
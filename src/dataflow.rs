@@ -0,0 +1,407 @@
+//! Variable definitions and uses within each function body and the top-level
+//! script: locals that are never read, and expansions of variables that are
+//! never defined.
+
+use {
+    crate::{
+        builtins::is_special_variable,
+        lints::{GLOBAL_SET_IN_FUNCTION, UNDEFINED_VARIABLE, UNUSED_VARIABLE},
+        parser::{SyntaxKind, SyntaxTree, TextRange},
+        references::all_variable_occurrences,
+        scopes::{BindingKind, ScopeMap},
+        server::State,
+        symbols::{is_command, Scope, SetCommand},
+    },
+    std::collections::HashSet,
+    tower_lsp::lsp_types::*,
+};
+
+/// Environment variables commonly inherited by fish that the server's own
+/// environment may lack.
+const ENVIRONMENT_VARIABLES: &[&str] = &[
+    "BROWSER",
+    "COLORTERM",
+    "DISPLAY",
+    "EDITOR",
+    "LANGUAGE",
+    "LOGNAME",
+    "MANPATH",
+    "OLDPWD",
+    "PAGER",
+    "SHELL",
+    "SSH_AUTH_SOCK",
+    "SSH_CLIENT",
+    "SSH_CONNECTION",
+    "SSH_TTY",
+    "TMPDIR",
+    "TZ",
+    "VISUAL",
+    "WAYLAND_DISPLAY",
+];
+
+pub fn variable_diagnostics(state: &State, uri: &Url, tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let scopes = ScopeMap::build(tree);
+    let mut diagnostics = unused_variables(tree, &scopes);
+    diagnostics.extend(undefined_variables(state, uri, tree, &scopes));
+    diagnostics.extend(global_sets(state, tree, &scopes));
+    diagnostics
+}
+
+/// `set -l` variables that nothing in their scope reads. Exported locals are
+/// read by child processes, and names starting with `_` are deliberately
+/// unused.
+fn unused_variables(tree: &SyntaxTree, scopes: &ScopeMap) -> Vec<Diagnostic> {
+    let src = tree.text();
+    let dynamic = dynamic_scopes(tree);
+    let occurrences = all_variable_occurrences(tree, scopes);
+    let read: HashSet<(&str, TextRange)> = occurrences
+        .iter()
+        .filter(|(_, occurrence, _)| !occurrence.write)
+        .map(|(name, _, scope)| (name.as_str(), *scope))
+        .collect();
+    let mut diagnostics = Vec::new();
+
+    for binding in &scopes.bindings {
+        if binding.kind != BindingKind::Set || binding.name.starts_with('_') {
+            continue;
+        }
+        let Some(set) = tree
+            .commands()
+            .find(|command| command.range == binding.statement)
+            .and_then(|command| SetCommand::parse(command, src))
+        else {
+            continue;
+        };
+        if set.scope() != Some(Scope::Local) || set.has_flag('x', "export") {
+            continue;
+        }
+        if dynamic
+            .iter()
+            .any(|scope| scope.contains_range(binding.scope))
+        {
+            continue;
+        }
+
+        if read.contains(&(binding.name.as_str(), binding.scope)) {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..UNUSED_VARIABLE.diagnostic(
+                tree.lsp_range(binding.name_range),
+                format!("Local variable `{}` is never read", binding.name),
+            )
+        });
+    }
+    diagnostics
+}
+
+/// Blocks whose variables may be read in ways the tree does not show, through
+/// `$$name` indirection or `eval`.
+fn dynamic_scopes(tree: &SyntaxTree) -> Vec<TextRange> {
+    let src = tree.text();
+    let mut ranges = Vec::new();
+    for node in tree.root().descendants() {
+        let dynamic = match node.kind {
+            SyntaxKind::Variable => node
+                .first_token(SyntaxKind::Dollar)
+                .is_some_and(|dollar| dollar.range.len() > 1),
+            SyntaxKind::Command => is_command(node, src, "eval"),
+            _ => false,
+        };
+        if dynamic {
+            ranges.push(function_or_file(tree, node.range.start));
+        }
+    }
+    ranges
+}
+
+/// `$name` expansions with no earlier definition in an enclosing scope, no
+/// global or universal definition anywhere in the workspace, and no meaning
+/// to fish or the environment.
+fn undefined_variables(
+    state: &State,
+    uri: &Url,
+    tree: &SyntaxTree,
+    scopes: &ScopeMap,
+) -> Vec<Diagnostic> {
+    let src = tree.text();
+    // The file's own globals are resolved in order, like its other variables.
+    let open = state
+        .documents
+        .iter()
+        .filter(|(other, _)| *other != uri)
+        .map(|(_, document)| &document.symbols);
+    let indexed = state
+        .workspace
+        .files
+        .iter()
+        .filter(|(other, _)| *other != uri)
+        .map(|(_, file)| &file.symbols);
+    let globals: HashSet<&str> = open
+        .chain(indexed)
+        .flat_map(|symbols| &symbols.globals)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+    let argparse = argparse_scopes(tree);
+
+    let mut diagnostics = Vec::new();
+    for node in tree.root().descendants() {
+        if node.kind != SyntaxKind::Variable {
+            continue;
+        }
+        let Some(token) = node.first_token(SyntaxKind::VariableName) else {
+            continue;
+        };
+        let name = token.text(src);
+        let offset = token.range.start;
+
+        let defined = scopes.resolve(tree, name, offset).is_some()
+            || globals.contains(name)
+            || is_known_variable(name)
+            || (name.starts_with("_flag_") && argparse.iter().any(|scope| scope.contains(offset)));
+        if defined {
+            continue;
+        }
+
+        let set_later = scopes
+            .bindings
+            .iter()
+            .any(|binding| binding.name == name && binding.statement.start > offset);
+        let message = if set_later {
+            format!("Variable `{name}` is used before it is set")
+        } else {
+            format!("Variable `{name}` is never defined")
+        };
+        diagnostics.push(UNDEFINED_VARIABLE.diagnostic(tree.lsp_range(token.range), message));
+    }
+    diagnostics
+}
+
+/// `set` without a scope option inside a function, for a variable the
+/// function has not defined but a script defines globally: fish changes the
+/// global rather than creating a local. Variables of fish and the environment
+/// are usually changed on purpose.
+fn global_sets(state: &State, tree: &SyntaxTree, scopes: &ScopeMap) -> Vec<Diagnostic> {
+    let src = tree.text();
+    let open = state.documents.values().map(|document| &document.symbols);
+    let indexed = state.workspace.files.values().map(|file| &file.symbols);
+    let globals: HashSet<&str> = open
+        .chain(indexed)
+        .flat_map(|symbols| &symbols.globals)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    for binding in &scopes.bindings {
+        if binding.kind != BindingKind::Set || is_known_variable(&binding.name) {
+            continue;
+        }
+        let in_function = tree
+            .root()
+            .path_at(binding.statement.start)
+            .iter()
+            .any(|node| node.kind == SyntaxKind::FunctionDef && node.end_keyword(src).is_some());
+        if !in_function {
+            continue;
+        }
+        let Some(set) = tree
+            .commands()
+            .find(|command| command.range == binding.statement)
+            .and_then(|command| SetCommand::parse(command, src))
+        else {
+            continue;
+        };
+        if set.scope().is_some() {
+            continue;
+        }
+
+        let global = if binding.scope == scopes.global_scope() {
+            true
+        } else {
+            let defined_before = scopes.bindings.iter().any(|other| {
+                other.name == binding.name
+                    && other.scope == binding.scope
+                    && other.statement.start < binding.statement.start
+            });
+            !defined_before && globals.contains(binding.name.as_str())
+        };
+        if !global {
+            continue;
+        }
+
+        diagnostics.push(GLOBAL_SET_IN_FUNCTION.diagnostic(
+            tree.lsp_range(binding.name_range),
+            format!(
+                "This changes the global `{}`; add `-l` for a local variable or `-g` to make it explicit",
+                binding.name
+            ),
+        ));
+    }
+    diagnostics
+}
+
+/// Whether fish or the environment defines `name`: special variables, fish's
+/// own `fish_*` settings and environment variables.
+fn is_known_variable(name: &str) -> bool {
+    is_special_variable(name)
+        || name.starts_with("fish_")
+        || name.starts_with("__fish_")
+        || name.starts_with("LC_")
+        || name.starts_with("XDG_")
+        || ENVIRONMENT_VARIABLES.contains(&name)
+        || std::env::var_os(name).is_some()
+}
+
+/// Functions, or the whole file, that call `argparse` and so define
+/// `_flag_*` variables.
+fn argparse_scopes(tree: &SyntaxTree) -> Vec<TextRange> {
+    tree.commands()
+        .filter(|command| is_command(command, tree.text(), "argparse"))
+        .map(|command| function_or_file(tree, command.range.start))
+        .collect()
+}
+
+/// The function around `offset`, or the whole file outside functions.
+fn function_or_file(tree: &SyntaxTree, offset: usize) -> TextRange {
+    tree.root()
+        .path_at(offset)
+        .iter()
+        .rev()
+        .find(|node| node.kind == SyntaxKind::FunctionDef)
+        .map_or(tree.root().range, |function| function.range)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{document::Document, parser::parse},
+    };
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file:///project/{path}")).unwrap()
+    }
+
+    /// The code, flagged text and message of each variable diagnostic for
+    /// `text`, with `others` open beside it.
+    fn check(text: &str, others: &[(&str, &str)]) -> Vec<(String, String, String)> {
+        let mut state = State::default();
+        for (path, other) in others {
+            state
+                .documents
+                .insert(uri(path), Document::new(other.to_string(), 1));
+        }
+        let tree = parse(text);
+        variable_diagnostics(&state, &uri("test.fish"), &tree)
+            .into_iter()
+            .map(|diagnostic| {
+                let range = tree.line_index().text_range(diagnostic.range);
+                let code = match diagnostic.code {
+                    Some(NumberOrString::String(code)) => code,
+                    _ => String::new(),
+                };
+                (
+                    code,
+                    text[range.start..range.end].to_string(),
+                    diagnostic.message,
+                )
+            })
+            .collect()
+    }
+
+    fn codes(text: &str) -> Vec<(String, String)> {
+        check(text, &[])
+            .into_iter()
+            .map(|(code, flagged, _)| (code, flagged))
+            .collect()
+    }
+
+    #[test]
+    fn use_before_set() {
+        assert_eq!(
+            check("echo $x\nset -l x 1\n", &[]),
+            [
+                (
+                    "UNUSED_VARIABLE".into(),
+                    "x".into(),
+                    "Local variable `x` is never read".into()
+                ),
+                (
+                    "UNDEFINED_VARIABLE".into(),
+                    "x".into(),
+                    "Variable `x` is used before it is set".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            codes("echo $y\nset -g y 1\n"),
+            [("UNDEFINED_VARIABLE".into(), "y".into())]
+        );
+        assert_eq!(codes("set -l x 1\necho $x\n"), []);
+        assert_eq!(
+            codes("begin\n    echo $x\n    set -l x 1\nend\n"),
+            [
+                ("UNUSED_VARIABLE".into(), "x".into()),
+                ("UNDEFINED_VARIABLE".into(), "x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_see_later_globals() {
+        let text = "function show\n    echo $setting\nend\nset -g setting 1\nshow\n";
+        assert_eq!(codes(text), []);
+        // Not the file's locals, though.
+        let text = "set -l setting 1\nfunction show\n    echo $setting\nend\n";
+        assert_eq!(
+            codes(text),
+            [
+                ("UNUSED_VARIABLE".into(), "setting".into()),
+                ("UNDEFINED_VARIABLE".into(), "setting".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn globals_of_other_files() {
+        let others = [("config.fish", "set -gx editor vim\n")];
+        assert_eq!(check("echo $editor\n", &others), []);
+        assert_eq!(
+            check("echo $pager\n", &others),
+            [(
+                "UNDEFINED_VARIABLE".into(),
+                "pager".into(),
+                "Variable `pager` is never defined".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn unused_locals() {
+        let text = "\
+function f
+    set -l unused 1
+    set -l used 2
+    set -l _ignored 3
+    set -lx exported 4
+    echo $used
+end
+";
+        assert_eq!(codes(text), [("UNUSED_VARIABLE".into(), "unused".into())]);
+        assert_eq!(
+            codes("function f\n    set -l name 1\n    eval echo \\$name\nend\n"),
+            []
+        );
+    }
+
+    #[test]
+    fn global_set_in_function() {
+        let text = "set -g count 0\nfunction f\n    set count 1\n    set -l total 1\n    echo $total\nend\n";
+        assert_eq!(
+            codes(text),
+            [("GLOBAL_SET_IN_FUNCTION".into(), "count".into())]
+        );
+    }
+}
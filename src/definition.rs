@@ -0,0 +1,275 @@
+use {
+    crate::{
+        parser::{command_args, parse, word_value, SyntaxTree},
+        scopes::ScopeMap,
+        server::State,
+        symbols::{function_name, is_command, symbol_at, SymbolRef},
+    },
+    std::path::{Path, PathBuf},
+    tower_lsp::lsp_types::*,
+};
+
+pub fn definition(state: &State, uri: &Url, position: Position) -> Vec<Location> {
+    let Some(document) = state.documents.get(uri) else {
+        return Vec::new();
+    };
+    let tree = &document.tree;
+    let offset = tree.line_index().offset(position);
+
+    match symbol_at(tree, offset) {
+        Some(SymbolRef::Command { name, .. }) => function_definitions(state, uri, tree, &name),
+        Some(SymbolRef::Variable { name, .. }) => {
+            variable_definitions(state, uri, tree, &name, offset)
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Where the function `name` is defined, searching the current file, the
+/// files it sources, other open files, the indexed workspace files and
+/// finally the file fish would autoload it from.
+pub fn function_definitions(
+    state: &State,
+    uri: &Url,
+    tree: &SyntaxTree,
+    name: &str,
+) -> Vec<Location> {
+    let local = functions_named(uri, tree, name);
+    if !local.is_empty() {
+        return local;
+    }
+
+    for path in sourced_files(uri, tree) {
+        if let Some((uri, tree)) = load(state, &path) {
+            let found = functions_named(&uri, &tree, name);
+            if !found.is_empty() {
+                return found;
+            }
+        }
+    }
+
+    let open: Vec<Location> = state
+        .documents
+        .iter()
+        .filter(|(other, _)| *other != uri)
+        .flat_map(|(other, document)| functions_named(other, &document.tree, name))
+        .collect();
+    if !open.is_empty() {
+        return open;
+    }
+
+    let indexed: Vec<Location> = state
+        .workspace
+        .files
+        .iter()
+        .filter(|(other, _)| !state.documents.contains_key(other))
+        .flat_map(|(other, file)| functions_named(other, &file.tree, name))
+        .collect();
+    if !indexed.is_empty() {
+        return indexed;
+    }
+
+    if let Some((uri, tree)) = state
+        .autoload
+        .function_file(name)
+        .and_then(|path| load(state, &path))
+    {
+        let found = functions_named(&uri, &tree, name);
+        if found.is_empty() {
+            return vec![Location::new(uri, Range::default())];
+        }
+        return found;
+    }
+
+    Vec::new()
+}
+
+fn variable_definitions(
+    state: &State,
+    uri: &Url,
+    tree: &SyntaxTree,
+    name: &str,
+    offset: usize,
+) -> Vec<Location> {
+    if let Some(binding) = ScopeMap::build(tree).resolve(tree, name, offset) {
+        return vec![Location::new(
+            uri.clone(),
+            tree.lsp_range(binding.name_range),
+        )];
+    }
+
+    // Fall back to globals defined by other open files.
+    state
+        .documents
+        .iter()
+        .filter(|(other, _)| *other != uri)
+        .flat_map(|(other, document)| {
+            let scopes = ScopeMap::build(&document.tree);
+            let global = scopes.global_scope();
+            scopes
+                .bindings
+                .into_iter()
+                .filter(move |binding| binding.name == name && binding.scope == global)
+                .map(|binding| {
+                    Location::new(other.clone(), document.tree.lsp_range(binding.name_range))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn functions_named(uri: &Url, tree: &SyntaxTree, name: &str) -> Vec<Location> {
+    tree.functions()
+        .filter_map(|function| function_name(function, tree.text()))
+        .filter(|(_, function_name)| function_name == name)
+        .map(|(word, _)| Location::new(uri.clone(), tree.lsp_range(word.range)))
+        .collect()
+}
+
+/// Files named by `source FILE` or `. FILE` with a static path, resolved
+/// against the sourcing file's directory.
+pub fn sourced_files(uri: &Url, tree: &SyntaxTree) -> Vec<PathBuf> {
+    let base = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf));
+
+    tree.commands()
+        .filter(|command| {
+            is_command(command, tree.text(), "source") || is_command(command, tree.text(), ".")
+        })
+        .filter_map(|command| command_args(command).next())
+        .filter_map(|word| word_value(word, tree.text()))
+        .filter_map(|path| expand_path(&path, base.as_deref()))
+        .collect()
+}
+
+pub fn expand_path(path: &str, base: Option<&Path>) -> Option<PathBuf> {
+    if let Some(rest) = path.strip_prefix("~/") {
+        return Some(PathBuf::from(std::env::var_os("HOME")?).join(rest));
+    }
+    let path = PathBuf::from(path);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(base?.join(path))
+    }
+}
+
+/// The syntax tree of the file at `path`, from the open documents or the
+/// workspace index when possible and from disk otherwise.
+pub fn load(state: &State, path: &Path) -> Option<(Url, SyntaxTree)> {
+    let uri = Url::from_file_path(path).ok()?;
+    if let Some(document) = state.documents.get(&uri) {
+        return Some((uri, document.tree.clone()));
+    }
+    if let Some(file) = state.workspace.files.get(&uri) {
+        return Some((uri, file.tree.clone()));
+    }
+    let text = std::fs::read_to_string(path).ok()?;
+    Some((uri, parse(&text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            autoload::AutoloadPaths, config::Config, document::Document, workspace::IndexedFile,
+        },
+    };
+
+    fn uri(dir: &Path, path: &str) -> Url {
+        Url::from_file_path(dir.join(path)).unwrap()
+    }
+
+    fn open(state: &mut State, dir: &Path, path: &str, text: &str) {
+        state
+            .documents
+            .insert(uri(dir, path), Document::new(text.into(), 1));
+    }
+
+    /// The files, relative to `dir`, and positions the symbol at
+    /// `line`:`character` of `path` is defined at.
+    fn definitions_at(
+        state: &State,
+        dir: &Path,
+        path: &str,
+        line: u32,
+        character: u32,
+    ) -> Vec<(String, u32, u32)> {
+        definition(state, &uri(dir, path), Position::new(line, character))
+            .into_iter()
+            .map(|location| {
+                let path = location.uri.to_file_path().unwrap();
+                let path = path.strip_prefix(dir).unwrap().display().to_string();
+                let start = location.range.start;
+                (path, start.line, start.character)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn functions_and_variables() {
+        let dir = Path::new("/project");
+        let mut state = State::default();
+        let text = "\
+function greet
+end
+set -l x 1
+function f
+    set -l x 2
+    echo $x
+end
+echo $x $shared
+greet
+helper
+";
+        open(&mut state, dir, "config.fish", text);
+        open(&mut state, dir, "other.fish", "set -g shared 1\n");
+        state.workspace.files.insert(
+            uri(dir, "lib.fish"),
+            IndexedFile::new("function helper\nend\n"),
+        );
+
+        let at = |line, character| definitions_at(&state, dir, "config.fish", line, character);
+        assert_eq!(at(5, 10), [("config.fish".into(), 4, 11)]);
+        assert_eq!(at(7, 6), [("config.fish".into(), 2, 7)]);
+        assert_eq!(at(7, 10), [("other.fish".into(), 0, 7)]);
+        assert_eq!(at(8, 0), [("config.fish".into(), 0, 9)]);
+        assert_eq!(at(9, 0), [("lib.fish".into(), 0, 9)]);
+        assert_eq!(at(0, 2), []);
+    }
+
+    #[test]
+    fn autoloaded_and_sourced_functions() {
+        let dir = std::env::temp_dir().join(format!("fish-lsp-definition-{}", std::process::id()));
+        for (path, text) in [
+            ("functions/foo.fish", "function foo\nend\n"),
+            ("functions/bar.fish", "# Defines nothing yet.\n"),
+            ("lib/util.fish", "function util\nend\n"),
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let mut state = State {
+            workspace_folders: vec![dir.clone()],
+            ..Default::default()
+        };
+        state.autoload = AutoloadPaths::new(&state.workspace_folders, &Config::default());
+        open(
+            &mut state,
+            &dir,
+            "config.fish",
+            "source lib/util.fish\nfoo\nbar\nutil\n",
+        );
+
+        let at = |line| definitions_at(&state, &dir, "config.fish", line, 0);
+        assert_eq!(at(1), [("functions/foo.fish".into(), 0, 9)]);
+        assert_eq!(at(2), [("functions/bar.fish".into(), 0, 0)]);
+        assert_eq!(at(3), [("lib/util.fish".into(), 0, 9)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,758 @@
+use {
+    crate::{
+        bashisms::bashism_diagnostics,
+        builtins::{is_builtin, is_fish_function},
+        config::Severity,
+        dataflow::variable_diagnostics,
+        lints::{self, Lint},
+        parser::{
+            block_body, command_args, command_name, word_value, Node, ParseErrorKind, SyntaxKind,
+            SyntaxTree, TextRange,
+        },
+        server::State,
+        symbols::{is_valid_variable_name, SetCommand},
+    },
+    std::collections::{HashMap, HashSet},
+    tower_lsp::lsp_types::*,
+};
+
+pub const SOURCE: &str = "fish-lsp";
+
+/// All diagnostics for a document, open or indexed.
+pub fn diagnostics(state: &State, uri: &Url, tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let mut diagnostics = syntax_diagnostics(uri, tree);
+    diagnostics.extend(set_diagnostics(tree));
+    diagnostics.extend(unknown_commands(state, tree));
+    diagnostics.extend(variable_diagnostics(state, uri, tree));
+    bashism_diagnostics(&state.config, state.fish_version, tree, &mut diagnostics);
+    lints::configure(&state.config, &mut diagnostics);
+    diagnostics
+}
+
+/// Parse errors. Unbalanced blocks get their own codes and link to the block
+/// they conflict with.
+pub fn syntax_diagnostics(uri: &Url, tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let src = tree.text();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for error in tree.errors() {
+        let related = |range: TextRange, message: String| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), tree.lsp_range(range)),
+            message,
+        };
+        let (lint, related) = match error.kind {
+            ParseErrorKind::UnclosedBlock => {
+                let mut information = Vec::new();
+                if let Some(block) = block_opened_at(tree, error.range.start) {
+                    // The `end` meant for this block usually went to the last
+                    // block nested in it.
+                    let last = last_body(block)
+                        .and_then(last_statement)
+                        .filter(|last| last.kind.is_block() && last.end_keyword(src).is_some());
+                    if let Some(last) = last {
+                        information.push(related(
+                            opener(last),
+                            format!("The last `end` closes this {} block", block_name(last, src)),
+                        ));
+                    }
+                    information.push(related(
+                        TextRange::empty(block.range.end),
+                        "Expected `end` here".into(),
+                    ));
+                }
+                (lints::UNCLOSED_BLOCK, information)
+            }
+            ParseErrorKind::StrayEnd => {
+                let closed = preceding_statement(tree, error.range.start, |node| {
+                    node.kind.is_block() && node.end_keyword(src).is_some()
+                });
+                let information = closed.map(|block| {
+                    related(
+                        opener(block),
+                        format!("This {} block is already closed", block_name(block, src)),
+                    )
+                });
+                (lints::STRAY_END, information.into_iter().collect())
+            }
+            ParseErrorKind::StrayElse => {
+                let enclosing = tree
+                    .root()
+                    .path_at(error.range.start)
+                    .into_iter()
+                    .rev()
+                    .find(|node| node.kind == SyntaxKind::IfStatement);
+                let information = match enclosing {
+                    Some(block) => Some(related(
+                        opener(block),
+                        "This if block already has a final `else`".into(),
+                    )),
+                    None => preceding_statement(tree, error.range.start, |node| {
+                        node.kind == SyntaxKind::IfStatement
+                    })
+                    .map(|block| {
+                        related(
+                            opener(block),
+                            "This if block was closed before the `else`".into(),
+                        )
+                    }),
+                };
+                (lints::STRAY_ELSE, information.into_iter().collect())
+            }
+            ParseErrorKind::StrayCase => {
+                let closed = preceding_statement(tree, error.range.start, |node| {
+                    node.kind == SyntaxKind::SwitchStatement
+                });
+                let information = closed.map(|block| {
+                    related(
+                        opener(block),
+                        "This switch block was closed before the `case`".into(),
+                    )
+                });
+                (lints::STRAY_CASE, information.into_iter().collect())
+            }
+            _ => (lints::SYNTAX_ERROR, Vec::new()),
+        };
+
+        diagnostics.push(Diagnostic {
+            related_information: (!related.is_empty()).then_some(related),
+            ..lint.diagnostic(error.lsp_range, error.message.clone())
+        });
+    }
+
+    diagnostics.extend(loop_control_diagnostics(tree));
+    diagnostics
+}
+
+/// `break` and `continue` with no loop around them in the same function.
+fn loop_control_diagnostics(tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let src = tree.text();
+    let mut diagnostics = Vec::new();
+
+    for command in tree.commands() {
+        let Some(word) = command_name(command) else {
+            continue;
+        };
+        let name = word.text(src);
+        if name != "break" && name != "continue" {
+            continue;
+        }
+        let in_loop = tree
+            .root()
+            .path_at(command.range.start)
+            .iter()
+            .rev()
+            .take_while(|node| node.kind != SyntaxKind::FunctionDef)
+            .any(|node| matches!(node.kind, SyntaxKind::WhileLoop | SyntaxKind::ForLoop));
+        if in_loop {
+            continue;
+        }
+
+        diagnostics.push(lints::LOOP_CONTROL_OUTSIDE_LOOP.diagnostic(
+            tree.lsp_range(word.range),
+            format!("`{name}` used outside of a loop"),
+        ));
+    }
+    diagnostics
+}
+
+/// The block whose opening keyword starts at `offset`.
+pub fn block_opened_at(tree: &SyntaxTree, offset: usize) -> Option<&Node> {
+    tree.root()
+        .descendants()
+        .find(|node| node.kind.is_block() && opener(node).start == offset)
+}
+
+/// The keyword opening a block, e.g. `if` for an [`SyntaxKind::IfStatement`].
+pub fn opener(block: &Node) -> TextRange {
+    let keyword = match block.kind {
+        SyntaxKind::IfStatement => block
+            .first_child(SyntaxKind::IfClause)
+            .and_then(Node::keyword),
+        _ => block.keyword(),
+    };
+    keyword.map_or(block.range, |keyword| keyword.range)
+}
+
+fn block_name<'a>(block: &Node, src: &'a str) -> &'a str {
+    let range = opener(block);
+    &src[range.start..range.end]
+}
+
+/// The body a block's `end` would follow: its own, or that of its last
+/// `if`, `else` or `case` clause.
+fn last_body(block: &Node) -> Option<&Node> {
+    block_body(block).or_else(|| block.child_nodes().last().and_then(block_body))
+}
+
+/// The statement of the last job in a job list.
+fn last_statement(list: &Node) -> Option<&Node> {
+    list.child_nodes()
+        .rev()
+        .filter(|job| job.kind == SyntaxKind::Job)
+        .find_map(statement)
+}
+
+fn statement(job: &Node) -> Option<&Node> {
+    job.first_child(SyntaxKind::Pipeline)?.child_nodes().next()
+}
+
+/// The closest statement before the error node at `offset`, in the same list,
+/// that satisfies `wanted`.
+fn preceding_statement(
+    tree: &SyntaxTree,
+    offset: usize,
+    wanted: impl Fn(&Node) -> bool,
+) -> Option<&Node> {
+    let path = tree.root().path_at(offset);
+    let error = path
+        .iter()
+        .rposition(|node| node.kind == SyntaxKind::ErrorNode)?;
+    let list = path[..error].last()?;
+    list.child_nodes()
+        .take_while(|node| node.range.end <= offset)
+        .filter(|node| node.kind == SyntaxKind::Job)
+        .filter_map(statement)
+        .filter(|node| wanted(node))
+        .last()
+}
+
+/// Misuse of `set`: unknown or conflicting options, illegal variable names
+/// or indices, and assignments to variables fish manages itself.
+fn set_diagnostics(tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let src = tree.text();
+    let mut diagnostics = Vec::new();
+
+    for command in tree.commands() {
+        let Some(set) = SetCommand::parse(command, src) else {
+            continue;
+        };
+        let mut report = |range: TextRange, lint: Lint, message: String| {
+            diagnostics.push(lint.diagnostic(tree.lsp_range(range), message));
+        };
+
+        for (word, option) in &set.options {
+            let unknown = match option.strip_prefix("--") {
+                Some(long) => (!SET_LONG_OPTIONS.contains(&long)).then(|| option.clone()),
+                None => option[1..]
+                    .chars()
+                    .find(|c| !SET_SHORT_OPTIONS.contains(*c))
+                    .map(|c| format!("-{c}")),
+            };
+            if let Some(unknown) = unknown {
+                report(
+                    word.range,
+                    lints::INVALID_SET_OPTIONS,
+                    format!("`set` has no option `{unknown}`"),
+                );
+            }
+        }
+
+        let options_range = match (set.options.first(), set.options.last()) {
+            (Some((first, _)), Some((last, _))) => first.range.cover(last.range),
+            _ => command.range,
+        };
+        let given = |flags: &[(char, &'static str)]| -> Vec<String> {
+            flags
+                .iter()
+                .filter(|(short, long)| set.has_flag(*short, long))
+                .map(|(_, long)| format!("--{long}"))
+                .collect()
+        };
+        let conflicts = [
+            given(&[
+                ('l', "local"),
+                ('f', "function"),
+                ('g', "global"),
+                ('U', "universal"),
+            ]),
+            given(&[('x', "export"), ('u', "unexport")]),
+            given(&[
+                ('e', "erase"),
+                ('q', "query"),
+                ('n', "names"),
+                ('S', "show"),
+            ]),
+        ];
+        for conflict in conflicts.iter().filter(|options| options.len() > 1) {
+            report(
+                options_range,
+                lints::INVALID_SET_OPTIONS,
+                format!("`set` cannot combine {}", conflict.join(" and ")),
+            );
+        }
+        let modifies = given(&[('a', "append"), ('p', "prepend")]);
+        let inspects = &conflicts[2];
+        if let (Some(modify), Some(inspect)) = (modifies.first(), inspects.first()) {
+            report(
+                options_range,
+                lints::INVALID_SET_OPTIONS,
+                format!("`set` cannot combine {inspect} and {modify}"),
+            );
+        }
+
+        if set.is_erase() && command_args(command).count() == set.options.len() {
+            report(
+                command.range,
+                lints::INVALID_SET_OPTIONS,
+                "`set --erase` needs the name of a variable to erase".into(),
+            );
+        }
+
+        // Erasing and querying take several names; other forms take one.
+        let extra_names = if set.is_erase() || set.is_query() {
+            set.values.as_slice()
+        } else {
+            &[]
+        };
+        let names = set
+            .name
+            .iter()
+            .map(|(word, _)| *word)
+            .chain(extra_names.iter().copied());
+        for word in names {
+            let Some(value) = word_value(word, src) else {
+                continue;
+            };
+            let (name, index) = match value.find('[') {
+                Some(bracket) => value.split_at(bracket),
+                None => (value.as_str(), ""),
+            };
+
+            if !is_valid_variable_name(name) {
+                report(
+                    word.range,
+                    lints::INVALID_VARIABLE_NAME,
+                    format!("`{name}` is not a valid variable name"),
+                );
+                continue;
+            }
+
+            if !index.is_empty() && !is_valid_index(index) {
+                report(
+                    word.range,
+                    lints::INVALID_INDEX,
+                    format!("`{index}` is not a valid index for `{name}`"),
+                );
+            }
+
+            if (set.is_assignment() || set.is_erase()) && READ_ONLY_VARIABLES.contains(&name) {
+                report(
+                    TextRange::new(word.range.start, word.range.start + name.len()),
+                    lints::READ_ONLY_VARIABLE,
+                    format!("`{name}` is read-only and cannot be set or erased"),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Letters of the short options `set` accepts, and its long options.
+const SET_SHORT_OPTIONS: &str = "aefghlLnpqSuUx";
+const SET_LONG_OPTIONS: &[&str] = &[
+    "append",
+    "erase",
+    "export",
+    "function",
+    "global",
+    "help",
+    "local",
+    "long",
+    "names",
+    "path",
+    "prepend",
+    "query",
+    "show",
+    "unexport",
+    "universal",
+    "unpath",
+];
+
+/// Variables fish maintains itself and refuses to let scripts change.
+const READ_ONLY_VARIABLES: &[&str] = &[
+    "_",
+    "FISH_VERSION",
+    "PWD",
+    "SHLVL",
+    "fish_kill_signal",
+    "fish_pid",
+    "history",
+    "hostname",
+    "last_pid",
+    "pipestatus",
+    "status",
+    "status_generation",
+    "version",
+];
+
+/// A literal `[...]` index: element numbers, negative numbers counting from
+/// the end, and `A..B` ranges, separated by spaces.
+fn is_valid_index(index: &str) -> bool {
+    let Some(inner) = index
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+    else {
+        return false;
+    };
+    !inner.trim().is_empty()
+        && inner.split_whitespace().all(|item| {
+            item.split("..")
+                .all(|end| end.is_empty() || end.parse::<i64>().is_ok())
+                && item != ".."
+        })
+}
+
+/// Calls to commands that are not a builtin, a function fish ships, a
+/// function defined in the workspace or on the function path, or an
+/// executable on `$PATH`.
+fn unknown_commands(state: &State, tree: &SyntaxTree) -> Vec<Diagnostic> {
+    if state.config.severity(&lints::UNKNOWN_COMMAND) == Severity::Off {
+        return Vec::new();
+    }
+
+    let open = state.documents.values().map(|document| &document.symbols);
+    let indexed = state.workspace.files.values().map(|file| &file.symbols);
+    let defined: HashSet<&str> = open
+        .chain(indexed)
+        .flat_map(|symbols| &symbols.functions)
+        .map(|symbol| symbol.name.as_str())
+        .collect();
+
+    let mut known: HashMap<String, bool> = HashMap::new();
+    let mut diagnostics = Vec::new();
+    for command in tree.commands() {
+        let Some(word) = command_name(command) else {
+            continue;
+        };
+        // Names built from expansions are only known at runtime, and paths
+        // and bash-style assignments are for other checks.
+        let Some(name) = word_value(word, tree.text()) else {
+            continue;
+        };
+        // `[[` is reported as a bashism.
+        if name.is_empty() || name.contains(['/', '=']) || name == "[[" {
+            continue;
+        }
+
+        let is_known = *known.entry(name.clone()).or_insert_with(|| {
+            is_builtin(&name)
+                || is_fish_function(&name)
+                || defined.contains(name.as_str())
+                || state.executables.contains(&name)
+                || state.autoload.function_file(&name).is_some()
+        });
+        if is_known {
+            continue;
+        }
+
+        diagnostics.push(lints::UNKNOWN_COMMAND.diagnostic(
+            tree.lsp_range(word.range),
+            format!(
+                "Unknown command `{name}`: not a builtin, a known function or an executable on $PATH"
+            ),
+        ));
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            autoload::AutoloadPaths,
+            config::{Config, LintSetting},
+            document::Document,
+            parser::parse,
+            workspace::IndexedFile,
+        },
+    };
+
+    /// The code and flagged text of each `set` diagnostic for `text`.
+    fn set_problems(text: &str) -> Vec<(String, String)> {
+        let tree = parse(text);
+        set_diagnostics(&tree)
+            .into_iter()
+            .map(|diagnostic| {
+                let range = tree.line_index().text_range(diagnostic.range);
+                let code = match diagnostic.code {
+                    Some(NumberOrString::String(code)) => code,
+                    _ => String::new(),
+                };
+                (code, text[range.start..range.end].to_string())
+            })
+            .collect()
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        set_diagnostics(&parse(text))
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn valid_set_commands() {
+        let text = "\
+set -gx EDITOR vim
+set -l list[2] b
+set list[1..-1] a b
+set -q status
+set -e list[1] other
+set --path -a MANPATH /opt/man
+set -S
+set
+";
+        assert_eq!(set_problems(text), []);
+    }
+
+    #[test]
+    fn unknown_options() {
+        assert_eq!(
+            messages("set -lz x 1\nset --bogus x\n"),
+            ["`set` has no option `-z`", "`set` has no option `--bogus`"]
+        );
+    }
+
+    #[test]
+    fn conflicting_options() {
+        assert_eq!(
+            messages("set -l -g x 1\nset -xu x\nset -eq x\nset -ea x\n"),
+            [
+                "`set` cannot combine --local and --global",
+                "`set` cannot combine --export and --unexport",
+                "`set` cannot combine --erase and --query",
+                "`set` cannot combine --erase and --append",
+            ]
+        );
+        assert_eq!(
+            set_problems("set -l -g x 1\n"),
+            [("INVALID_SET_OPTIONS".into(), "-l -g".into())]
+        );
+        assert_eq!(
+            messages("set -e\n"),
+            ["`set --erase` needs the name of a variable to erase"]
+        );
+    }
+
+    #[test]
+    fn names_and_indexes() {
+        assert_eq!(
+            set_problems("set a.b 2\nset -e a b-c\nset x[a] 1\nset x[] 1\nset x[1..] 1\n"),
+            [
+                ("INVALID_VARIABLE_NAME".into(), "a.b".into()),
+                ("INVALID_VARIABLE_NAME".into(), "b-c".into()),
+                ("INVALID_INDEX".into(), "x[a]".into()),
+                ("INVALID_INDEX".into(), "x[]".into()),
+            ]
+        );
+        // Names that are not literal cannot be checked.
+        assert_eq!(set_problems("set $name 1\nset x[$i] 1\n"), []);
+    }
+
+    #[test]
+    fn read_only_variables() {
+        assert_eq!(
+            set_problems("set status 1\nset -e PWD\nset -q pipestatus\nset history[1] x\n"),
+            [
+                ("READ_ONLY_VARIABLE".into(), "status".into()),
+                ("READ_ONLY_VARIABLE".into(), "PWD".into()),
+                ("READ_ONLY_VARIABLE".into(), "history".into()),
+            ]
+        );
+    }
+
+    /// The flagged text of each unknown command in `text`.
+    fn unknown(state: &State, text: &str) -> Vec<String> {
+        let tree = parse(text);
+        unknown_commands(state, &tree)
+            .into_iter()
+            .map(|diagnostic| {
+                let range = tree.line_index().text_range(diagnostic.range);
+                text[range.start..range.end].to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unknown_commands_are_reported() {
+        let dir = std::env::temp_dir().join(format!("fish-lsp-unknown-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("functions")).unwrap();
+        std::fs::write(dir.join("functions/autoloaded.fish"), "").unwrap();
+
+        let mut state = State {
+            executables: ["git".to_string()].into(),
+            ..Default::default()
+        };
+        state.autoload = AutoloadPaths::new(std::slice::from_ref(&dir), &Config::default());
+        let uri = |path: &str| Url::parse(&format!("file:///project/{path}")).unwrap();
+        state.documents.insert(
+            uri("open.fish"),
+            Document::new("function open_fn\nend\n".into(), 1),
+        );
+        state.workspace.files.insert(
+            uri("indexed.fish"),
+            IndexedFile::new("function indexed_fn\nend\n"),
+        );
+
+        let text = "\
+git status
+open_fn
+indexed_fn
+autoloaded
+fish_add_path ~/bin
+__fish_print_hostnames
+set x 1
+unknown_tool
+./local-script
+$cmd arg
+FOO=bar env
+if unknown_tool; end
+";
+        assert_eq!(unknown(&state, text), ["unknown_tool", "unknown_tool"]);
+
+        state.config.lints = [("UNKNOWN_COMMAND".into(), LintSetting::Enabled(false))].into();
+        assert_eq!(unknown(&state, text), [] as [&str; 0]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// The code, flagged text and related messages of each syntax diagnostic.
+    fn block_problems(text: &str) -> Vec<(String, String, Vec<String>)> {
+        let uri = Url::parse("file:///project/config.fish").unwrap();
+        let tree = parse(text);
+        let flagged = |range| {
+            let range = tree.line_index().text_range(range);
+            text[range.start..range.end].to_string()
+        };
+        syntax_diagnostics(&uri, &tree)
+            .into_iter()
+            .map(|diagnostic| {
+                let code = match diagnostic.code {
+                    Some(NumberOrString::String(code)) => code,
+                    _ => String::new(),
+                };
+                let related = diagnostic
+                    .related_information
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|related| {
+                        format!("{}: {}", flagged(related.location.range), related.message)
+                    })
+                    .collect();
+                (code, flagged(diagnostic.range), related)
+            })
+            .collect()
+    }
+
+    fn problem(code: &str, text: &str, related: &[&str]) -> (String, String, Vec<String>) {
+        (
+            code.into(),
+            text.into(),
+            related.iter().map(|related| related.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn stray_block_keywords() {
+        assert_eq!(
+            block_problems(
+                "if true
+    echo
+end
+end
+"
+            ),
+            [problem(
+                "STRAY_END",
+                "end",
+                &["if: This if block is already closed"]
+            )]
+        );
+        assert_eq!(
+            block_problems(
+                "if true
+else
+else
+end
+"
+            ),
+            [problem(
+                "STRAY_ELSE",
+                "else",
+                &["if: This if block already has a final `else`"]
+            )]
+        );
+        assert_eq!(
+            block_problems(
+                "if true
+end
+else
+"
+            ),
+            [problem(
+                "STRAY_ELSE",
+                "else",
+                &["if: This if block was closed before the `else`"]
+            )]
+        );
+        assert_eq!(
+            block_problems(
+                "switch $x
+end
+case a
+"
+            ),
+            [problem(
+                "STRAY_CASE",
+                "case",
+                &["switch: This switch block was closed before the `case`"]
+            )]
+        );
+    }
+
+    #[test]
+    fn unclosed_blocks() {
+        let problems = block_problems(
+            "function f
+    if true
+        echo
+    end
+",
+        );
+        assert_eq!(
+            problems,
+            [problem(
+                "UNCLOSED_BLOCK",
+                "function",
+                &[
+                    "if: The last `end` closes this if block",
+                    ": Expected `end` here"
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn loop_control() {
+        let text = "\
+break
+for x in a b
+    continue
+end
+while true
+    function f
+        break
+    end
+end
+";
+        assert_eq!(
+            block_problems(text),
+            [
+                problem("LOOP_CONTROL_OUTSIDE_LOOP", "break", &[]),
+                problem("LOOP_CONTROL_OUTSIDE_LOOP", "break", &[]),
+            ]
+        );
+    }
+}
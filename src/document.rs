@@ -0,0 +1,184 @@
+use {
+    crate::{
+        parser::{parse, SyntaxTree, TextRange},
+        symbols::Symbols,
+    },
+    ropey::Rope,
+    tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent},
+};
+
+/// An open text document together with everything derived from its text.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub version: i32,
+    pub text: Rope,
+    pub tree: SyntaxTree,
+    pub symbols: Symbols,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        let tree = parse(&text);
+        let symbols = Symbols::extract(&tree);
+
+        Self {
+            version,
+            text: Rope::from_str(&text),
+            tree,
+            symbols,
+        }
+    }
+
+    /// Apply the changes of a `didChange` notification in order. Ranged
+    /// changes are spliced into the buffer and only the affected statements
+    /// are parsed again; a change without a range replaces the whole text.
+    pub fn apply_changes(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = self.char_index(range.start);
+                    let end = self.char_index(range.end).max(start);
+                    let edited =
+                        TextRange::new(self.text.char_to_byte(start), self.text.char_to_byte(end));
+
+                    self.text.remove(start..end);
+                    self.text.insert(start, &change.text);
+                    self.tree = self
+                        .tree
+                        .reparse(self.text.to_string(), edited, change.text.len());
+                }
+                None => {
+                    self.text = Rope::from_str(&change.text);
+                    self.tree = parse(&change.text);
+                }
+            }
+        }
+
+        self.symbols = Symbols::extract(&self.tree);
+        self.version = version;
+    }
+
+    /// Char index of an LSP position, whose column counts UTF-16 code units.
+    /// Columns past the end of a line clamp to the line end.
+    fn char_index(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.text.len_lines() {
+            return self.text.len_chars();
+        }
+
+        let line_start = self.text.line_to_char(line);
+        let content = self.text.line(line);
+        let mut content_len = content.len_chars();
+        while content_len > 0 && matches!(content.char(content_len - 1), '\n' | '\r') {
+            content_len -= 1;
+        }
+        let content = content.slice(..content_len);
+        let units = (position.character as usize).min(content.len_utf16_cu());
+
+        line_start + content.utf16_cu_to_char(units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tower_lsp::lsp_types::Range};
+
+    /// The start and end line and column of a change, and its new text.
+    type Edit<'a> = ((u32, u32), (u32, u32), &'a str);
+
+    /// Apply `edits` in turn, checking after every one that the buffer holds
+    /// `expected` and the reparsed tree matches a fresh parse.
+    fn check_edits(text: &str, edits: &[Edit], expected: &[&str]) {
+        let mut document = Document::new(text.to_string(), 0);
+        for (version, (((start_line, start), (end_line, end), new_text), expected)) in
+            edits.iter().zip(expected).enumerate()
+        {
+            let change = TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(*start_line, *start),
+                    Position::new(*end_line, *end),
+                )),
+                range_length: None,
+                text: new_text.to_string(),
+            };
+            document.apply_changes(vec![change], version as i32 + 1);
+            assert_eq!(document.text.to_string(), *expected);
+            assert_eq!(document.tree, parse(expected), "after {new_text:?}");
+        }
+    }
+
+    #[test]
+    fn typing_a_function() {
+        let typed = "function greet\n    echo hi\nend\n";
+        let mut expected = Vec::new();
+        let mut edits = Vec::new();
+        let (mut line, mut column) = (0, 0);
+        for (i, c) in typed.char_indices() {
+            edits.push(((line, column), (line, column), &typed[i..i + c.len_utf8()]));
+            expected.push(&typed[..i + c.len_utf8()]);
+            if c == '\n' {
+                (line, column) = (line + 1, 0);
+            } else {
+                column += 1;
+            }
+        }
+        check_edits("", &edits, &expected);
+    }
+
+    #[test]
+    fn opening_and_closing_constructs() {
+        check_edits(
+            "echo a\necho b\necho c\n",
+            &[
+                ((1, 5), (1, 5), "\""),
+                ((1, 7), (1, 7), "\""),
+                ((0, 0), (0, 0), "if true\n"),
+                ((4, 0), (4, 0), "end\n"),
+                ((1, 0), (4, 0), ""),
+                ((1, 0), (1, 0), "(echo "),
+                ((1, 9), (1, 9), ")"),
+            ],
+            &[
+                "echo a\necho \"b\necho c\n",
+                "echo a\necho \"b\"\necho c\n",
+                "if true\necho a\necho \"b\"\necho c\n",
+                "if true\necho a\necho \"b\"\necho c\nend\n",
+                "if true\nend\n",
+                "if true\n(echo end\n",
+                "if true\n(echo end)\n",
+            ],
+        );
+    }
+
+    #[test]
+    fn line_endings() {
+        check_edits(
+            "echo a\r\necho b\recho c\n",
+            &[
+                ((1, 6), (1, 6), "!"),
+                ((2, 0), (2, 4), "printf"),
+                ((0, 6), (1, 0), ""),
+                ((1, 0), (1, 0), "set x 1\r"),
+            ],
+            &[
+                "echo a\r\necho b!\recho c\n",
+                "echo a\r\necho b!\rprintf c\n",
+                "echo aecho b!\rprintf c\n",
+                "echo aecho b!\rset x 1\rprintf c\n",
+            ],
+        );
+    }
+
+    #[test]
+    fn utf16_columns() {
+        check_edits(
+            "echo 😀 é\n",
+            &[
+                ((0, 8), (0, 9), "e"),
+                ((0, 7), (0, 8), ""),
+                ((0, 5), (0, 7), ""),
+            ],
+            &["echo 😀 e\n", "echo 😀e\n", "echo e\n"],
+        );
+    }
+}
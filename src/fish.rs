@@ -0,0 +1,303 @@
+//! Syntax checking with the locally installed fish, which knows fish's real
+//! grammar, through `fish --no-execute`.
+
+use {
+    crate::{
+        config::{Config, Severity},
+        lints::FISH_SYNTAX_ERROR,
+        parser::{SyntaxTree, TextRange},
+    },
+    std::{collections::HashSet, process::Stdio, time::Duration},
+    tokio::{io::AsyncWriteExt, process::Command},
+    tower_lsp::lsp_types::*,
+};
+
+/// How long fish may take to check a buffer before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A fish release, by major and minor version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(pub u32, pub u32);
+
+impl Version {
+    /// The first `MAJOR.MINOR` in `text`, such as `3.3` or the output of
+    /// `fish --version`, `fish, version 3.7.1`.
+    pub fn parse(text: &str) -> Option<Self> {
+        text.split(|c: char| !c.is_ascii_digit() && c != '.')
+            .find_map(|word| {
+                let mut parts = word.split('.');
+                let major = parts.next()?.parse().ok()?;
+                let minor = parts.next()?.parse().ok()?;
+                Some(Version(major, minor))
+            })
+    }
+}
+
+/// The version of the fish to target: the configured `fishVersion`, or that
+/// of the fish that would check documents, whether or not checking is on.
+pub async fn version(config: &Config, executables: &HashSet<String>) -> Option<Version> {
+    if let Some(version) = &config.fish_version {
+        return Version::parse(version);
+    }
+    let fish = match &config.fish_path {
+        Some(path) => path.as_str(),
+        None if executables.contains("fish") => "fish",
+        None => return None,
+    };
+    let run = Command::new(fish)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(TIMEOUT, run).await {
+        Ok(Ok(output)) => Version::parse(&String::from_utf8_lossy(&output.stdout)),
+        _ => None,
+    }
+}
+
+/// The fish binary to run: the configured `fishPath`, or `fish` when it is on
+/// `$PATH`.
+pub fn fish_binary(config: &Config, executables: &HashSet<String>) -> Option<String> {
+    if config.severity(&FISH_SYNTAX_ERROR) == Severity::Off {
+        return None;
+    }
+    match &config.fish_path {
+        Some(path) => Some(path.clone()),
+        None => executables.contains("fish").then(|| "fish".to_string()),
+    }
+}
+
+/// Errors `fish --no-execute` reports for the text of `tree`. Empty when fish
+/// cannot be run, takes too long or finds nothing wrong.
+pub async fn check(fish: &str, tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let Ok(mut child) = Command::new(fish)
+        .arg("--no-execute")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    else {
+        return Vec::new();
+    };
+
+    let text = tree.text().to_string();
+    let mut stdin = child.stdin.take();
+    let run = async move {
+        if let Some(stdin) = &mut stdin {
+            // fish stops reading at the first error, closing the pipe early.
+            let _ = stdin.write_all(text.as_bytes()).await;
+        }
+        drop(stdin);
+        child.wait_with_output().await
+    };
+    match tokio::time::timeout(TIMEOUT, run).await {
+        Ok(Ok(output)) => parse_errors(tree, &String::from_utf8_lossy(&output.stderr)),
+        _ => Vec::new(),
+    }
+}
+
+/// Turn fish's error report into diagnostics. Each error reads
+///
+/// ```text
+/// Standard input (line 3): Missing end to balance this if statement
+/// if true
+/// ^^
+/// ```
+///
+/// where the carets, optionally joined by `~`, mark the offending columns of
+/// the quoted line.
+fn parse_errors(tree: &SyntaxTree, stderr: &str) -> Vec<Diagnostic> {
+    let lines = tree.line_index();
+    let mut diagnostics = Vec::new();
+    let mut output = stderr.lines().peekable();
+
+    while let Some(line) = output.next() {
+        let Some((line_number, message)) = error_header(line) else {
+            continue;
+        };
+        let Some(row) = line_number
+            .checked_sub(1)
+            .filter(|row| *row < lines.line_count())
+        else {
+            continue;
+        };
+        let line_range = lines.line_range(row);
+        let source_line = &tree.text()[line_range.start..line_range.end];
+
+        // The quoted source line, then the carets under it.
+        output.next_if(|quoted| error_header(quoted).is_none());
+        let carets = output
+            .next_if(|marker| marker.trim_start().starts_with('^'))
+            .and_then(caret_columns);
+        let range = match carets {
+            Some((start, end)) => {
+                let byte = |column: usize| {
+                    source_line
+                        .char_indices()
+                        .nth(column)
+                        .map_or(source_line.len(), |(byte, _)| byte)
+                };
+                TextRange::new(line_range.start + byte(start), line_range.start + byte(end))
+            }
+            None => {
+                let indent = source_line.len() - source_line.trim_start().len();
+                TextRange::new(line_range.start + indent, line_range.end)
+            }
+        };
+
+        diagnostics.push(FISH_SYNTAX_ERROR.diagnostic(tree.lsp_range(range), message));
+    }
+    diagnostics
+}
+
+/// The 1-based line number and message of a `NAME (line N): MESSAGE` line.
+fn error_header(line: &str) -> Option<(usize, &str)> {
+    let (_, rest) = line.split_once("(line ")?;
+    let (number, message) = rest.split_once("): ")?;
+    Some((number.parse().ok()?, message.trim()))
+}
+
+/// The character columns a `^~~^` marker spans, end exclusive.
+fn caret_columns(marker: &str) -> Option<(usize, usize)> {
+    let start = marker.chars().position(|c| c == '^')?;
+    let length = marker
+        .chars()
+        .skip(start)
+        .take_while(|c| matches!(c, '^' | '~'))
+        .count();
+    Some((start, start + length))
+}
+
+/// Add fish's findings to the server's own, leaving out errors on lines
+/// where the server already reports one.
+pub fn merge(diagnostics: &mut Vec<Diagnostic>, fish: Vec<Diagnostic>) {
+    let reported: HashSet<u32> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+        .map(|diagnostic| diagnostic.range.start.line)
+        .collect();
+    diagnostics.extend(
+        fish.into_iter()
+            .filter(|diagnostic| !reported.contains(&diagnostic.range.start.line)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    /// A script standing in for fish, see `tests/fixtures/fish`.
+    const STUB: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fish");
+
+    fn ranges(tree: &SyntaxTree, diagnostics: &[Diagnostic]) -> Vec<(String, String)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let range = tree.line_index().text_range(diagnostic.range);
+                let text = tree.text()[range.start..range.end].to_string();
+                (text, diagnostic.message.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_error_reports() {
+        let tree = parse("echo ok\nif true\n    echo\n");
+        let stderr = "\
+Standard input (line 2): Missing end to balance this if statement
+if true
+^^
+warning: Error while reading file Standard input
+Standard input (line 9): Beyond the end
+Standard input (line 3): No carets
+";
+        assert_eq!(
+            ranges(&tree, &parse_errors(&tree, stderr)),
+            [
+                (
+                    "if".into(),
+                    "Missing end to balance this if statement".into()
+                ),
+                ("echo".into(), "No carets".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_with_parser_errors() {
+        let error = |line, code: &str| Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(code.into())),
+            ..Default::default()
+        };
+        let mut diagnostics = vec![error(0, "SYNTAX_ERROR")];
+        merge(
+            &mut diagnostics,
+            vec![error(0, "FISH_SYNTAX_ERROR"), error(1, "FISH_SYNTAX_ERROR")],
+        );
+        let lines: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.code.clone()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (0, Some(NumberOrString::String("SYNTAX_ERROR".into()))),
+                (1, Some(NumberOrString::String("FISH_SYNTAX_ERROR".into()))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_with_fish() {
+        let tree = parse("echo ok\n  echo broken\n");
+        let diagnostics = check(STUB, &tree).await;
+        assert_eq!(
+            ranges(&tree, &diagnostics),
+            [("broken".into(), "Unexpected word".into())]
+        );
+        assert!(check(STUB, &parse("echo ok\n")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_fish() {
+        let tree = parse("echo broken\n");
+        assert!(check("/nonexistent/fish", &tree).await.is_empty());
+
+        let config = Config {
+            fish_path: Some("/nonexistent/fish".into()),
+            ..Default::default()
+        };
+        assert_eq!(version(&config, &HashSet::new()).await, None);
+        assert_eq!(fish_binary(&Config::default(), &HashSet::new()), None);
+    }
+
+    #[tokio::test]
+    async fn fish_timing_out() {
+        let started = std::time::Instant::now();
+        assert!(check(STUB, &parse("hang\necho broken\n")).await.is_empty());
+        assert!(started.elapsed() < TIMEOUT * 2);
+    }
+
+    #[tokio::test]
+    async fn versions() {
+        let stub = Config {
+            fish_path: Some(STUB.into()),
+            ..Default::default()
+        };
+        assert_eq!(version(&stub, &HashSet::new()).await, Some(Version(3, 7)));
+        let configured = Config {
+            fish_version: Some("3.3".into()),
+            ..stub
+        };
+        assert_eq!(
+            version(&configured, &HashSet::new()).await,
+            Some(Version(3, 3))
+        );
+        assert_eq!(Version::parse("fish, version 3.7.1"), Some(Version(3, 7)));
+        assert_eq!(Version::parse("unknown"), None);
+    }
+}
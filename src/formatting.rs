@@ -0,0 +1,445 @@
+//! Document and range formatting in the style of `fish_indent`: block bodies
+//! indented one level, one statement per line, single spaces between words
+//! and around `|`, `&&` and `||`, and redirections written against their
+//! targets. Comments and line continuations are kept, and lines continued
+//! with `\` or after a `|` are indented one level further.
+
+use {
+    crate::parser::{Element, Node, SyntaxKind, SyntaxTree, TextRange, Token},
+    tower_lsp::lsp_types::{FormattingOptions, TextEdit},
+};
+
+/// Edits formatting the whole document, or `None` when it has syntax errors.
+pub fn format_document(tree: &SyntaxTree, options: &FormattingOptions) -> Option<Vec<TextEdit>> {
+    let root = tree.root();
+    let span = TextRange::new(0, tree.text().len());
+    if has_errors(tree, span) {
+        return None;
+    }
+    let mut formatter = Formatter::new(tree.text(), options, 0);
+    formatter.elements(root, &root.children);
+    let newline = formatter.newline;
+    let mut text = formatter.out.trim_end_matches(newline).to_string();
+    // A newline after a `\` ending the file would make it a line continuation.
+    if !text.is_empty() && !ends_in_bare_escape(tree) {
+        text.push_str(newline);
+    }
+    Some(edits(tree, span, &text))
+}
+
+/// Edits formatting the statements `range` touches, or `None` when they have
+/// syntax errors.
+pub fn format_range(
+    tree: &SyntaxTree,
+    options: &FormattingOptions,
+    range: TextRange,
+) -> Option<Vec<TextEdit>> {
+    let src = tree.text();
+    let lines = tree.line_index();
+    let first_line = lines.line_range(lines.line_of(range.start));
+    let last_line = lines.line_range(lines.line_of(range.end));
+    let range = TextRange::new(first_line.start, last_line.end);
+
+    // The innermost list of statements holding the whole range, the depth
+    // its statements are indented to, and the statement holding the list.
+    let (mut list, mut list_depth, mut statement) = (tree.root(), 0, None);
+    let (mut node, mut depth, mut job) = (tree.root(), 0, None);
+    while let Some(child) = node
+        .child_nodes()
+        .find(|child| child.range.contains_range(range))
+    {
+        depth += indents(child) as usize;
+        match child.kind {
+            SyntaxKind::JobList => (list, list_depth, statement) = (child, depth, job),
+            SyntaxKind::Job => job = Some(child),
+            _ => {}
+        }
+        node = child;
+    }
+
+    let starts_line = |offset: usize| {
+        let line_start = lines.line_range(lines.line_of(offset)).start;
+        src[line_start..offset].trim().is_empty()
+    };
+    let touched = |element: &Element| {
+        let element = element.range();
+        element.start < range.end.max(range.start + 1) && range.start < element.end
+    };
+    let statements: Vec<usize> = (0..list.children.len())
+        .filter(|&i| {
+            matches!(
+                list.children[i].kind(),
+                SyntaxKind::Job | SyntaxKind::Comment
+            )
+        })
+        .collect();
+    let last = *statements.iter().rfind(|&&i| touched(&list.children[i]))?;
+    let first = statements
+        .iter()
+        .position(|&i| touched(&list.children[i]))?;
+    // Start at the first statement of the line, so it can be indented.
+    let Some(first) = statements[..=first]
+        .iter()
+        .rev()
+        .find(|&&i| starts_line(list.children[i].range().start))
+    else {
+        // The range starts beside a block header: format the whole block.
+        return format_range(tree, options, statement?.range);
+    };
+
+    let elements = &list.children[*first..=last];
+    let start = elements[0].range().start;
+    let span = TextRange::new(
+        lines.line_range(lines.line_of(start)).start,
+        list.children[last].range().end,
+    );
+    if has_errors(tree, span) {
+        return None;
+    }
+    let mut formatter = Formatter::new(src, options, list_depth);
+    formatter.elements(list, elements);
+    Some(edits(tree, span, &formatter.out))
+}
+
+fn has_errors(tree: &SyntaxTree, span: TextRange) -> bool {
+    tree.errors()
+        .iter()
+        .any(|error| span.start <= error.range.start && error.range.start <= span.end)
+}
+
+/// Whether the file ends in a `\` escaping nothing or a lone `\r`.
+fn ends_in_bare_escape(tree: &SyntaxTree) -> bool {
+    tree.root().tokens().last().is_some_and(|token| {
+        token.kind == SyntaxKind::Escape && matches!(tree.token_text(token), "\\" | "\\\r")
+    })
+}
+
+/// The line terminator the document uses.
+fn newline(src: &str) -> &'static str {
+    if src.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Whether the contents of `node` are indented one level past the statement
+/// holding it.
+fn indents(node: &Node) -> bool {
+    matches!(node.kind, SyntaxKind::JobList | SyntaxKind::CaseClause)
+}
+
+/// A single edit replacing the part of `span` that `formatted` changes, if any.
+fn edits(tree: &SyntaxTree, span: TextRange, formatted: &str) -> Vec<TextEdit> {
+    let old = &tree.text()[span.start..span.end];
+    let prefix = old
+        .char_indices()
+        .zip(formatted.chars())
+        .find(|((_, old), new)| old != new)
+        .map_or(old.len().min(formatted.len()), |((at, _), _)| at);
+    let suffix = old[prefix..]
+        .chars()
+        .rev()
+        .zip(formatted[prefix..].chars().rev())
+        .take_while(|(old, new)| old == new)
+        .map(|(old, _)| old.len_utf8())
+        .sum::<usize>();
+    if prefix == old.len() && prefix == formatted.len() {
+        return Vec::new();
+    }
+    let replaced = TextRange::new(span.start + prefix, span.end - suffix);
+    vec![TextEdit {
+        range: tree.lsp_range(replaced),
+        new_text: formatted[prefix..formatted.len() - suffix].to_string(),
+    }]
+}
+
+struct Formatter<'a> {
+    src: &'a str,
+    out: String,
+    indent: String,
+    newline: &'static str,
+    depth: usize,
+    /// Nothing but indentation is due on the current line yet.
+    line_start: bool,
+    /// A space is due before the next word on the line.
+    space: bool,
+    /// The current line continues a statement from the line before.
+    continued: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(src: &'a str, options: &FormattingOptions, depth: usize) -> Self {
+        let indent = if options.insert_spaces {
+            " ".repeat(options.tab_size as usize)
+        } else {
+            "\t".to_string()
+        };
+        Self {
+            src,
+            out: String::new(),
+            indent,
+            newline: newline(src),
+            depth,
+            line_start: true,
+            space: false,
+            continued: false,
+        }
+    }
+
+    /// Write `text` on the current line, indenting it if it is the first.
+    fn word(&mut self, text: &str) {
+        if self.line_start {
+            let depth = self.depth + self.continued as usize;
+            self.out.push_str(&self.indent.repeat(depth));
+        } else if self.space {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.line_start = false;
+        self.space = false;
+    }
+
+    fn space(&mut self) {
+        self.space = !self.line_start;
+    }
+
+    /// End the line. At most one blank line is kept, and none at the start.
+    fn newline(&mut self, continued: bool) {
+        let blank_line = self.line_start && self.out.ends_with(&self.newline.repeat(2));
+        if !self.out.is_empty() && !blank_line {
+            self.out.push_str(self.newline);
+        }
+        self.line_start = true;
+        self.space = false;
+        self.continued = continued;
+    }
+
+    /// Start a new line unless one was just started.
+    fn break_line(&mut self) {
+        if !self.line_start {
+            self.newline(false);
+        }
+    }
+
+    fn elements(&mut self, parent: &Node, elements: &[Element]) {
+        for (i, element) in elements.iter().enumerate() {
+            match element {
+                Element::Token(token) => self.token(parent, token, &elements[i + 1..]),
+                Element::Node(node) => self.node(parent, node),
+            }
+        }
+    }
+
+    fn node(&mut self, parent: &Node, node: &Node) {
+        match node.kind {
+            SyntaxKind::Job if is_statement_list(parent) => {
+                // Jobs continuing a condition with `and` or `or` may follow
+                // a `;` on the same line; every other job starts its own.
+                if !starts_with_conjunction(node, self.src) {
+                    self.break_line();
+                }
+                self.continued = false;
+                self.elements(node, &node.children);
+            }
+            SyntaxKind::CaseClause => {
+                self.break_line();
+                self.depth += 1;
+                self.elements(node, &node.children);
+                self.depth -= 1;
+            }
+            SyntaxKind::JobList => {
+                self.depth += 1;
+                self.elements(node, &node.children);
+                self.depth -= 1;
+            }
+            SyntaxKind::Redirect => {
+                self.space();
+                for child in &node.children {
+                    match child {
+                        Element::Token(token) if token.kind == SyntaxKind::Whitespace => {}
+                        Element::Token(token) => self.token(node, token, &[]),
+                        Element::Node(target) => {
+                            self.space = false;
+                            self.word(target.text(self.src));
+                        }
+                    }
+                }
+            }
+            SyntaxKind::Word => self.word(node.text(self.src)),
+            _ => self.elements(node, &node.children),
+        }
+    }
+
+    fn token(&mut self, parent: &Node, token: &Token, rest: &[Element]) {
+        let text = token.text(self.src);
+        match token.kind {
+            SyntaxKind::Whitespace => self.space(),
+            SyntaxKind::Newline => {
+                let continued = matches!(
+                    parent.kind,
+                    SyntaxKind::Command
+                        | SyntaxKind::Pipeline
+                        | SyntaxKind::Job
+                        | SyntaxKind::NotStatement
+                        | SyntaxKind::Redirect
+                );
+                self.newline(continued);
+            }
+            SyntaxKind::LineContinuation => {
+                self.word("\\");
+                self.newline(true);
+            }
+            SyntaxKind::Semicolon => {
+                // A `;` only survives before `and` or `or`; elsewhere the
+                // statements it separates go on lines of their own.
+                let next = rest
+                    .iter()
+                    .find(|element| element.kind() != SyntaxKind::Whitespace);
+                if let Some(Element::Node(job)) = next {
+                    if job.kind == SyntaxKind::Job && starts_with_conjunction(job, self.src) {
+                        self.space = false;
+                        self.word(";");
+                        self.space();
+                    }
+                }
+            }
+            SyntaxKind::Comment => {
+                let switch_body = parent.kind == SyntaxKind::SwitchStatement;
+                self.depth += switch_body as usize;
+                self.space();
+                self.word(text);
+                self.depth -= switch_body as usize;
+            }
+            SyntaxKind::Pipe | SyntaxKind::AndAnd | SyntaxKind::OrOr | SyntaxKind::Ampersand => {
+                self.space();
+                self.word(text);
+                self.space();
+            }
+            SyntaxKind::RedirectOp => {
+                self.space();
+                self.word(text);
+            }
+            SyntaxKind::Keyword if closes_block(parent, token, self.src) => {
+                self.break_line();
+                self.word(text);
+            }
+            _ => self.word(text),
+        }
+    }
+}
+
+/// Nodes whose jobs are statements, each on its own line.
+fn is_statement_list(node: &Node) -> bool {
+    matches!(node.kind, SyntaxKind::SourceFile | SyntaxKind::JobList)
+}
+
+fn starts_with_conjunction(job: &Node, src: &str) -> bool {
+    job.children
+        .first()
+        .and_then(Element::as_token)
+        .is_some_and(|token| {
+            token.kind == SyntaxKind::Keyword && matches!(token.text(src), "and" | "or")
+        })
+}
+
+/// Whether `keyword` is the `end` of a block or the `else` starting one of
+/// its clauses, which always start their own line.
+fn closes_block(parent: &Node, keyword: &Token, src: &str) -> bool {
+    match parent.kind {
+        SyntaxKind::ElseIfClause | SyntaxKind::ElseClause => {
+            parent.keyword().is_some_and(|first| first == keyword)
+        }
+        kind => kind.is_block() && keyword.text(src) == "end",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::parser::{parse, LineIndex},
+    };
+
+    /// Pairs of scripts and their expected formatting, written by hand after
+    /// `fish_indent`'s rules rather than produced by running it.
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/formatting");
+
+    fn options() -> FormattingOptions {
+        FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        }
+    }
+
+    fn apply(text: &str, edits: &[TextEdit]) -> String {
+        let lines = LineIndex::new(text);
+        let mut out = text.to_string();
+        for edit in edits.iter().rev() {
+            let range = lines.text_range(edit.range);
+            out.replace_range(range.start..range.end, &edit.new_text);
+        }
+        out
+    }
+
+    /// Format `text`, checking that formatting the result changes nothing.
+    fn format(text: &str) -> String {
+        let edits = format_document(&parse(text), &options()).expect("syntax errors");
+        let formatted = apply(text, &edits);
+        let again = format_document(&parse(&formatted), &options()).expect("syntax errors");
+        assert_eq!(again, [], "formatting {formatted:?} again");
+        formatted
+    }
+
+    #[test]
+    fn formatting_corpus() {
+        for name in ["blocks", "comments", "continuations", "spacing"] {
+            let read = |file: &str| std::fs::read_to_string(format!("{FIXTURES}/{file}")).unwrap();
+            let expected = read(&format!("{name}.formatted.fish"));
+            assert_eq!(format(&read(&format!("{name}.fish"))), expected, "{name}");
+            assert_eq!(format(&expected), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(
+            format("if true\r\necho a\r\nend\r\n\r\n\r\n"),
+            "if true\r\n    echo a\r\nend\r\n"
+        );
+        // A lone `\r` separates words, not statements.
+        assert_eq!(
+            format("echo a\rif true\recho b\rend\r"),
+            "echo a if true echo b end\n"
+        );
+    }
+
+    #[test]
+    fn trailing_backslash() {
+        for text in ["echo foo \\", "echo foo\\", "echo foo \\\r"] {
+            assert_eq!(format(text), text);
+        }
+        assert_eq!(format("echo foo \\\n\n"), "echo foo \\\n");
+        assert_eq!(format("echo foo \\\nbar"), "echo foo \\\n    bar\n");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for text in ["echo >|foo\n", "if true\n", "echo 'a\n"] {
+            assert_eq!(format_document(&parse(text), &options()), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let text = "function f\necho  a\n  echo   b\nend\necho   c\n";
+        let tree = parse(text);
+        let second = text.find("  echo").unwrap();
+        let edits = format_range(&tree, &options(), TextRange::new(second, second)).unwrap();
+        assert_eq!(
+            apply(text, &edits),
+            "function f\necho  a\n    echo b\nend\necho   c\n"
+        );
+    }
+}
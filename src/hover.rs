@@ -0,0 +1,320 @@
+use {
+    crate::{
+        builtins::{builtin, special_variable},
+        definition::load,
+        parser::{header_words, word_value, Node, SyntaxTree},
+        scopes::{BindingKind, ScopeMap},
+        server::State,
+        symbols::{function_description, leading_comment, symbol_at, Scope, SetCommand, SymbolRef},
+    },
+    tower_lsp::lsp_types::*,
+};
+
+pub fn hover(state: &State, uri: &Url, position: Position) -> Option<Hover> {
+    let document = state.documents.get(uri)?;
+    let tree = &document.tree;
+    let offset = tree.line_index().offset(position);
+    let symbol = symbol_at(tree, offset)?;
+
+    let markdown = match &symbol {
+        SymbolRef::Command { name, .. } => command_hover(state, uri, name)?,
+        SymbolRef::Variable { name, .. } => variable_hover(tree, name, offset)?,
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: markdown,
+        }),
+        range: Some(tree.lsp_range(symbol.range())),
+    })
+}
+
+fn command_hover(state: &State, uri: &Url, name: &str) -> Option<String> {
+    // Prefer a definition in the hovered file, then any other open file.
+    let documents = state
+        .documents
+        .get(uri)
+        .into_iter()
+        .chain(state.documents.values());
+    for document in documents {
+        if let Some(function) = find_function(&document.tree, name) {
+            return Some(function_hover(&document.tree, function));
+        }
+    }
+
+    // Then the indexed workspace files that are not open.
+    for (other, file) in &state.workspace.files {
+        if state.documents.contains_key(other) {
+            continue;
+        }
+        if let Some(function) = find_function(&file.tree, name) {
+            let path = other
+                .to_file_path()
+                .map_or_else(|_| other.to_string(), |path| path.display().to_string());
+            return Some(format!(
+                "{}\n\nDefined in `{path}`",
+                function_hover(&file.tree, function)
+            ));
+        }
+    }
+
+    // Then the file fish would autoload it from.
+    if let Some(path) = state.autoload.function_file(name) {
+        if let Some((_, tree)) = load(state, &path) {
+            if let Some(function) = find_function(&tree, name) {
+                return Some(format!(
+                    "{}\n\nDefined in `{}`",
+                    function_hover(&tree, function),
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    let builtin = builtin(name)?;
+    Some(format!(
+        "```fish\n{}\n```\n\n{}",
+        builtin.synopsis, builtin.description
+    ))
+}
+
+fn find_function<'a>(tree: &'a SyntaxTree, name: &str) -> Option<&'a Node> {
+    tree.functions().find(|function| {
+        header_words(function)
+            .next()
+            .and_then(|word| word_value(word, tree.text()))
+            .is_some_and(|function_name| function_name == name)
+    })
+}
+
+/// The text of a block's header, from its keyword to its last word.
+fn header<'a>(tree: &'a SyntaxTree, block: &Node) -> &'a str {
+    let header_end = header_words(block)
+        .last()
+        .map_or(block.range.start, |word| word.range.end);
+    &tree.text()[block.range.start..header_end]
+}
+
+fn function_hover(tree: &SyntaxTree, function: &Node) -> String {
+    let mut markdown = format!("```fish\n{}\n```", header(tree, function));
+    if let Some(description) = function_description(function, tree.text()) {
+        markdown.push_str("\n\n");
+        markdown.push_str(&description);
+    }
+    if let Some(comment) = leading_comment(tree, function) {
+        markdown.push_str("\n\n");
+        markdown.push_str(&comment);
+    }
+    markdown
+}
+
+fn variable_hover(tree: &SyntaxTree, name: &str, offset: usize) -> Option<String> {
+    let src = tree.text();
+    let scopes = ScopeMap::build(tree);
+    let binding = scopes.resolve(tree, name, offset);
+    let statement = binding.and_then(|binding| {
+        tree.root()
+            .descendants()
+            .find(|node| node.kind.is_statement() && node.range == binding.statement)
+    });
+
+    let mut sections = Vec::new();
+    match (binding.map(|binding| binding.kind), statement) {
+        (Some(BindingKind::Set), Some(command)) => {
+            if let Some(set) = SetCommand::parse(command, src) {
+                sections.extend(set_sections(&set, src));
+            }
+        }
+        (Some(BindingKind::Read), Some(command)) => {
+            sections.push(format!("```fish\n{}\n```", command.text(src)));
+        }
+        (Some(BindingKind::For), Some(for_loop)) => {
+            sections.push(format!(
+                "```fish\n{}\n```\n\nLoop variable",
+                header(tree, for_loop)
+            ));
+        }
+        (Some(BindingKind::Argument), Some(function)) => {
+            sections.push(format!(
+                "```fish\n{}\n```\n\nFunction argument",
+                header(tree, function)
+            ));
+        }
+        _ => {}
+    }
+
+    if let Some(description) = special_variable(name) {
+        sections.insert(0, format!("`${name}`: {description}"));
+    }
+
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+/// The statement, scope and value of the `set` that defines a variable.
+fn set_sections(set: &SetCommand, src: &str) -> Vec<String> {
+    let mut sections = vec![format!("```fish\n{}\n```", set.command.text(src))];
+
+    let mut attributes = Vec::new();
+    match set.scope() {
+        Some(Scope::Local) => attributes.push("local"),
+        Some(Scope::Function) => attributes.push("function"),
+        Some(Scope::Global) => attributes.push("global"),
+        Some(Scope::Universal) => attributes.push("universal"),
+        None => {}
+    }
+    if set.has_flag('x', "export") {
+        attributes.push("exported");
+    } else if set.has_flag('u', "unexport") {
+        attributes.push("unexported");
+    }
+    if set.has_flag('a', "append") {
+        attributes.push("appended to");
+    } else if set.has_flag('p', "prepend") {
+        attributes.push("prepended to");
+    }
+    if !attributes.is_empty() {
+        sections.push(format!("Scope: {}", attributes.join(", ")));
+    }
+
+    let values: Option<Vec<String>> = set
+        .values
+        .iter()
+        .map(|word| word_value(word, src))
+        .collect();
+    if let Some(values) = values.filter(|values| !values.is_empty()) {
+        let shown: Vec<String> = values.iter().map(|value| format!("`{value}`")).collect();
+        sections.push(format!("Value: {}", shown.join(" ")));
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{document::Document, workspace::IndexedFile},
+    };
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file:///project/{path}")).unwrap()
+    }
+
+    fn state_with(files: &[(&str, &str)]) -> State {
+        let mut state = State::default();
+        for (path, text) in files {
+            state
+                .documents
+                .insert(uri(path), Document::new(text.to_string(), 1));
+        }
+        state
+    }
+
+    /// The markdown shown when hovering `line`:`character` of `path`.
+    fn hover_text(state: &State, path: &str, line: u32, character: u32) -> Option<String> {
+        let hover = hover(state, &uri(path), Position::new(line, character))?;
+        match hover.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn scoped_variables() {
+        let text = "\
+function a
+    set -l x one
+end
+function b
+    echo $x
+end
+if true
+    set -l y two
+end
+echo $y
+set -g z three
+function c
+    set -l z four
+    echo $z
+end
+echo $z
+";
+        let state = state_with(&[("config.fish", text)]);
+        // Another function's local, and a local of a block that has ended.
+        assert_eq!(hover_text(&state, "config.fish", 4, 10), None);
+        assert_eq!(hover_text(&state, "config.fish", 9, 6), None);
+        assert_eq!(
+            hover_text(&state, "config.fish", 13, 10).as_deref(),
+            Some("```fish\nset -l z four\n```\n\nScope: local\n\nValue: `four`")
+        );
+        assert_eq!(
+            hover_text(&state, "config.fish", 15, 6).as_deref(),
+            Some("```fish\nset -g z three\n```\n\nScope: global\n\nValue: `three`")
+        );
+    }
+
+    #[test]
+    fn indexed_functions() {
+        let mut state = state_with(&[("config.fish", "greet\n")]);
+        state.workspace.files.insert(
+            uri("functions/greet.fish"),
+            IndexedFile::new(
+                "# Says hello.\nfunction greet --description 'Greet someone'\n    echo hi\nend\n",
+            ),
+        );
+        assert_eq!(
+            hover_text(&state, "config.fish", 0, 2).as_deref(),
+            Some(
+                "```fish\nfunction greet --description 'Greet someone'\n```\n\nGreet someone\
+                 \n\nSays hello.\n\nDefined in `/project/functions/greet.fish`"
+            )
+        );
+    }
+
+    #[test]
+    fn special_variables() {
+        let state = state_with(&[(
+            "config.fish",
+            "echo $FISH_VERSION $fish_version
+",
+        )]);
+        assert_eq!(
+            hover_text(&state, "config.fish", 0, 7).as_deref(),
+            Some("`$FISH_VERSION`: The version of the running fish.")
+        );
+        assert_eq!(hover_text(&state, "config.fish", 0, 22), None);
+    }
+
+    #[test]
+    fn builtins_and_functions() {
+        let text = "\
+# Prints a greeting.
+function greet --description 'Greet someone'
+    echo hi
+end
+greet
+";
+        let state = state_with(&[("config.fish", text), ("other.fish", "greet\n")]);
+        let function = "```fish\nfunction greet --description 'Greet someone'\n```\n\n\
+                        Greet someone\n\nPrints a greeting.";
+        assert_eq!(
+            hover_text(&state, "config.fish", 4, 0).as_deref(),
+            Some(function)
+        );
+        // Found in another open file too.
+        assert_eq!(
+            hover_text(&state, "other.fish", 0, 0).as_deref(),
+            Some(function)
+        );
+
+        let echo = builtin("echo").unwrap();
+        assert_eq!(
+            hover_text(&state, "config.fish", 2, 5),
+            Some(format!(
+                "```fish\n{}\n```\n\n{}",
+                echo.synopsis, echo.description
+            ))
+        );
+        assert_eq!(hover_text(&state, "config.fish", 0, 3), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! The registry of everything the server reports: each lint has a stable
+//! code, a default severity and a description. Users re-rate or turn off
+//! lints by code through the `lints` setting.
+
+use {
+    crate::{
+        config::{Config, Severity},
+        diagnostics::SOURCE,
+    },
+    tower_lsp::lsp_types::*,
+};
+
+/// The published `docs/lints.md`, which diagnostics link to unless
+/// `lintDocumentationUrl` points elsewhere.
+pub const DOCUMENTATION_URL: &str = "https://github.com/fish-lsp/fish-lsp/blob/main/docs/lints.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lint {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+pub const SYNTAX_ERROR: Lint = Lint {
+    code: "SYNTAX_ERROR",
+    severity: Severity::Error,
+    description: "Text fish cannot parse, such as unclosed quotes or substitutions",
+};
+pub const FISH_SYNTAX_ERROR: Lint = Lint {
+    code: "FISH_SYNTAX_ERROR",
+    severity: Severity::Error,
+    description: "An error reported by `fish --no-execute`",
+};
+pub const UNCLOSED_BLOCK: Lint = Lint {
+    code: "UNCLOSED_BLOCK",
+    severity: Severity::Error,
+    description:
+        "A block opened by `function`, `if`, `while`, `for`, `switch` or `begin` with no `end`",
+};
+pub const STRAY_END: Lint = Lint {
+    code: "STRAY_END",
+    severity: Severity::Error,
+    description: "An `end` that closes no block",
+};
+pub const STRAY_ELSE: Lint = Lint {
+    code: "STRAY_ELSE",
+    severity: Severity::Error,
+    description: "An `else` outside an `if` block, or after its final `else`",
+};
+pub const STRAY_CASE: Lint = Lint {
+    code: "STRAY_CASE",
+    severity: Severity::Error,
+    description: "A `case` outside a `switch` block",
+};
+pub const LOOP_CONTROL_OUTSIDE_LOOP: Lint = Lint {
+    code: "LOOP_CONTROL_OUTSIDE_LOOP",
+    severity: Severity::Error,
+    description: "`break` or `continue` with no enclosing `for` or `while` loop",
+};
+pub const INVALID_SET_OPTIONS: Lint = Lint {
+    code: "INVALID_SET_OPTIONS",
+    severity: Severity::Error,
+    description: "Unknown or conflicting options to `set`",
+};
+pub const INVALID_VARIABLE_NAME: Lint = Lint {
+    code: "INVALID_VARIABLE_NAME",
+    severity: Severity::Error,
+    description: "A variable name with characters other than letters, digits and `_`",
+};
+pub const INVALID_INDEX: Lint = Lint {
+    code: "INVALID_INDEX",
+    severity: Severity::Error,
+    description: "A `set` index that is not a list of numbers or ranges",
+};
+pub const READ_ONLY_VARIABLE: Lint = Lint {
+    code: "READ_ONLY_VARIABLE",
+    severity: Severity::Error,
+    description: "Setting or erasing a variable fish maintains itself",
+};
+pub const UNKNOWN_COMMAND: Lint = Lint {
+    code: "UNKNOWN_COMMAND",
+    severity: Severity::Warning,
+    description: "A command that is not a builtin, a known function or an executable on `$PATH`",
+};
+pub const UNUSED_VARIABLE: Lint = Lint {
+    code: "UNUSED_VARIABLE",
+    severity: Severity::Warning,
+    description: "A local variable that is set but never read",
+};
+pub const UNDEFINED_VARIABLE: Lint = Lint {
+    code: "UNDEFINED_VARIABLE",
+    severity: Severity::Warning,
+    description: "An expansion of a variable that is never defined",
+};
+pub const GLOBAL_SET_IN_FUNCTION: Lint = Lint {
+    code: "GLOBAL_SET_IN_FUNCTION",
+    severity: Severity::Warning,
+    description: "`set` without a scope option in a function, changing a global variable",
+};
+pub const BASHISM: Lint = Lint {
+    code: "BASHISM",
+    severity: Severity::Warning,
+    description: "Bash syntax that fish spells differently",
+};
+pub const BASH_SYNTAX: Lint = Lint {
+    code: "BASH_SYNTAX",
+    severity: Severity::Error,
+    description: "Bash syntax fish cannot parse, such as `${var}`, `$?`, here documents or `fi`",
+};
+pub const UNUSED_SUPPRESSION: Lint = Lint {
+    code: "UNUSED_SUPPRESSION",
+    severity: Severity::Warning,
+    description: "A `# fish-lsp:` comment that silences no diagnostics",
+};
+pub const INVALID_SUPPRESSION: Lint = Lint {
+    code: "INVALID_SUPPRESSION",
+    severity: Severity::Warning,
+    description: "A `# fish-lsp:` comment with an unknown directive or lint code",
+};
+
+pub const LINTS: &[Lint] = &[
+    SYNTAX_ERROR,
+    FISH_SYNTAX_ERROR,
+    UNCLOSED_BLOCK,
+    STRAY_END,
+    STRAY_ELSE,
+    STRAY_CASE,
+    LOOP_CONTROL_OUTSIDE_LOOP,
+    INVALID_SET_OPTIONS,
+    INVALID_VARIABLE_NAME,
+    INVALID_INDEX,
+    READ_ONLY_VARIABLE,
+    UNKNOWN_COMMAND,
+    UNUSED_VARIABLE,
+    UNDEFINED_VARIABLE,
+    GLOBAL_SET_IN_FUNCTION,
+    BASHISM,
+    BASH_SYNTAX,
+    UNUSED_SUPPRESSION,
+    INVALID_SUPPRESSION,
+];
+
+/// The lint with `code`, ignoring case.
+pub fn find(code: &str) -> Option<&'static Lint> {
+    LINTS
+        .iter()
+        .find(|lint| lint.code.eq_ignore_ascii_case(code))
+}
+
+impl Lint {
+    /// A diagnostic for this lint at its default severity.
+    pub fn diagnostic(&self, range: Range, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            range,
+            severity: self.severity.to_lsp(),
+            code: Some(NumberOrString::String(self.code.into())),
+            source: Some(SOURCE.into()),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Give each diagnostic the severity configured for its lint, dropping those
+/// that are turned off, and link it to the lint's documentation.
+pub fn configure(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        let Some(lint) = find(code) else {
+            return true;
+        };
+        let Some(severity) = config.severity(lint).to_lsp() else {
+            return false;
+        };
+        diagnostic.severity = Some(severity);
+        let base = config
+            .lint_documentation_url
+            .as_deref()
+            .unwrap_or(DOCUMENTATION_URL);
+        diagnostic.code_description = Url::parse(&format!("{base}#{}", lint.code.to_lowercase()))
+            .ok()
+            .map(|href| CodeDescription { href });
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn href(config: &Config) -> Option<String> {
+        let mut diagnostics = vec![UNKNOWN_COMMAND.diagnostic(Range::default(), "")];
+        configure(config, &mut diagnostics);
+        diagnostics[0]
+            .code_description
+            .as_ref()
+            .map(|description| description.href.to_string())
+    }
+
+    #[test]
+    fn links_to_documentation() {
+        assert_eq!(
+            href(&Config::default()),
+            Some(format!("{DOCUMENTATION_URL}#unknown_command"))
+        );
+        let config = Config {
+            lint_documentation_url: Some("https://example.com/lints".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            href(&config).as_deref(),
+            Some("https://example.com/lints#unknown_command")
+        );
+    }
+}
@@ -2,7 +2,30 @@
 
 use anyhow::Result as Anyhow;
 
+pub(crate) mod actions;
+pub(crate) mod autoload;
+pub(crate) mod bashisms;
+pub(crate) mod builtins;
 pub(crate) mod completions;
+pub(crate) mod config;
+pub(crate) mod dataflow;
+pub(crate) mod definition;
+pub(crate) mod diagnostics;
+pub(crate) mod document;
+pub(crate) mod fish;
+pub(crate) mod formatting;
+pub(crate) mod hover;
+pub(crate) mod lints;
+pub(crate) mod outline;
+pub(crate) mod parser;
+pub(crate) mod references;
+pub(crate) mod rename;
+pub(crate) mod scheduler;
+pub(crate) mod scopes;
+pub(crate) mod suppressions;
+pub(crate) mod symbols;
+pub(crate) mod translate;
+pub(crate) mod workspace;
 
 mod cli;
 mod server;
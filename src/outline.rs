@@ -0,0 +1,251 @@
+use {
+    crate::{
+        parser::{Node, SyntaxKind, SyntaxTree, TextRange},
+        scopes::{BindingKind, ScopeMap},
+        symbols::{
+            abbr_definition, alias_definition, function_description, function_events,
+            function_name, CompleteCommand, Scope, SetCommand,
+        },
+    },
+    tower_lsp::lsp_types::*,
+};
+
+/// The outline of a document: functions with the blocks nested inside them,
+/// global and universal variables, abbreviations, aliases and completions.
+pub fn document_symbols(tree: &SyntaxTree) -> Vec<DocumentSymbol> {
+    let outline = Outline {
+        tree,
+        scopes: ScopeMap::build(tree),
+    };
+    outline.children(tree.root())
+}
+
+struct Outline<'a> {
+    tree: &'a SyntaxTree,
+    scopes: ScopeMap,
+}
+
+impl Outline<'_> {
+    fn children(&self, node: &Node) -> Vec<DocumentSymbol> {
+        let mut symbols = Vec::new();
+        for child in node.child_nodes() {
+            match child.kind {
+                SyntaxKind::FunctionDef => symbols.extend(self.function(child)),
+                kind if kind.is_block() => symbols.push(self.block(child)),
+                SyntaxKind::Command => symbols.extend(self.command(child)),
+                _ => symbols.extend(self.children(child)),
+            }
+        }
+        symbols
+    }
+
+    fn function(&self, function: &Node) -> Option<DocumentSymbol> {
+        let src = self.tree.text();
+        let (word, name) = function_name(function, src)?;
+        let events = function_events(function, src);
+        let (kind, detail) = if events.is_empty() {
+            (SymbolKind::FUNCTION, function_description(function, src))
+        } else {
+            (SymbolKind::EVENT, Some(events.join(" ")))
+        };
+        Some(self.symbol(
+            name,
+            detail,
+            kind,
+            function.range,
+            word.range,
+            self.children(function),
+        ))
+    }
+
+    /// Control flow blocks are named after their first line, e.g.
+    /// `if status is-interactive`.
+    fn block(&self, block: &Node) -> DocumentSymbol {
+        let text = block.text(self.tree.text());
+        let header = text.lines().next().unwrap_or_default().trim_end();
+        let header_range = TextRange::new(block.range.start, block.range.start + header.len());
+        self.symbol(
+            header.to_string(),
+            None,
+            SymbolKind::NAMESPACE,
+            block.range,
+            header_range,
+            self.children(block),
+        )
+    }
+
+    fn command(&self, command: &Node) -> Option<DocumentSymbol> {
+        let src = self.tree.text();
+
+        if let Some(set) = SetCommand::parse(command, src).filter(SetCommand::is_assignment) {
+            let binding = self.scopes.bindings.iter().find(|binding| {
+                binding.kind == BindingKind::Set && binding.statement == command.range
+            })?;
+            if binding.scope != self.scopes.global_scope() {
+                return None;
+            }
+            let mut detail = match set.scope() {
+                Some(Scope::Universal) => "universal".to_string(),
+                _ => "global".to_string(),
+            };
+            if set.has_flag('x', "export") {
+                detail.push_str(", exported");
+            }
+            let kind = if set.scope() == Some(Scope::Universal) {
+                SymbolKind::PROPERTY
+            } else {
+                SymbolKind::VARIABLE
+            };
+            return Some(self.symbol(
+                binding.name.clone(),
+                Some(detail),
+                kind,
+                command.range,
+                binding.name_range,
+                Vec::new(),
+            ));
+        }
+
+        if let Some((word, name, expansion)) = abbr_definition(command, src) {
+            return Some(self.symbol(
+                name,
+                expansion,
+                SymbolKind::CONSTANT,
+                command.range,
+                word.range,
+                Vec::new(),
+            ));
+        }
+
+        if let Some((word, name, body)) = alias_definition(command, src) {
+            return Some(self.symbol(
+                name,
+                Some(format!("alias for {body}")),
+                SymbolKind::FUNCTION,
+                command.range,
+                word.range,
+                Vec::new(),
+            ));
+        }
+
+        if let Some(complete) = CompleteCommand::parse(command, src) {
+            let (word, name) = complete.command;
+            let detail = match (complete.options.is_empty(), complete.description) {
+                (false, Some(description)) => {
+                    Some(format!("{}: {description}", complete.options.join(" ")))
+                }
+                (false, None) => Some(complete.options.join(" ")),
+                (true, description) => description,
+            };
+            return Some(self.symbol(
+                format!("complete {name}"),
+                detail,
+                SymbolKind::KEY,
+                command.range,
+                word.range,
+                Vec::new(),
+            ));
+        }
+
+        None
+    }
+
+    #[allow(deprecated)]
+    fn symbol(
+        &self,
+        name: String,
+        detail: Option<String>,
+        kind: SymbolKind,
+        range: TextRange,
+        selection_range: TextRange,
+        children: Vec<DocumentSymbol>,
+    ) -> DocumentSymbol {
+        // Editors reject empty names.
+        let name = if name.trim().is_empty() {
+            "<unnamed>".to_string()
+        } else {
+            name
+        };
+        DocumentSymbol {
+            name,
+            detail,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: self.tree.lsp_range(range),
+            selection_range: self.tree.lsp_range(selection_range),
+            children: (!children.is_empty()).then_some(children),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    /// One line per symbol, indented by depth: name, kind and detail.
+    fn render(symbols: &[DocumentSymbol], depth: usize, out: &mut Vec<String>) {
+        for symbol in symbols {
+            let detail = symbol.detail.as_deref().unwrap_or("-");
+            out.push(format!(
+                "{}{} {:?} {detail}",
+                "  ".repeat(depth),
+                symbol.name,
+                symbol.kind
+            ));
+            render(
+                symbol.children.as_deref().unwrap_or_default(),
+                depth + 1,
+                out,
+            );
+        }
+    }
+
+    #[test]
+    fn outline() {
+        let text = "\
+set -gx EDITOR vim
+set -U fish_greeting ''
+set -l file_local 1
+abbr -a gco git checkout
+alias ll 'ls -l'
+complete -c mytool -l verbose -d 'Be verbose'
+function greet --description 'Greet someone'
+    if test -n \"$argv\"
+        set -l name $argv[1]
+        set -g greeted yes
+    end
+end
+function on_exit --on-event fish_exit
+end
+";
+        let tree = parse(text);
+        let symbols = document_symbols(&tree);
+        let mut lines = Vec::new();
+        render(&symbols, 0, &mut lines);
+        assert_eq!(
+            lines,
+            [
+                "EDITOR Variable global, exported",
+                "fish_greeting Property universal",
+                "gco Constant git checkout",
+                "ll Function alias for 'ls -l'",
+                "complete mytool Key --verbose: Be verbose",
+                "greet Function Greet someone",
+                "  if test -n \"$argv\" Namespace -",
+                "    greeted Variable global",
+                "on_exit Event --on-event fish_exit",
+            ]
+        );
+
+        let greet = &symbols[5];
+        assert_eq!(
+            greet.range,
+            tree.lsp_range(tree.functions().next().unwrap().range)
+        );
+        assert_eq!(
+            greet.selection_range,
+            Range::new(Position::new(6, 9), Position::new(6, 14))
+        );
+    }
+}
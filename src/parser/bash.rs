@@ -0,0 +1,827 @@
+//! A recognizer for the common subset of POSIX shell and bash, enough to
+//! translate snippets to fish.
+//!
+//! Unlike the fish parser this is not lossless: [`parse`] keeps the raw text
+//! of each word and the byte span of each statement, and turns anything it
+//! does not understand into an [`ItemKind::Unsupported`] statement naming the
+//! construct, so the rest of the input can still be used.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A word as written, quotes and expansions included.
+    Word(String),
+    /// `;`, `;;`, `&`, `&&`, `|`, `|&`, `||`, `(` or `)`.
+    Op(&'static str),
+    /// A redirection operator, with any file descriptor before it.
+    Redirect(String),
+    /// The bodies of the here documents started on a line, from the newline
+    /// ending that line. The line's [`Token::Newline`] comes after it.
+    Heredoc,
+    Newline,
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub kind: ItemKind,
+    /// Bytes of the input this statement was read from.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemKind {
+    List(AndOr),
+    Comment(String),
+    /// An empty line between statements.
+    Blank,
+    /// A statement using a construct the recognizer does not handle.
+    Unsupported(&'static str),
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(&'static str, Pipeline)>,
+    pub background: bool,
+    /// A comment after the statement on the same line.
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    pub negated: bool,
+    /// Commands with the operator joining each to the next, `|` or `|&`.
+    pub commands: Vec<(Command, Option<&'static str>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple {
+        /// `NAME=value` words before the command name.
+        assignments: Vec<String>,
+        words: Vec<String>,
+        /// Redirection operators and their targets.
+        redirects: Vec<(String, String)>,
+    },
+    /// `[[ ... ]]`, with the words between the brackets.
+    Test(Vec<String>),
+    If {
+        /// Each condition with the body it guards, `if` first and then every
+        /// `elif`.
+        branches: Vec<(Vec<Item>, Vec<Item>)>,
+        otherwise: Option<Vec<Item>>,
+    },
+    For {
+        variable: String,
+        /// `None` when there is no `in`, looping over the arguments.
+        items: Option<Vec<String>>,
+        body: Vec<Item>,
+    },
+    While {
+        until: bool,
+        condition: Vec<Item>,
+        body: Vec<Item>,
+    },
+    Case {
+        word: String,
+        arms: Vec<(Vec<String>, Vec<Item>)>,
+    },
+    Function {
+        name: String,
+        body: Vec<Item>,
+    },
+    /// `{ ...; }`
+    Group(Vec<Item>),
+    /// `( ... )`
+    Subshell(Vec<Item>),
+    /// A compound command with redirections after it, like
+    /// `while ...; done < file`.
+    Redirected {
+        command: Box<Command>,
+        redirects: Vec<(String, String)>,
+    },
+}
+
+/// Read `src` as a sequence of statements.
+pub fn parse(src: &str) -> Vec<Item> {
+    let tokens = Lexer::new(src).tokens();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: 0,
+    };
+    let mut items = Vec::new();
+    loop {
+        let (mut parsed, closer) = parser.items(&[]);
+        items.append(&mut parsed);
+        if closer.is_none() {
+            break;
+        }
+        // A closer nothing opened, like a stray `fi` or `)`.
+        let start = parser.span_start();
+        parser.skip_line();
+        items.push(Item {
+            kind: ItemKind::Unsupported("unmatched keyword"),
+            span: start..parser.end,
+        });
+    }
+    items
+}
+
+/// The words of `src`, such as the elements of an array `(a b c)`, ignoring
+/// operators and comments.
+pub fn words(src: &str) -> Vec<String> {
+    Lexer::new(src)
+        .tokens()
+        .into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Word(word) => Some(word),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The end of the quoted, bracketed or substituted text starting at the
+/// beginning of `text`, skipping nested quotes and substitutions.
+pub fn matching(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let close = match bytes.first() {
+        Some(b'\'') => return text[1..].find('\'').map_or(text.len(), |end| end + 2),
+        Some(b'`') => return closing_backtick(text),
+        Some(b'"') => b'"',
+        Some(b'(') => b')',
+        Some(b'{') => b'}',
+        _ => return text.len().min(1),
+    };
+    let quoted = close == b'"';
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            c if c == close => return i + 1,
+            b'$' if matches!(bytes.get(i + 1), Some(b'(' | b'{')) => {
+                i += 1 + matching(&text[i + 1..]);
+            }
+            b'`' => i += closing_backtick(&text[i..]),
+            b'"' | b'\'' if !quoted => i += matching(&text[i..]),
+            c if c == bytes[0] && !quoted => i += matching(&text[i..]),
+            _ => i += 1,
+        }
+    }
+    text.len()
+}
+
+fn closing_backtick(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'`' => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    text.len()
+}
+
+pub fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `word` is an assignment, `NAME=value`, `NAME+=value` or
+/// `NAME[index]=value`.
+pub fn is_assignment(word: &str) -> bool {
+    let Some((target, _)) = word.split_once('=') else {
+        return false;
+    };
+    let target = target.strip_suffix('+').unwrap_or(target);
+    let name = match target.split_once('[') {
+        Some((name, index)) if index.ends_with(']') => name,
+        Some(_) => return false,
+        None => target,
+    };
+    is_name(name)
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    tokens: Vec<(Token, Range<usize>)>,
+    /// Delimiters of here documents whose bodies start at the next newline,
+    /// and whether their lines may be indented with tabs.
+    heredocs: Vec<(String, bool)>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            tokens: Vec::new(),
+            heredocs: Vec::new(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn push(&mut self, token: Token, start: usize) {
+        self.tokens.push((token, start..self.pos));
+    }
+
+    fn tokens(mut self) -> Vec<(Token, Range<usize>)> {
+        while self.pos < self.src.len() {
+            let rest = self.rest();
+            let start = self.pos;
+            if rest.starts_with("\\\n") {
+                self.pos += 2;
+            } else if rest.starts_with([' ', '\t', '\r']) {
+                self.pos += 1;
+            } else if rest.starts_with('\n') {
+                self.pos += 1;
+                if !self.heredocs.is_empty() {
+                    self.heredoc_bodies();
+                    self.push(Token::Heredoc, start);
+                }
+                self.tokens.push((Token::Newline, self.pos..self.pos));
+            } else if rest.starts_with('#') {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                self.pos += end;
+                self.push(Token::Comment(rest[..end].to_string()), start);
+            } else if let Some(op) = self.operator() {
+                self.pos += op.len();
+                let token = match op {
+                    ";;" => Token::Op(";;"),
+                    ";" => Token::Op(";"),
+                    "&&" => Token::Op("&&"),
+                    "&" => Token::Op("&"),
+                    "||" => Token::Op("||"),
+                    "|&" => Token::Op("|&"),
+                    "|" => Token::Op("|"),
+                    "(" => Token::Op("("),
+                    ")" => Token::Op(")"),
+                    redirect => Token::Redirect(redirect.to_string()),
+                };
+                let heredoc = matches!(&token, Token::Redirect(op) if op.ends_with("<<") || op.ends_with("<<-"));
+                self.push(token, start);
+                if heredoc {
+                    self.heredoc_start();
+                }
+            } else {
+                self.word();
+            }
+        }
+        self.tokens
+    }
+
+    /// The operator at the current position, if any. A redirection may start
+    /// with the file descriptor it applies to, as in `2>&1`.
+    fn operator(&self) -> Option<&'a str> {
+        let rest = self.rest();
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        const REDIRECTS: &[&str] = &[
+            "<<<", "<<-", "<<", "<&", "<>", "<", ">>", ">&", ">|", ">", "&>>", "&>",
+        ];
+        if let Some(op) = REDIRECTS
+            .iter()
+            .find(|op| rest[digits..].starts_with(**op) && (digits == 0 || !op.starts_with('&')))
+        {
+            return Some(&rest[..digits + op.len()]);
+        }
+        if digits > 0 {
+            return None;
+        }
+        [";;", ";", "&&", "&", "||", "|&", "|", "(", ")"]
+            .into_iter()
+            .find(|op| rest.starts_with(op))
+            .map(|op| &rest[..op.len()])
+    }
+
+    fn word(&mut self) {
+        let start = self.pos;
+        let bytes = self.src.as_bytes();
+        while self.pos < bytes.len() {
+            match bytes[self.pos] {
+                b' ' | b'\t' | b'\r' | b'\n' | b';' | b'&' | b'|' | b'<' | b'>' | b')' => break,
+                // An array assignment, `NAME=(a b)`.
+                b'(' if self.pos > start && bytes[self.pos - 1] == b'=' => {
+                    self.pos += matching(self.rest());
+                }
+                b'(' => break,
+                b'\\' => {
+                    self.pos += 1;
+                    self.pos += self.rest().chars().next().map_or(0, char::len_utf8);
+                }
+                b'\'' | b'"' | b'`' => self.pos += matching(self.rest()),
+                b'$' if matches!(bytes.get(self.pos + 1), Some(b'(' | b'{')) => {
+                    self.pos += 1;
+                    self.pos += matching(self.rest());
+                }
+                _ => self.pos += self.rest().chars().next().map_or(1, char::len_utf8),
+            }
+        }
+        let text = self.src[start..self.pos].to_string();
+        self.push(Token::Word(text), start);
+    }
+
+    /// Note the delimiter of the here document just started.
+    fn heredoc_start(&mut self) {
+        let strip_tabs =
+            matches!(self.tokens.last(), Some((Token::Redirect(op), _)) if op.ends_with('-'));
+        let rest = self.rest();
+        let skipped = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        self.pos += skipped;
+        if self.pos >= self.src.len() || self.rest().starts_with('\n') {
+            return;
+        }
+        self.word();
+        if let Some((Token::Word(delimiter), _)) = self.tokens.last() {
+            let delimiter = delimiter.replace(['\'', '"', '\\'], "");
+            self.heredocs.push((delimiter, strip_tabs));
+        }
+    }
+
+    /// Skip the bodies of the here documents started on the line just ended.
+    fn heredoc_bodies(&mut self) {
+        for (delimiter, strip_tabs) in std::mem::take(&mut self.heredocs) {
+            while self.pos < self.src.len() {
+                let rest = self.rest();
+                let end = rest.find('\n').map_or(rest.len(), |end| end + 1);
+                let line = rest[..end].trim_end_matches(['\n', '\r']);
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                self.pos += end;
+                if line == delimiter {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    /// The end of the last token consumed.
+    end: usize,
+}
+
+type Parsed<T> = Result<T, &'static str>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn at_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(found)) if *found == op)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let (token, range) = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        self.end = range.end;
+        Some(token)
+    }
+
+    fn span_start(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, range)| range.start)
+    }
+
+    fn expect_word(&mut self, word: &str, what: &'static str) -> Parsed<()> {
+        if self.peek_word() == Some(word) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(what)
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline | Token::Comment(_))) {
+            self.bump();
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline | Token::Op(";"))) {
+            self.bump();
+        }
+    }
+
+    /// Skip to the end of the line, past anything nested in it.
+    fn skip_line(&mut self) {
+        while let Some(token) = self.peek() {
+            if *token == Token::Newline {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    /// Statements up to one of the words or operators in `closers`, which is
+    /// returned but not consumed, or to the end of the input.
+    fn items(&mut self, closers: &[&str]) -> (Vec<Item>, Option<String>) {
+        let mut items = Vec::new();
+        let mut newlines = 0;
+        loop {
+            let start = self.span_start();
+            let closer = match self.peek() {
+                None => return (items, None),
+                Some(Token::Word(word)) => closers.contains(&word.as_str()).then(|| word.clone()),
+                Some(Token::Op(op @ (")" | ";;"))) => Some(op.to_string()),
+                _ => None,
+            };
+            if let Some(closer) = closer {
+                return (items, Some(closer));
+            }
+            match self.peek() {
+                Some(Token::Newline) => {
+                    self.bump();
+                    newlines += 1;
+                    if newlines == 2 && !items.is_empty() {
+                        items.push(Item {
+                            kind: ItemKind::Blank,
+                            span: start..start,
+                        });
+                    }
+                    continue;
+                }
+                Some(Token::Op(";")) => {
+                    self.bump();
+                }
+                Some(Token::Comment(comment)) => {
+                    let comment = comment.clone();
+                    self.bump();
+                    items.push(Item {
+                        kind: ItemKind::Comment(comment),
+                        span: start..self.end,
+                    });
+                }
+                _ => {
+                    let kind = match self.and_or() {
+                        Ok(list) => ItemKind::List(list),
+                        Err(what) => {
+                            self.skip_line();
+                            ItemKind::Unsupported(what)
+                        }
+                    };
+                    items.push(Item {
+                        kind,
+                        span: start..self.end,
+                    });
+                }
+            }
+            newlines = 0;
+        }
+    }
+
+    /// Statements up to `closer`, which is consumed.
+    fn body(&mut self, closer: &str, what: &'static str) -> Parsed<Vec<Item>> {
+        let (items, found) = self.items(&[closer]);
+        if found.as_deref() != Some(closer) {
+            return Err(what);
+        }
+        self.bump();
+        Ok(items)
+    }
+
+    fn and_or(&mut self) -> Parsed<AndOr> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        let mut background = false;
+        loop {
+            match self.peek() {
+                Some(Token::Op(op @ ("&&" | "||"))) => {
+                    let op = *op;
+                    self.bump();
+                    self.skip_newlines();
+                    rest.push((op, self.pipeline()?));
+                }
+                Some(Token::Op("&")) => {
+                    self.bump();
+                    background = true;
+                    break;
+                }
+                _ => break,
+            }
+        }
+        let comment = match self.peek() {
+            Some(Token::Comment(comment)) => {
+                let comment = comment.clone();
+                self.bump();
+                Some(comment)
+            }
+            _ => None,
+        };
+        Ok(AndOr {
+            first,
+            rest,
+            background,
+            comment,
+        })
+    }
+
+    fn pipeline(&mut self) -> Parsed<Pipeline> {
+        let negated = self.peek_word() == Some("!");
+        if negated {
+            self.bump();
+        }
+        let mut commands = Vec::new();
+        loop {
+            let command = self.command()?;
+            match self.peek() {
+                Some(Token::Op(op @ ("|" | "|&"))) => {
+                    let op = *op;
+                    self.bump();
+                    self.skip_newlines();
+                    commands.push((command, Some(op)));
+                }
+                _ => {
+                    commands.push((command, None));
+                    break;
+                }
+            }
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn command(&mut self) -> Parsed<Command> {
+        let command = self.unredirected_command()?;
+        if matches!(command, Command::Simple { .. }) {
+            return Ok(command);
+        }
+        let mut redirects = Vec::new();
+        while let Some(Token::Redirect(_)) = self.peek() {
+            redirects.push(self.redirect()?);
+        }
+        Ok(match redirects.is_empty() {
+            true => command,
+            false => Command::Redirected {
+                command: Box::new(command),
+                redirects,
+            },
+        })
+    }
+
+    fn unredirected_command(&mut self) -> Parsed<Command> {
+        let word = match self.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            Some(Token::Op("(")) => {
+                self.bump();
+                if self.at_op("(") {
+                    return Err("arithmetic command `(( ))`");
+                }
+                let (items, closer) = self.items(&[]);
+                if closer.as_deref() != Some(")") {
+                    return Err("unclosed subshell");
+                }
+                self.bump();
+                return Ok(Command::Subshell(items));
+            }
+            Some(Token::Redirect(_)) => return self.simple_command(),
+            _ => return Err("unrecognized syntax"),
+        };
+        match word.as_str() {
+            "if" => self.if_command(),
+            "for" => self.for_command(),
+            "while" | "until" => {
+                self.bump();
+                let condition = self.body("do", "incomplete `while` loop")?;
+                let body = self.body("done", "incomplete `while` loop")?;
+                Ok(Command::While {
+                    until: word == "until",
+                    condition,
+                    body,
+                })
+            }
+            "case" => self.case_command(),
+            "function" => {
+                self.bump();
+                let Some(Token::Word(name)) = self.bump() else {
+                    return Err("function without a name");
+                };
+                if self.at_op("(") {
+                    self.bump();
+                    if !self.at_op(")") {
+                        return Err("unrecognized syntax");
+                    }
+                    self.bump();
+                }
+                self.function_body(name)
+            }
+            "{" => {
+                self.bump();
+                Ok(Command::Group(self.body("}", "unclosed `{`")?))
+            }
+            "[[" => {
+                self.bump();
+                let mut words = Vec::new();
+                loop {
+                    match self.bump() {
+                        Some(Token::Word(word)) if word == "]]" => break,
+                        Some(Token::Word(word)) => words.push(word),
+                        Some(Token::Op(op @ ("&&" | "||" | "(" | ")"))) => words.push(op.into()),
+                        Some(Token::Redirect(op)) if op == "<" || op == ">" => {
+                            return Err("string comparison in `[[ ]]`")
+                        }
+                        _ => return Err("unclosed `[[`"),
+                    }
+                }
+                Ok(Command::Test(words))
+            }
+            "select" => Err("`select` loop"),
+            "then" | "elif" | "else" | "fi" | "do" | "done" | "esac" | "in" | "}" => {
+                Err("unmatched keyword")
+            }
+            _ if matches!(self.tokens.get(self.pos + 1), Some((Token::Op("("), _))) => {
+                self.bump();
+                self.bump();
+                if !self.at_op(")") {
+                    return Err("unrecognized syntax");
+                }
+                self.bump();
+                self.function_body(word)
+            }
+            _ => self.simple_command(),
+        }
+    }
+
+    fn function_body(&mut self, name: String) -> Parsed<Command> {
+        self.skip_newlines();
+        let body = match self.command()? {
+            Command::Group(body) | Command::Subshell(body) => body,
+            _ => return Err("function body that is not a `{ }` group"),
+        };
+        Ok(Command::Function { name, body })
+    }
+
+    fn if_command(&mut self) -> Parsed<Command> {
+        const INCOMPLETE: &str = "incomplete `if` statement";
+        self.bump();
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.body("then", INCOMPLETE)?;
+            let (body, closer) = self.items(&["elif", "else", "fi"]);
+            branches.push((condition, body));
+            match closer.as_deref() {
+                Some("elif") => {
+                    self.bump();
+                }
+                Some("else") => {
+                    self.bump();
+                    let otherwise = self.body("fi", INCOMPLETE)?;
+                    return Ok(Command::If {
+                        branches,
+                        otherwise: Some(otherwise),
+                    });
+                }
+                Some("fi") => {
+                    self.bump();
+                    return Ok(Command::If {
+                        branches,
+                        otherwise: None,
+                    });
+                }
+                _ => return Err(INCOMPLETE),
+            }
+        }
+    }
+
+    fn for_command(&mut self) -> Parsed<Command> {
+        const INCOMPLETE: &str = "incomplete `for` loop";
+        self.bump();
+        if self.at_op("(") {
+            return Err("C-style `for (( ))` loop");
+        }
+        let Some(Token::Word(variable)) = self.bump() else {
+            return Err(INCOMPLETE);
+        };
+        self.skip_newlines();
+        let items = if self.peek_word() == Some("in") {
+            self.bump();
+            let mut items = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                items.push(word.clone());
+                self.bump();
+            }
+            Some(items)
+        } else {
+            None
+        };
+        self.skip_separators();
+        self.expect_word("do", INCOMPLETE)?;
+        let body = self.body("done", INCOMPLETE)?;
+        Ok(Command::For {
+            variable,
+            items,
+            body,
+        })
+    }
+
+    fn case_command(&mut self) -> Parsed<Command> {
+        const INCOMPLETE: &str = "incomplete `case` statement";
+        self.bump();
+        let Some(Token::Word(word)) = self.bump() else {
+            return Err(INCOMPLETE);
+        };
+        self.skip_newlines();
+        self.expect_word("in", INCOMPLETE)?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_word() == Some("esac") {
+                self.bump();
+                return Ok(Command::Case { word, arms });
+            }
+            if self.at_op("(") {
+                self.bump();
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let Some(Token::Word(pattern)) = self.bump() else {
+                    return Err(INCOMPLETE);
+                };
+                patterns.push(pattern);
+                match self.bump() {
+                    Some(Token::Op("|")) => continue,
+                    Some(Token::Op(")")) => break,
+                    _ => return Err(INCOMPLETE),
+                }
+            }
+            let (body, closer) = self.items(&["esac"]);
+            arms.push((patterns, body));
+            match closer.as_deref() {
+                Some(";;") => {
+                    self.bump();
+                }
+                Some("esac") => {}
+                _ => return Err(INCOMPLETE),
+            }
+        }
+    }
+
+    fn simple_command(&mut self) -> Parsed<Command> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    if words.is_empty() && is_assignment(word) {
+                        assignments.push(word.clone());
+                    } else {
+                        words.push(word.clone());
+                    }
+                    self.bump();
+                }
+                Some(Token::Redirect(_)) => redirects.push(self.redirect()?),
+                Some(Token::Op("(")) => return Err("unrecognized syntax"),
+                _ => break,
+            }
+        }
+        Ok(Command::Simple {
+            assignments,
+            words,
+            redirects,
+        })
+    }
+
+    /// A redirection operator and its target.
+    fn redirect(&mut self) -> Parsed<(String, String)> {
+        let Some(Token::Redirect(op)) = self.bump() else {
+            return Err("unrecognized syntax");
+        };
+        if op.ends_with("<<<") {
+            return Err("here string `<<<`");
+        }
+        if op.contains("<<") {
+            return Err("here document");
+        }
+        let Some(Token::Word(target)) = self.bump() else {
+            return Err("redirection without a target");
+        };
+        Ok((op, target))
+    }
+}
@@ -0,0 +1,1122 @@
+use super::{
+    syntax::{Element, Node, SyntaxKind, TextRange, Token},
+    ParseError, ParseErrorKind,
+};
+
+/// Where a job list is being parsed, which decides the keywords that close it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    TopLevel,
+    Substitution,
+    Block,
+    IfBody,
+    CaseBody,
+}
+
+impl Context {
+    fn closes_on(self, keyword: &str) -> bool {
+        match self {
+            Context::TopLevel | Context::Substitution => false,
+            Context::Block => keyword == "end",
+            Context::IfBody => keyword == "end" || keyword == "else",
+            Context::CaseBody => keyword == "end" || keyword == "case",
+        }
+    }
+}
+
+pub(super) struct Parser<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    stack: Vec<(SyntaxKind, usize, Vec<Element>)>,
+    errors: Vec<ParseError>,
+    substitution_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+            stack: Vec::new(),
+            errors: Vec::new(),
+            substitution_depth: 0,
+        }
+    }
+
+    pub(super) fn parse_source_file(mut self) -> (Node, Vec<ParseError>) {
+        self.start(SyntaxKind::SourceFile);
+        self.job_list(Context::TopLevel);
+        let root = self.finish_root();
+        (root, self.errors)
+    }
+
+    // ---- tree building -------------------------------------------------
+
+    fn start(&mut self, kind: SyntaxKind) {
+        self.stack.push((kind, self.pos, Vec::new()));
+    }
+
+    fn finish(&mut self) {
+        let node = self.pop_node();
+        self.stack
+            .last_mut()
+            .expect("finish() without a parent node")
+            .2
+            .push(Element::Node(node));
+    }
+
+    fn finish_root(&mut self) -> Node {
+        assert_eq!(self.stack.len(), 1, "unbalanced syntax tree builder");
+        self.pop_node()
+    }
+
+    fn pop_node(&mut self) -> Node {
+        let (kind, start, children) = self.stack.pop().expect("no open node");
+        let start = children.first().map_or(start, |c| c.range().start);
+        let end = children.last().map_or(start, |c| c.range().end);
+        Node {
+            kind,
+            range: TextRange::new(start, end),
+            children,
+        }
+    }
+
+    fn bump(&mut self, kind: SyntaxKind, len: usize) {
+        debug_assert!(len > 0 || kind == SyntaxKind::Error);
+        let range = TextRange::new(self.pos, self.pos + len);
+        self.pos += len;
+        self.stack
+            .last_mut()
+            .expect("token outside of a node")
+            .2
+            .push(Element::Token(Token { kind, range }));
+    }
+
+    fn error(&mut self, kind: ParseErrorKind, range: TextRange, message: impl Into<String>) {
+        self.errors.push(ParseError::new(kind, range, message));
+    }
+
+    // ---- character helpers -----------------------------------------------
+
+    fn at_eof(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<u8> {
+        self.bytes.get(self.pos + n).copied()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn char_len(&self) -> usize {
+        self.rest().chars().next().map_or(0, char::len_utf8)
+    }
+
+    fn newline_len(&self) -> usize {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'\n'), _) => 1,
+            (Some(b'\r'), Some(b'\n')) => 2,
+            _ => 0,
+        }
+    }
+
+    fn continuation_len_at(&self, at: usize) -> usize {
+        match (
+            self.bytes.get(at),
+            self.bytes.get(at + 1),
+            self.bytes.get(at + 2),
+        ) {
+            (Some(b'\\'), Some(b'\n'), _) => 2,
+            (Some(b'\\'), Some(b'\r'), Some(b'\n')) => 3,
+            _ => 0,
+        }
+    }
+
+    /// Byte length of the whitespace and line continuations at `at`.
+    fn blank_len_at(&self, at: usize) -> usize {
+        let mut i = at;
+        loop {
+            match self.bytes.get(i) {
+                Some(b' ' | b'\t') => i += 1,
+                Some(b'\r') if self.bytes.get(i + 1) != Some(&b'\n') => i += 1,
+                Some(b'\\') if self.continuation_len_at(i) > 0 => i += self.continuation_len_at(i),
+                _ => return i - at,
+            }
+        }
+    }
+
+    /// Consume spaces, tabs and line continuations.
+    fn blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t') => {
+                    let len = self.bytes[self.pos..]
+                        .iter()
+                        .take_while(|b| matches!(b, b' ' | b'\t'))
+                        .count();
+                    self.bump(SyntaxKind::Whitespace, len);
+                }
+                Some(b'\r') if self.peek_at(1) != Some(b'\n') => {
+                    self.bump(SyntaxKind::Whitespace, 1)
+                }
+                Some(b'\\') if self.continuation_len_at(self.pos) > 0 => {
+                    let len = self.continuation_len_at(self.pos);
+                    self.bump(SyntaxKind::LineContinuation, len);
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Consume blanks, comments and newlines, as allowed after `|`, `&&` and `||`.
+    fn blanks_and_newlines(&mut self) {
+        loop {
+            self.blanks();
+            if self.peek() == Some(b'#') {
+                self.comment();
+            } else if self.newline_len() > 0 {
+                let len = self.newline_len();
+                self.bump(SyntaxKind::Newline, len);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn comment(&mut self) {
+        let len = self.rest().find('\n').unwrap_or(self.rest().len());
+        let len = if self.rest()[..len].ends_with('\r') {
+            len - 1
+        } else {
+            len
+        };
+        self.bump(SyntaxKind::Comment, len);
+    }
+
+    /// Whether the byte at `at` ends an unquoted word.
+    fn is_word_end(&self, at: usize) -> bool {
+        match self.bytes.get(at) {
+            None => true,
+            Some(b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'<' | b'>' | b')') => true,
+            Some(b'\r') => true,
+            Some(b'\\') => self.continuation_len_at(at) > 0,
+            _ => false,
+        }
+    }
+
+    /// The bare word starting at `at`, if it consists only of plain characters.
+    fn bare_word_at(&self, at: usize) -> Option<&'a str> {
+        let mut end = at;
+        while end < self.bytes.len() && !self.is_word_end(end) {
+            match self.bytes[end] {
+                b'\'' | b'"' | b'$' | b'(' | b'\\' | b'{' | b'}' | b'[' | b'*' | b'?' | b'~' => {
+                    return None
+                }
+                _ => end += 1,
+            }
+        }
+        (end > at).then(|| &self.src[at..end])
+    }
+
+    fn bare_word(&self) -> Option<&'a str> {
+        self.bare_word_at(self.pos)
+    }
+
+    /// The bare word after the current one and any blanks that follow it.
+    fn next_bare_word(&self) -> Option<&'a str> {
+        let current = self.bare_word()?;
+        let at = self.pos + current.len();
+        let at = at + self.blank_len_at(at);
+        self.bare_word_at(at)
+    }
+
+    /// Length of a redirection operator at `at`, e.g. `>`, `2>>`, `2>&1`, `&>`.
+    fn redirect_len_at(&self, at: usize) -> usize {
+        let bytes = self.bytes;
+        let mut i = at;
+        if bytes.get(i) == Some(&b'&') {
+            if bytes.get(i + 1) != Some(&b'>') {
+                return 0;
+            }
+            i += 2;
+            if bytes.get(i) == Some(&b'>') {
+                i += 1;
+            }
+            return i - at;
+        }
+
+        while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+            i += 1;
+        }
+        match bytes.get(i) {
+            Some(b'<') => i += 1,
+            Some(b'>') => {
+                i += 1;
+                if bytes.get(i) == Some(&b'>') {
+                    i += 1;
+                } else if bytes.get(i) == Some(&b'|') {
+                    // `2>|` is a pipe of stderr, not a redirection.
+                    return 0;
+                }
+            }
+            _ => return 0,
+        }
+        if bytes.get(i) == Some(&b'?') {
+            i += 1;
+        }
+        if bytes.get(i) == Some(&b'&') {
+            let mut j = i + 1;
+            if bytes.get(j) == Some(&b'-') {
+                j += 1;
+            } else {
+                while bytes.get(j).is_some_and(u8::is_ascii_digit) {
+                    j += 1;
+                }
+            }
+            if j > i + 1 {
+                i = j;
+            }
+        }
+        i - at
+    }
+
+    /// Length of a pipe operator at the cursor: `|`, `&|` or `N>|`.
+    fn pipe_len(&self) -> usize {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'|'), Some(b'|')) => 0,
+            (Some(b'|'), _) => 1,
+            (Some(b'&'), Some(b'|')) => 2,
+            (Some(b'0'..=b'9'), _) => {
+                let digits = self.bytes[self.pos..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count();
+                if self.peek_at(digits) == Some(b'>') && self.peek_at(digits + 1) == Some(b'|') {
+                    digits + 2
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn at_statement_end(&self) -> bool {
+        match self.peek() {
+            None | Some(b'\n' | b';' | b')' | b'#') => true,
+            Some(b'\r') => self.newline_len() > 0,
+            Some(b'|') => true,
+            Some(b'&') => self.redirect_len_at(self.pos) == 0,
+            _ => self.pipe_len() > 0,
+        }
+    }
+
+    // ---- job lists and jobs ---------------------------------------------
+
+    fn job_list(&mut self, context: Context) {
+        loop {
+            self.blanks();
+            match self.peek() {
+                None => return,
+                Some(b'#') => {
+                    self.comment();
+                    continue;
+                }
+                Some(b';') => {
+                    self.bump(SyntaxKind::Semicolon, 1);
+                    continue;
+                }
+                Some(b')') => {
+                    if context == Context::Substitution || self.substitution_depth > 0 {
+                        return;
+                    }
+                    self.error(
+                        ParseErrorKind::UnexpectedToken,
+                        TextRange::new(self.pos, self.pos + 1),
+                        "Unexpected ')' found, expecting a command",
+                    );
+                    self.bump(SyntaxKind::Error, 1);
+                    continue;
+                }
+                _ if self.newline_len() > 0 => {
+                    let len = self.newline_len();
+                    self.bump(SyntaxKind::Newline, len);
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(word) = self.bare_word() {
+                if context.closes_on(word) {
+                    return;
+                }
+                if matches!(word, "end" | "else" | "case") {
+                    self.stray_keyword(word);
+                    continue;
+                }
+            }
+
+            let before = self.pos;
+            self.job();
+            if self.pos == before {
+                let len = self.char_len().max(1);
+                self.error(
+                    ParseErrorKind::UnexpectedToken,
+                    TextRange::new(self.pos, self.pos + len),
+                    format!("Unexpected '{}'", &self.rest()[..len]),
+                );
+                self.bump(SyntaxKind::Error, len);
+            }
+        }
+    }
+
+    fn stray_keyword(&mut self, word: &str) {
+        let range = TextRange::new(self.pos, self.pos + word.len());
+        let (kind, message) = match word {
+            "end" => (ParseErrorKind::StrayEnd, "'end' outside of a block"),
+            "else" => (
+                ParseErrorKind::StrayElse,
+                "'else' builtin not inside of if block",
+            ),
+            _ => (
+                ParseErrorKind::StrayCase,
+                "'case' builtin not inside of switch block",
+            ),
+        };
+        self.error(kind, range, message);
+        self.start(SyntaxKind::ErrorNode);
+        self.bump(SyntaxKind::Keyword, word.len());
+        if word == "end" {
+            self.trailing_arguments();
+        } else {
+            self.arguments();
+        }
+        self.finish();
+    }
+
+    /// A job conjunction: pipelines joined by `&&`/`||`, optionally decorated
+    /// with a leading `and`/`or`.
+    fn job(&mut self) {
+        self.start(SyntaxKind::Job);
+        if matches!(self.bare_word(), Some("and" | "or")) && !self.next_is_option_or_end() {
+            let len = self.bare_word().unwrap().len();
+            self.bump(SyntaxKind::Keyword, len);
+            self.blanks();
+        }
+
+        if matches!(self.peek(), Some(b'&' | b'|')) && self.redirect_len_at(self.pos) == 0 {
+            let len = self.operator_len();
+            self.error(
+                ParseErrorKind::UnexpectedToken,
+                TextRange::new(self.pos, self.pos + len),
+                "Expected a command, but found an operator",
+            );
+            self.bump(SyntaxKind::Error, len);
+            self.blanks();
+        }
+
+        self.pipeline();
+        loop {
+            let blank = self.blank_len_at(self.pos);
+            let (kind, len) = match (self.peek_at(blank), self.peek_at(blank + 1)) {
+                (Some(b'&'), Some(b'&')) => (SyntaxKind::AndAnd, 2),
+                (Some(b'|'), Some(b'|')) => (SyntaxKind::OrOr, 2),
+                _ => break,
+            };
+            self.blanks();
+            let op_start = self.pos;
+            self.bump(kind, len);
+            self.blanks_and_newlines();
+            if self.at_statement_end() {
+                self.error(
+                    ParseErrorKind::MissingCommand,
+                    TextRange::new(op_start, op_start + len),
+                    "Expected a command after this operator",
+                );
+                break;
+            }
+            self.pipeline();
+        }
+        self.finish();
+    }
+
+    fn operator_len(&self) -> usize {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'&'), Some(b'&' | b'|')) | (Some(b'|'), Some(b'|')) => 2,
+            _ => 1,
+        }
+    }
+
+    fn pipeline(&mut self) {
+        self.start(SyntaxKind::Pipeline);
+        if self.bare_word() == Some("time") && !self.next_is_option_or_end() {
+            self.bump(SyntaxKind::Keyword, 4);
+            self.blanks();
+        }
+        self.statement();
+        loop {
+            let blank = self.blank_len_at(self.pos);
+            let saved = self.pos;
+            self.pos += blank;
+            let pipe = self.pipe_len();
+            self.pos = saved;
+            if pipe == 0 {
+                break;
+            }
+            self.blanks();
+            let op_start = self.pos;
+            self.bump(SyntaxKind::Pipe, pipe);
+            self.blanks_and_newlines();
+            if self.at_statement_end() {
+                self.error(
+                    ParseErrorKind::MissingCommand,
+                    TextRange::new(op_start, op_start + pipe),
+                    "Expected a command after this pipe",
+                );
+                break;
+            }
+            self.statement();
+        }
+
+        let blank = self.blank_len_at(self.pos);
+        if self.peek_at(blank) == Some(b'&')
+            && !matches!(self.peek_at(blank + 1), Some(b'&' | b'|' | b'>'))
+        {
+            self.blanks();
+            self.bump(SyntaxKind::Ampersand, 1);
+        }
+        self.finish();
+    }
+
+    /// Whether the word after the current bare word is an option or nothing,
+    /// meaning the current word is used as a plain command (`time --help`).
+    fn next_is_option_or_end(&self) -> bool {
+        let Some(word) = self.bare_word() else {
+            return false;
+        };
+        let at = self.pos + word.len();
+        let at = at + self.blank_len_at(at);
+        matches!(
+            self.bytes.get(at),
+            None | Some(b'\n' | b';' | b')' | b'|' | b'&' | b'#' | b'-')
+        )
+    }
+
+    // ---- statements ------------------------------------------------------
+
+    fn statement(&mut self) {
+        if self.at_statement_end() {
+            return;
+        }
+        let keyword = self.bare_word().unwrap_or("");
+        match keyword {
+            "function" => self.function_def(),
+            "if" if !self.next_is_option_or_end() => self.if_statement(),
+            "while" if !self.next_is_option_or_end() => self.while_loop(),
+            "for" => self.for_loop(),
+            "switch" => self.switch_statement(),
+            "begin" if !self.next_is_help() => self.begin_block(),
+            "not" | "!" if !self.next_is_option_or_end() => {
+                self.start(SyntaxKind::NotStatement);
+                self.bump(SyntaxKind::Keyword, keyword.len());
+                self.blanks();
+                if self.bare_word() == Some("time") && !self.next_is_option_or_end() {
+                    self.bump(SyntaxKind::Keyword, 4);
+                    self.blanks();
+                }
+                self.statement();
+                self.finish();
+            }
+            _ => self.command(),
+        }
+    }
+
+    fn next_is_help(&self) -> bool {
+        matches!(self.next_bare_word(), Some("-h" | "--help"))
+    }
+
+    fn command(&mut self) {
+        self.start(SyntaxKind::Command);
+        if matches!(self.bare_word(), Some("command" | "builtin" | "exec"))
+            && !self.next_is_option_or_end()
+        {
+            let len = self.bare_word().unwrap().len();
+            self.bump(SyntaxKind::Keyword, len);
+            self.blanks();
+        }
+        if self.redirect_len_at(self.pos) == 0 {
+            self.word();
+        }
+        self.arguments();
+        self.finish();
+    }
+
+    /// Arguments and redirections up to the end of the statement.
+    fn arguments(&mut self) {
+        loop {
+            let blank = self.blank_len_at(self.pos);
+            let saved = self.pos;
+            self.pos += blank;
+            let redirect = self.redirect_len_at(self.pos);
+            let done = redirect == 0 && self.at_statement_end();
+            self.pos = saved;
+            if done {
+                return;
+            }
+            self.blanks();
+            if redirect > 0 {
+                self.redirect(redirect);
+            } else {
+                self.word();
+            }
+        }
+    }
+
+    /// Redirections, and erroneous arguments, that follow a keyword like `end`.
+    fn trailing_arguments(&mut self) {
+        loop {
+            let blank = self.blank_len_at(self.pos);
+            let saved = self.pos;
+            self.pos += blank;
+            let redirect = self.redirect_len_at(self.pos);
+            let done = redirect == 0 && self.at_statement_end();
+            self.pos = saved;
+            if done {
+                return;
+            }
+            self.blanks();
+            if redirect > 0 {
+                self.redirect(redirect);
+            } else {
+                let start = self.pos;
+                self.word();
+                self.error(
+                    ParseErrorKind::UnexpectedToken,
+                    TextRange::new(start, self.pos),
+                    "Unexpected argument after 'end'",
+                );
+            }
+        }
+    }
+
+    /// Words up to the end of a block header such as `function foo -a x`.
+    fn header_words(&mut self) {
+        loop {
+            let blank = self.blank_len_at(self.pos);
+            let saved = self.pos;
+            self.pos += blank;
+            let done = self.at_statement_end() && self.redirect_len_at(self.pos) == 0;
+            self.pos = saved;
+            if done {
+                return;
+            }
+            self.blanks();
+            if self.redirect_len_at(self.pos) > 0 {
+                let len = self.redirect_len_at(self.pos);
+                self.redirect(len);
+            } else {
+                self.word();
+            }
+        }
+    }
+
+    fn redirect(&mut self, len: usize) {
+        self.start(SyntaxKind::Redirect);
+        let op_start = self.pos;
+        let op = &self.src[self.pos..self.pos + len];
+        self.bump(SyntaxKind::RedirectOp, len);
+        if !op.contains('&') || op.starts_with('&') {
+            self.blanks();
+            if self.at_statement_end() || self.redirect_len_at(self.pos) > 0 {
+                self.error(
+                    ParseErrorKind::MissingArgument,
+                    TextRange::new(op_start, op_start + len),
+                    "Expected a file name after this redirection",
+                );
+            } else {
+                self.word();
+            }
+        }
+        self.finish();
+    }
+
+    fn block_body(&mut self, context: Context) {
+        self.start(SyntaxKind::JobList);
+        self.job_list(context);
+        self.finish();
+    }
+
+    /// Expect the `end` of a block opened by the keyword at `opener`.
+    fn block_end(&mut self, opener: TextRange, what: &str) {
+        if self.bare_word() == Some("end") {
+            self.bump(SyntaxKind::Keyword, 3);
+            self.trailing_arguments();
+        } else {
+            self.error(
+                ParseErrorKind::UnclosedBlock,
+                opener,
+                format!("Missing end to balance this {what}"),
+            );
+        }
+    }
+
+    fn keyword(&mut self, len: usize) -> TextRange {
+        let range = TextRange::new(self.pos, self.pos + len);
+        self.bump(SyntaxKind::Keyword, len);
+        range
+    }
+
+    fn function_def(&mut self) {
+        self.start(SyntaxKind::FunctionDef);
+        let opener = self.keyword("function".len());
+        self.blanks();
+        if self.at_statement_end() {
+            self.error(
+                ParseErrorKind::MissingArgument,
+                opener,
+                "Expected a function name after 'function'",
+            );
+        }
+        self.header_words();
+        self.block_body(Context::Block);
+        self.block_end(opener, "function definition");
+        self.finish();
+    }
+
+    fn begin_block(&mut self) {
+        self.start(SyntaxKind::BeginBlock);
+        let opener = self.keyword("begin".len());
+        self.block_body(Context::Block);
+        self.block_end(opener, "begin");
+        self.finish();
+    }
+
+    fn while_loop(&mut self) {
+        self.start(SyntaxKind::WhileLoop);
+        let opener = self.keyword("while".len());
+        self.blanks();
+        self.condition(opener, "while");
+        self.block_body(Context::Block);
+        self.block_end(opener, "while loop");
+        self.finish();
+    }
+
+    fn condition(&mut self, opener: TextRange, keyword: &str) {
+        if self.at_statement_end() {
+            self.error(
+                ParseErrorKind::MissingCommand,
+                opener,
+                format!("Expected a condition after '{keyword}'"),
+            );
+        } else {
+            self.job();
+        }
+    }
+
+    fn for_loop(&mut self) {
+        self.start(SyntaxKind::ForLoop);
+        let opener = self.keyword("for".len());
+        self.blanks();
+        if self.at_statement_end() {
+            self.error(
+                ParseErrorKind::MissingArgument,
+                opener,
+                "Expected a variable name after 'for'",
+            );
+        } else {
+            self.word();
+            self.blanks();
+            if self.bare_word() == Some("in") {
+                self.bump(SyntaxKind::Keyword, 2);
+            } else {
+                self.error(
+                    ParseErrorKind::MissingArgument,
+                    opener,
+                    "Expected 'in' after the loop variable",
+                );
+            }
+            self.header_words();
+        }
+        self.block_body(Context::Block);
+        self.block_end(opener, "for loop");
+        self.finish();
+    }
+
+    fn if_statement(&mut self) {
+        self.start(SyntaxKind::IfStatement);
+        let opener = TextRange::new(self.pos, self.pos + 2);
+
+        self.start(SyntaxKind::IfClause);
+        self.keyword(2);
+        self.blanks();
+        self.condition(opener, "if");
+        self.block_body(Context::IfBody);
+        self.finish();
+
+        let mut seen_else = false;
+        while self.bare_word() == Some("else") {
+            let else_range = TextRange::new(self.pos, self.pos + 4);
+            let is_else_if = self.next_bare_word() == Some("if");
+            self.start(if is_else_if {
+                SyntaxKind::ElseIfClause
+            } else {
+                SyntaxKind::ElseClause
+            });
+            if seen_else {
+                self.error(
+                    ParseErrorKind::StrayElse,
+                    else_range,
+                    "'else' after the final 'else' of this if block",
+                );
+            }
+            self.keyword(4);
+            self.blanks();
+            if is_else_if {
+                let if_range = self.keyword(2);
+                self.blanks();
+                self.condition(if_range, "else if");
+            } else {
+                seen_else = true;
+            }
+            self.block_body(Context::IfBody);
+            self.finish();
+        }
+
+        self.block_end(opener, "if statement");
+        self.finish();
+    }
+
+    fn switch_statement(&mut self) {
+        self.start(SyntaxKind::SwitchStatement);
+        let opener = self.keyword("switch".len());
+        self.blanks();
+        if self.at_statement_end() {
+            self.error(
+                ParseErrorKind::MissingArgument,
+                opener,
+                "Expected a value after 'switch'",
+            );
+        }
+        self.header_words();
+
+        loop {
+            self.blanks_and_newlines();
+            if self.peek() == Some(b';') {
+                self.bump(SyntaxKind::Semicolon, 1);
+                continue;
+            }
+            match self.bare_word() {
+                Some("end") => break,
+                Some("case") => {
+                    self.start(SyntaxKind::CaseClause);
+                    self.keyword(4);
+                    self.header_words();
+                    self.block_body(Context::CaseBody);
+                    self.finish();
+                }
+                _ if self.at_eof() => break,
+                _ if self.peek() == Some(b')') && self.substitution_depth > 0 => break,
+                _ => {
+                    let start = self.pos;
+                    self.start(SyntaxKind::ErrorNode);
+                    self.job();
+                    if self.pos == start {
+                        let len = self.char_len().max(1);
+                        self.bump(SyntaxKind::Error, len);
+                    }
+                    self.finish();
+                    self.error(
+                        ParseErrorKind::UnexpectedToken,
+                        TextRange::new(start, self.pos),
+                        "Expected a 'case' inside this switch",
+                    );
+                }
+            }
+        }
+
+        self.block_end(opener, "switch statement");
+        self.finish();
+    }
+
+    // ---- words -------------------------------------------------------------
+
+    fn word(&mut self) {
+        self.start(SyntaxKind::Word);
+        let start = self.pos;
+        let mut brace_depth = 0usize;
+        let mut brace_open = Vec::new();
+
+        while let Some(byte) = self.peek() {
+            match byte {
+                b' ' | b'\t' if brace_depth > 0 => self.text_run(true),
+                b'\\' => self.escape(),
+                _ if self.is_word_end(self.pos) => break,
+                b'\'' => self.single_quoted(),
+                b'"' => self.double_quoted(),
+                b'$' => self.dollar(false),
+                b'(' => self.substitution(),
+                b'{' => {
+                    brace_open.push(self.pos);
+                    brace_depth += 1;
+                    self.bump(SyntaxKind::LBrace, 1);
+                }
+                b'}' if brace_depth > 0 => {
+                    brace_open.pop();
+                    brace_depth -= 1;
+                    self.bump(SyntaxKind::RBrace, 1);
+                }
+                b',' if brace_depth > 0 => self.bump(SyntaxKind::Comma, 1),
+                _ => self.text_run(brace_depth > 0),
+            }
+        }
+
+        for open in brace_open {
+            self.error(
+                ParseErrorKind::UnclosedBrace,
+                TextRange::new(open, open + 1),
+                "Unexpected end of string, incomplete brace expansion",
+            );
+        }
+        if self.pos == start {
+            let len = self.char_len().max(1);
+            self.error(
+                ParseErrorKind::UnexpectedToken,
+                TextRange::new(self.pos, self.pos + len),
+                format!("Unexpected '{}'", &self.rest()[..len]),
+            );
+            self.bump(SyntaxKind::Error, len);
+        }
+        self.finish();
+    }
+
+    fn text_run(&mut self, in_braces: bool) {
+        let start = self.pos;
+        let mut end = self.pos;
+        while end < self.bytes.len() {
+            let byte = self.bytes[end];
+            let special = matches!(byte, b'\\' | b'\'' | b'"' | b'$' | b'(' | b'{' | b'}')
+                || (in_braces && byte == b',');
+            let blank_in_braces = in_braces && matches!(byte, b' ' | b'\t');
+            if special || (!blank_in_braces && self.is_word_end(end)) {
+                break;
+            }
+            end += 1;
+        }
+        if end == start {
+            end += self.char_len().max(1);
+        }
+        self.bump(SyntaxKind::Text, end - start);
+    }
+
+    fn escape(&mut self) {
+        let continuation = self.continuation_len_at(self.pos);
+        if continuation > 0 {
+            self.bump(SyntaxKind::LineContinuation, continuation);
+            return;
+        }
+        let Some(next) = self.rest()[1..].chars().next() else {
+            self.bump(SyntaxKind::Escape, 1);
+            return;
+        };
+        let mut len = 1 + next.len_utf8();
+        let max_digits = match next {
+            'x' | 'X' => 2,
+            'u' => 4,
+            'U' => 8,
+            '0'..='7' => 2,
+            _ => 0,
+        };
+        let digits = self.bytes[self.pos + len..]
+            .iter()
+            .take(max_digits)
+            .take_while(|b| {
+                if matches!(next, '0'..='7') {
+                    (b'0'..=b'7').contains(b)
+                } else {
+                    b.is_ascii_hexdigit()
+                }
+            })
+            .count();
+        len += digits;
+        self.bump(SyntaxKind::Escape, len);
+    }
+
+    fn single_quoted(&mut self) {
+        let start = self.pos;
+        let mut i = self.pos + 1;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'\\' => i += 2,
+                b'\'' => {
+                    self.bump(SyntaxKind::SingleQuoted, i + 1 - start);
+                    return;
+                }
+                _ => i += 1,
+            }
+        }
+        self.error(
+            ParseErrorKind::UnclosedQuote,
+            TextRange::new(start, start + 1),
+            "Unexpected end of string, quotes are not balanced",
+        );
+        self.bump(SyntaxKind::SingleQuoted, self.bytes.len() - start);
+    }
+
+    fn double_quoted(&mut self) {
+        self.start(SyntaxKind::DoubleQuotedString);
+        let start = self.pos;
+        self.bump(SyntaxKind::DoubleQuote, 1);
+        loop {
+            match self.peek() {
+                None => {
+                    self.error(
+                        ParseErrorKind::UnclosedQuote,
+                        TextRange::new(start, start + 1),
+                        "Unexpected end of string, quotes are not balanced",
+                    );
+                    break;
+                }
+                Some(b'"') => {
+                    self.bump(SyntaxKind::DoubleQuote, 1);
+                    break;
+                }
+                Some(b'\\') => {
+                    let continuation = self.continuation_len_at(self.pos);
+                    if continuation > 0 {
+                        self.bump(SyntaxKind::LineContinuation, continuation);
+                    } else if matches!(self.peek_at(1), Some(b'"' | b'\\' | b'$')) {
+                        self.bump(SyntaxKind::Escape, 2);
+                    } else {
+                        self.bump(SyntaxKind::StringText, 1);
+                    }
+                }
+                Some(b'$') => self.dollar(true),
+                Some(_) => {
+                    let len = self.bytes[self.pos..]
+                        .iter()
+                        .take_while(|b| !matches!(b, b'"' | b'\\' | b'$'))
+                        .count();
+                    self.bump(SyntaxKind::StringText, len);
+                }
+            }
+        }
+        self.finish();
+    }
+
+    /// A `$` expansion: either a variable or a `$(...)` substitution. Inside
+    /// double quotes a `$` that starts no expansion is literal text.
+    fn dollar(&mut self, in_quotes: bool) {
+        let dollars = self.bytes[self.pos..]
+            .iter()
+            .take_while(|b| **b == b'$')
+            .count();
+        if dollars == 1 && self.peek_at(1) == Some(b'(') {
+            self.substitution();
+            return;
+        }
+        let name_at = self.pos + dollars;
+        let has_name = self
+            .bytes
+            .get(name_at)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_');
+        if in_quotes && !has_name {
+            self.bump(SyntaxKind::StringText, dollars);
+            return;
+        }
+
+        self.start(SyntaxKind::Variable);
+        let start = self.pos;
+        self.bump(SyntaxKind::Dollar, dollars);
+        let name_len = self.bytes[self.pos..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+            .count();
+        if name_len == 0 {
+            let shown = self.rest().chars().next().filter(|c| !c.is_whitespace());
+            let message = match shown {
+                Some(c) => format!("${c} is not a valid variable in fish"),
+                None => "Expected a variable name after this $".to_string(),
+            };
+            self.error(
+                ParseErrorKind::InvalidVariable,
+                TextRange::new(start, self.pos + shown.map_or(0, char::len_utf8)),
+                message,
+            );
+        } else {
+            self.bump(SyntaxKind::VariableName, name_len);
+            if self.peek() == Some(b'[') {
+                self.index();
+            }
+        }
+        self.finish();
+    }
+
+    fn index(&mut self) {
+        self.start(SyntaxKind::Index);
+        let start = self.pos;
+        self.bump(SyntaxKind::LBracket, 1);
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => {
+                    self.error(
+                        ParseErrorKind::UnclosedIndex,
+                        TextRange::new(start, start + 1),
+                        "Unexpected end of string, incomplete index",
+                    );
+                    break;
+                }
+                Some(b']') => {
+                    self.bump(SyntaxKind::RBracket, 1);
+                    break;
+                }
+                Some(b' ' | b'\t') => self.blanks(),
+                Some(b'$') => self.dollar(false),
+                Some(b'(') => self.substitution(),
+                Some(b'\'') => self.single_quoted(),
+                Some(b'"') => self.double_quoted(),
+                Some(_) => {
+                    let len = self.bytes[self.pos..]
+                        .iter()
+                        .take_while(|b| {
+                            !matches!(b, b']' | b'\n' | b' ' | b'\t' | b'$' | b'(' | b'\'' | b'"')
+                        })
+                        .count()
+                        .max(1);
+                    self.bump(SyntaxKind::Text, len);
+                }
+            }
+        }
+        self.finish();
+    }
+
+    fn substitution(&mut self) {
+        self.start(SyntaxKind::CommandSubstitution);
+        let start = self.pos;
+        if self.peek() == Some(b'$') {
+            self.bump(SyntaxKind::Dollar, 1);
+        }
+        self.bump(SyntaxKind::LParen, 1);
+        let opener = TextRange::new(start, self.pos);
+        self.substitution_depth += 1;
+        self.job_list(Context::Substitution);
+        self.substitution_depth -= 1;
+        if self.peek() == Some(b')') {
+            self.bump(SyntaxKind::RParen, 1);
+        } else {
+            self.error(
+                ParseErrorKind::UnclosedSubstitution,
+                opener,
+                "Unexpected end of string, parenthesis do not match",
+            );
+        }
+        self.finish();
+    }
+}
@@ -0,0 +1,117 @@
+use super::{
+    grammar::Parser,
+    syntax::{shift_range, Element, Node, SyntaxKind, TextRange},
+    ParseErrorKind, SyntaxTree,
+};
+
+/// Errors that mean a construct ran off the end of the reparsed region, so
+/// the region boundaries can no longer be trusted.
+fn escapes_region(kind: ParseErrorKind) -> bool {
+    matches!(
+        kind,
+        ParseErrorKind::UnclosedBlock
+            | ParseErrorKind::UnclosedQuote
+            | ParseErrorKind::UnclosedSubstitution
+    )
+}
+
+impl SyntaxTree {
+    /// Produce the tree for `new_text`, which is this tree's text with the
+    /// bytes in `edited` replaced by `inserted` new bytes.
+    ///
+    /// Only the top-level statements touched by the edit are parsed again,
+    /// widened to the surrounding newlines or semicolons. When the reparsed
+    /// region leaves a block, quote or substitution open, or no longer ends
+    /// in a terminator, the whole text is parsed instead, since the change may
+    /// swallow everything after it.
+    pub fn reparse(&self, new_text: String, edited: TextRange, inserted: usize) -> SyntaxTree {
+        match self.try_reparse(&new_text, edited, inserted) {
+            Some((root, errors)) => SyntaxTree::new(new_text, root, errors),
+            None => super::parse(&new_text),
+        }
+    }
+
+    fn try_reparse(
+        &self,
+        new_text: &str,
+        edited: TextRange,
+        inserted: usize,
+    ) -> Option<(Node, Vec<super::ParseError>)> {
+        let items = &self.root.children;
+        if items.is_empty() || edited.end > self.text.len() {
+            return None;
+        }
+
+        let mut first = items
+            .iter()
+            .position(|item| item.range().end >= edited.start)?;
+        let mut last = items
+            .iter()
+            .rposition(|item| item.range().start <= edited.end)?;
+        if last < first {
+            last = first;
+        }
+        while first > 0 && !items[first - 1].kind().is_terminator() {
+            first -= 1;
+        }
+        while last + 1 < items.len() && !items[last].kind().is_terminator() {
+            last += 1;
+        }
+
+        let old_region = items[first].range().cover(items[last].range());
+        let delta = inserted as isize - edited.len() as isize;
+        let new_end = (old_region.end as isize + delta) as usize;
+        if new_end < old_region.start || !new_text.is_char_boundary(new_end) {
+            return None;
+        }
+        let region_text = new_text.get(old_region.start..new_end)?;
+
+        let (mut region_root, mut region_errors) = Parser::new(region_text).parse_source_file();
+        if region_errors.iter().any(|error| escapes_region(error.kind)) {
+            return None;
+        }
+        let ends_cleanly = region_root
+            .children
+            .last()
+            .is_some_and(|item| item.kind().is_terminator());
+        if !ends_cleanly && new_end < new_text.len() {
+            return None;
+        }
+
+        region_root.shift(old_region.start as isize);
+        for error in &mut region_errors {
+            error.range = shift_range(error.range, old_region.start as isize);
+        }
+
+        let mut children = Vec::with_capacity(items.len());
+        children.extend(items[..first].iter().cloned());
+        children.extend(region_root.children);
+        for item in &items[last + 1..] {
+            let mut item = item.clone();
+            match &mut item {
+                Element::Node(node) => node.shift(delta),
+                Element::Token(token) => token.range = shift_range(token.range, delta),
+            }
+            children.push(item);
+        }
+
+        let mut errors = Vec::with_capacity(self.errors.len());
+        for error in &self.errors {
+            if error.range.start < old_region.start {
+                errors.push(error.clone());
+            } else if error.range.start >= old_region.end {
+                let mut error = error.clone();
+                error.range = shift_range(error.range, delta);
+                errors.push(error);
+            }
+        }
+        errors.extend(region_errors);
+
+        let root = Node {
+            kind: SyntaxKind::SourceFile,
+            range: TextRange::new(0, new_text.len()),
+            children,
+        };
+        Some((root, errors))
+    }
+}
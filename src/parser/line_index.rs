@@ -0,0 +1,147 @@
+use {
+    super::syntax::TextRange,
+    tower_lsp::lsp_types::{Position, Range},
+};
+
+/// Maps byte offsets to LSP positions, whose columns are counted in UTF-16
+/// code units, and back. Lines end at `\n`, `\r\n` or a lone `\r`, as in
+/// LSP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|&(i, b)| match b {
+                    b'\n' => true,
+                    b'\r' => bytes.get(i + 1) != Some(&b'\n'),
+                    _ => false,
+                })
+                .map(|(i, _)| i + 1),
+        );
+
+        Self {
+            text: text.to_string(),
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Byte range of `line`, excluding its line terminator.
+    pub fn line_range(&self, line: usize) -> TextRange {
+        let start = self.line_starts[line.min(self.line_starts.len() - 1)];
+        let mut end = self
+            .line_starts
+            .get(line + 1)
+            .map(|next| next - 1)
+            .unwrap_or(self.text.len());
+        if end > start && self.text.as_bytes()[end - 1] == b'\r' {
+            end -= 1;
+        }
+        TextRange::new(start, end)
+    }
+
+    pub fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_of(offset);
+        let start = self.line_starts[line];
+        let character = self.text[start..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum::<usize>();
+
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    /// Byte offset of an LSP position. Columns past the end of the line clamp
+    /// to the line end, and positions inside a surrogate pair snap forward.
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.line_starts.len() {
+            return self.text.len();
+        }
+
+        let range = self.line_range(line);
+        let mut units = 0;
+        for (i, ch) in self.text[range.start..range.end].char_indices() {
+            if units >= position.character as usize {
+                return range.start + i;
+            }
+            units += ch.len_utf16();
+        }
+        range.end
+    }
+
+    pub fn range(&self, range: TextRange) -> Range {
+        Range {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+
+    pub fn text_range(&self, range: Range) -> TextRange {
+        TextRange::new(self.offset(range.start), self.offset(range.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_endings() {
+        let text = "a\nb\r\nc\rd\r\re";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_count(), 6);
+        let lines: Vec<_> = (0..index.line_count())
+            .map(|line| {
+                let range = index.line_range(line);
+                &text[range.start..range.end]
+            })
+            .collect();
+        assert_eq!(lines, ["a", "b", "c", "d", "", "e"]);
+        assert_eq!(index.position(text.len()), Position::new(5, 1));
+        assert_eq!(index.offset(Position::new(3, 0)), 7);
+        assert_eq!(index.offset(Position::new(2, 5)), 6);
+    }
+
+    #[test]
+    fn trailing_carriage_return() {
+        let text = "echo\r";
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(text.len()), Position::new(1, 0));
+        assert_eq!(index.offset(Position::new(1, 0)), text.len());
+        assert_eq!(index.offset(Position::new(0, 9)), 4);
+    }
+
+    #[test]
+    fn utf16_columns() {
+        let text = "é😀x\n";
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(text.find('x').unwrap()), Position::new(0, 3));
+        assert_eq!(index.offset(Position::new(0, 3)), text.find('x').unwrap());
+        // Inside the surrogate pair of 😀.
+        assert_eq!(index.offset(Position::new(0, 2)), text.find('x').unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+//! A lossless parser for fish scripts.
+//!
+//! [`parse`] turns source text into a [`SyntaxTree`] whose tokens cover every
+//! byte of the input, so the original text can always be recovered from the
+//! tree. Syntax errors never abort parsing; they are collected alongside the
+//! tree with both byte and LSP (UTF-16) ranges.
+
+pub mod bash;
+mod grammar;
+mod incremental;
+mod line_index;
+mod syntax;
+
+pub use {
+    line_index::LineIndex,
+    syntax::{Element, Node, SyntaxKind, TextRange, Token},
+};
+
+use tower_lsp::lsp_types::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseErrorKind {
+    UnclosedBlock,
+    StrayEnd,
+    StrayElse,
+    StrayCase,
+    UnclosedQuote,
+    UnclosedSubstitution,
+    UnclosedBrace,
+    UnclosedIndex,
+    InvalidVariable,
+    MissingCommand,
+    MissingArgument,
+    UnexpectedToken,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub range: TextRange,
+    /// Same span as `range`, in LSP coordinates.
+    pub lsp_range: Range,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, range: TextRange, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            range,
+            lsp_range: Range::default(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree {
+    text: String,
+    root: Node,
+    errors: Vec<ParseError>,
+    line_index: LineIndex,
+}
+
+pub fn parse(text: &str) -> SyntaxTree {
+    let (root, errors) = grammar::Parser::new(text).parse_source_file();
+    SyntaxTree::new(text.to_string(), root, errors)
+}
+
+impl SyntaxTree {
+    fn new(text: String, root: Node, mut errors: Vec<ParseError>) -> Self {
+        let line_index = LineIndex::new(&text);
+        errors.sort_by_key(|error| (error.range.start, error.range.end));
+        for error in &mut errors {
+            error.lsp_range = line_index.range(error.range);
+        }
+
+        Self {
+            text,
+            root,
+            errors,
+            line_index,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.line_index
+    }
+
+    pub fn lsp_range(&self, range: TextRange) -> Range {
+        self.line_index.range(range)
+    }
+
+    pub fn node_text(&self, node: &Node) -> &str {
+        node.text(&self.text)
+    }
+
+    pub fn token_text(&self, token: &Token) -> &str {
+        token.text(&self.text)
+    }
+
+    /// Every function definition in the file, including nested ones.
+    pub fn functions(&self) -> impl Iterator<Item = &Node> {
+        self.root
+            .descendants()
+            .filter(|node| node.kind == SyntaxKind::FunctionDef)
+    }
+
+    /// Every plain command in the file, including those inside blocks and
+    /// command substitutions.
+    pub fn commands(&self) -> impl Iterator<Item = &Node> {
+        self.root
+            .descendants()
+            .filter(|node| node.kind == SyntaxKind::Command)
+    }
+}
+
+/// The word naming the command run by a [`SyntaxKind::Command`] node.
+pub fn command_name(command: &Node) -> Option<&Node> {
+    command.first_child(SyntaxKind::Word)
+}
+
+/// The argument words of a command, after its name and excluding redirections.
+pub fn command_args(command: &Node) -> impl Iterator<Item = &Node> {
+    command
+        .child_nodes()
+        .filter(|node| node.kind == SyntaxKind::Word)
+        .skip(1)
+}
+
+/// Words following the keyword of a block header, e.g. the name and options
+/// of `function foo --description bar`.
+pub fn header_words(block: &Node) -> impl Iterator<Item = &Node> {
+    block
+        .child_nodes()
+        .take_while(|node| node.kind != SyntaxKind::JobList)
+        .filter(|node| node.kind == SyntaxKind::Word)
+}
+
+/// The body of a block statement or clause.
+pub fn block_body(block: &Node) -> Option<&Node> {
+    block.first_child(SyntaxKind::JobList)
+}
+
+/// The unescaped value of a word that contains no expansions, such as
+/// `foo`, `'foo'`, `"foo"` or `f\oo`. Returns `None` for words with
+/// variables, command substitutions or brace expansions.
+pub fn word_value(word: &Node, src: &str) -> Option<String> {
+    let mut value = String::new();
+    for child in &word.children {
+        match child {
+            Element::Token(token) => push_token_value(token, src, &mut value)?,
+            Element::Node(node) if node.kind == SyntaxKind::DoubleQuotedString => {
+                for part in &node.children {
+                    match part {
+                        Element::Token(token) => push_token_value(token, src, &mut value)?,
+                        Element::Node(_) => return None,
+                    }
+                }
+            }
+            Element::Node(_) => return None,
+        }
+    }
+    Some(value)
+}
+
+fn push_token_value(token: &Token, src: &str, out: &mut String) -> Option<()> {
+    let text = token.text(src);
+    match token.kind {
+        SyntaxKind::Text | SyntaxKind::StringText | SyntaxKind::Keyword => out.push_str(text),
+        SyntaxKind::DoubleQuote | SyntaxKind::LineContinuation => {}
+        SyntaxKind::SingleQuoted => {
+            let inner = text.strip_prefix('\'').unwrap_or(text);
+            let inner = inner.strip_suffix('\'').unwrap_or(inner);
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match (c, chars.clone().next()) {
+                    ('\\', Some(next @ ('\'' | '\\'))) => {
+                        out.push(next);
+                        chars.next();
+                    }
+                    _ => out.push(c),
+                }
+            }
+        }
+        SyntaxKind::Escape => out.push_str(&unescape(text)),
+        _ => return None,
+    }
+    Some(())
+}
+
+fn unescape(escape: &str) -> String {
+    let body = &escape[1..];
+    let mut chars = body.chars();
+    let Some(first) = chars.next() else {
+        return String::new();
+    };
+    let digits = chars.as_str();
+    let from_radix = |radix| {
+        u32::from_str_radix(digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+            .map(String::from)
+            .unwrap_or_default()
+    };
+    match first {
+        'n' => "\n".into(),
+        't' => "\t".into(),
+        'r' => "\r".into(),
+        'e' => "\x1b".into(),
+        'a' => "\x07".into(),
+        'b' => "\x08".into(),
+        'f' => "\x0c".into(),
+        'v' => "\x0b".into(),
+        'x' | 'X' | 'u' | 'U' if !digits.is_empty() => from_radix(16),
+        '0'..='7' => {
+            let octal = format!("{first}{digits}");
+            u32::from_str_radix(&octal, 8)
+                .ok()
+                .and_then(char::from_u32)
+                .map(String::from)
+                .unwrap_or_default()
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(text: &str) -> Vec<(ParseErrorKind, &str)> {
+        parse(text)
+            .errors
+            .into_iter()
+            .map(|error| (error.kind, &text[error.range.start..error.range.end]))
+            .collect()
+    }
+
+    #[test]
+    fn unexpected_characters_in_words() {
+        assert_eq!(
+            errors("echo >|foo\n"),
+            [(ParseErrorKind::UnexpectedToken, ">")]
+        );
+        assert_eq!(errors("echo >foo | cat\n"), []);
+    }
+
+    #[test]
+    fn round_trips_source() {
+        for text in ["echo >|foo\n", "if true\n  echo ${\n", "echo 'a\\\n"] {
+            let tree = parse(text);
+            assert_eq!(tree.root().text(tree.text()), text);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+
+/// Every kind of token and node that can appear in a fish syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    // Tokens
+    Whitespace,
+    Newline,
+    Comment,
+    LineContinuation,
+    Semicolon,
+    Pipe,
+    AndAnd,
+    OrOr,
+    Ampersand,
+    RedirectOp,
+    Keyword,
+    Text,
+    Escape,
+    SingleQuoted,
+    DoubleQuote,
+    StringText,
+    Dollar,
+    VariableName,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Error,
+
+    // Nodes
+    SourceFile,
+    JobList,
+    Job,
+    Pipeline,
+    Command,
+    NotStatement,
+    FunctionDef,
+    IfStatement,
+    IfClause,
+    ElseIfClause,
+    ElseClause,
+    WhileLoop,
+    ForLoop,
+    SwitchStatement,
+    CaseClause,
+    BeginBlock,
+    Redirect,
+    Word,
+    DoubleQuotedString,
+    Variable,
+    Index,
+    CommandSubstitution,
+    ErrorNode,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace | SyntaxKind::Comment | SyntaxKind::LineContinuation
+        )
+    }
+
+    pub fn is_terminator(self) -> bool {
+        matches!(self, SyntaxKind::Newline | SyntaxKind::Semicolon)
+    }
+
+    /// Statements that open a block closed by `end`.
+    pub fn is_block(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::FunctionDef
+                | SyntaxKind::IfStatement
+                | SyntaxKind::WhileLoop
+                | SyntaxKind::ForLoop
+                | SyntaxKind::SwitchStatement
+                | SyntaxKind::BeginBlock
+        )
+    }
+
+    pub fn is_statement(self) -> bool {
+        self.is_block() || matches!(self, SyntaxKind::Command | SyntaxKind::NotStatement)
+    }
+}
+
+/// A half-open byte range into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TextRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TextRange {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn empty(at: usize) -> Self {
+        Self { start: at, end: at }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether `offset` lies inside the range, counting the end as inside so
+    /// a cursor placed right after a word still belongs to it.
+    pub fn contains_inclusive(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    pub fn contains_range(&self, other: TextRange) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    pub fn intersects(&self, other: TextRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    pub fn cover(&self, other: TextRange) -> TextRange {
+        TextRange::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for TextRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: SyntaxKind,
+    pub range: TextRange,
+}
+
+impl Token {
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.range.start..self.range.end]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+impl Element {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            Element::Node(node) => node.kind,
+            Element::Token(token) => token.kind,
+        }
+    }
+
+    pub fn range(&self) -> TextRange {
+        match self {
+            Element::Node(node) => node.range,
+            Element::Token(token) => token.range,
+        }
+    }
+
+    pub fn as_node(&self) -> Option<&Node> {
+        match self {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        }
+    }
+
+    pub fn as_token(&self) -> Option<&Token> {
+        match self {
+            Element::Node(_) => None,
+            Element::Token(token) => Some(token),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: SyntaxKind,
+    pub range: TextRange,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.range.start..self.range.end]
+    }
+
+    pub fn child_nodes(&self) -> impl DoubleEndedIterator<Item = &Node> {
+        self.children.iter().filter_map(Element::as_node)
+    }
+
+    pub fn child_tokens(&self) -> impl DoubleEndedIterator<Item = &Token> {
+        self.children.iter().filter_map(Element::as_token)
+    }
+
+    pub fn first_child(&self, kind: SyntaxKind) -> Option<&Node> {
+        self.child_nodes().find(|node| node.kind == kind)
+    }
+
+    pub fn first_token(&self, kind: SyntaxKind) -> Option<&Token> {
+        self.child_tokens().find(|token| token.kind == kind)
+    }
+
+    /// The first keyword token directly under this node, e.g. `function` for a
+    /// [`SyntaxKind::FunctionDef`].
+    pub fn keyword(&self) -> Option<&Token> {
+        self.first_token(SyntaxKind::Keyword)
+    }
+
+    /// The closing `end` keyword of a block, if it was written.
+    pub fn end_keyword<'a>(&'a self, src: &str) -> Option<&'a Token> {
+        self.child_tokens()
+            .filter(|token| token.kind == SyntaxKind::Keyword)
+            .find(|token| token.text(src) == "end")
+    }
+
+    /// All nodes below this one in pre-order, not including `self`.
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            stack: self.child_nodes().rev().collect(),
+        }
+    }
+
+    /// All tokens below this one in source order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut out = Vec::new();
+        collect_tokens(self, &mut out);
+        out
+    }
+
+    /// The innermost path of nodes whose range contains `offset`, starting
+    /// with `self`.
+    pub fn path_at(&self, offset: usize) -> Vec<&Node> {
+        let mut path = vec![self];
+        let mut current = self;
+        'outer: loop {
+            for child in current.child_nodes() {
+                if child.range.contains(offset)
+                    || (child.range.end == offset && ends_in_word(child))
+                {
+                    path.push(child);
+                    current = child;
+                    continue 'outer;
+                }
+            }
+            break;
+        }
+        path
+    }
+
+    /// The token containing `offset`, preferring the token that ends at the
+    /// offset when the cursor sits right after a word.
+    pub fn token_at(&self, offset: usize) -> Option<&Token> {
+        let tokens = self.tokens();
+        tokens
+            .iter()
+            .find(|token| token.range.contains(offset))
+            .filter(|token| !token.kind.is_terminator() && token.kind != SyntaxKind::Whitespace)
+            .or_else(|| {
+                tokens
+                    .iter()
+                    .find(|token| token.range.end == offset && !token.range.is_empty())
+            })
+            .or_else(|| tokens.iter().find(|token| token.range.contains(offset)))
+            .copied()
+    }
+
+    /// Move every range in this subtree by `delta` bytes.
+    pub(crate) fn shift(&mut self, delta: isize) {
+        self.range = shift_range(self.range, delta);
+        for child in &mut self.children {
+            match child {
+                Element::Node(node) => node.shift(delta),
+                Element::Token(token) => token.range = shift_range(token.range, delta),
+            }
+        }
+    }
+}
+
+fn ends_in_word(node: &Node) -> bool {
+    matches!(
+        node.kind,
+        SyntaxKind::Word
+            | SyntaxKind::Variable
+            | SyntaxKind::Command
+            | SyntaxKind::Pipeline
+            | SyntaxKind::Job
+            | SyntaxKind::Redirect
+            | SyntaxKind::Index
+    ) || node.children.last().is_some_and(|last| {
+        matches!(
+            last.kind(),
+            SyntaxKind::Text | SyntaxKind::VariableName | SyntaxKind::Keyword
+        )
+    })
+}
+
+pub(crate) fn shift_range(range: TextRange, delta: isize) -> TextRange {
+    TextRange::new(
+        (range.start as isize + delta) as usize,
+        (range.end as isize + delta) as usize,
+    )
+}
+
+fn collect_tokens<'a>(node: &'a Node, out: &mut Vec<&'a Token>) {
+    for child in &node.children {
+        match child {
+            Element::Node(node) => collect_tokens(node, out),
+            Element::Token(token) => out.push(token),
+        }
+    }
+}
+
+pub struct Descendants<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.child_nodes().rev());
+        Some(node)
+    }
+}
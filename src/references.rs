@@ -0,0 +1,440 @@
+use {
+    crate::{
+        parser::{command_args, command_name, word_value, Node, SyntaxKind, SyntaxTree, TextRange},
+        scopes::ScopeMap,
+        server::State,
+        symbols::{function_name, is_command, symbol_at, SetCommand, SymbolRef},
+    },
+    tower_lsp::lsp_types::*,
+};
+
+/// One use of a symbol inside a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occurrence {
+    pub range: TextRange,
+    /// Definitions, assignments and erasures write; everything else reads.
+    pub write: bool,
+}
+
+/// A symbol resolved well enough to find its other uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Function(String),
+    /// A variable together with the scope that identifies it.
+    Variable {
+        name: String,
+        scope: TextRange,
+    },
+}
+
+impl Target {
+    pub fn at(tree: &SyntaxTree, scopes: &ScopeMap, offset: usize) -> Option<Self> {
+        match symbol_at(tree, offset)? {
+            SymbolRef::Command { name, range } => {
+                let token = tree.root().token_at(range.start);
+                if token.is_some_and(|token| token.kind == SyntaxKind::Keyword) {
+                    return None;
+                }
+                Some(Target::Function(name))
+            }
+            SymbolRef::Variable { name, range } => {
+                let scope = scopes
+                    .bindings
+                    .iter()
+                    .find(|binding| binding.name_range.start == range.start)
+                    .map_or_else(
+                        || scopes.scope_of_use(tree, &name, range.start),
+                        |binding| binding.scope,
+                    );
+                Some(Target::Variable { name, scope })
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Target::Function(name) | Target::Variable { name, .. } => name,
+        }
+    }
+
+    /// Every occurrence of the target in `tree`, in document order.
+    pub fn occurrences(&self, tree: &SyntaxTree, scopes: &ScopeMap) -> Vec<Occurrence> {
+        let mut occurrences = match self {
+            Target::Function(name) => function_occurrences(tree, name),
+            Target::Variable { name, scope } => variable_occurrences(tree, scopes, name)
+                .into_iter()
+                .filter(|(_, other)| other == scope)
+                .map(|(occurrence, _)| occurrence)
+                .collect(),
+        };
+        occurrences.sort_by_key(|occurrence| occurrence.range.start);
+        occurrences.dedup_by_key(|occurrence| occurrence.range);
+        occurrences
+    }
+}
+
+/// Definitions of and calls to the function `name`, and the places that
+/// name it as an argument: `complete -c NAME` and `funcsave NAME`.
+pub fn function_occurrences(tree: &SyntaxTree, name: &str) -> Vec<Occurrence> {
+    let src = tree.text();
+    let mut occurrences = Vec::new();
+    for node in tree.root().descendants() {
+        match node.kind {
+            SyntaxKind::FunctionDef => {
+                if let Some((word, _)) = function_name(node, src).filter(|(_, n)| n == name) {
+                    occurrences.push(Occurrence {
+                        range: word.range,
+                        write: true,
+                    });
+                }
+            }
+            SyntaxKind::Command => {
+                let named = command_name(node)
+                    .filter(|word| word_value(word, src).is_some_and(|value| value == name))
+                    .map(|word| word.range);
+                let ranges = named
+                    .into_iter()
+                    .chain(function_name_arguments(node, src, name));
+                occurrences.extend(ranges.map(|range| Occurrence {
+                    range,
+                    write: false,
+                }));
+            }
+            _ => {}
+        }
+    }
+    occurrences
+}
+
+/// Where `complete` or `funcsave` names the function `name` in its arguments.
+fn function_name_arguments(command: &Node, src: &str, name: &str) -> Vec<TextRange> {
+    let mut ranges = Vec::new();
+    if is_command(command, src, "funcsave") {
+        for word in command_args(command) {
+            if word_value(word, src).is_some_and(|value| value == name) {
+                ranges.push(word.range);
+            }
+        }
+    } else if is_command(command, src, "complete") {
+        let mut args = command_args(command);
+        while let Some(word) = args.next() {
+            let Some(value) = word_value(word, src) else {
+                continue;
+            };
+            let (word, prefix) = match value.as_str() {
+                "-c" | "--command" => match args.next() {
+                    Some(word) => (word, ""),
+                    None => break,
+                },
+                _ if value.starts_with("--command=") => (word, "--command="),
+                _ if value.starts_with("-c") => (word, "-c"),
+                _ => continue,
+            };
+            // Only literal words have a known layout to rewrite.
+            let text = word.text(src);
+            if text.strip_prefix(prefix) == Some(name) {
+                let start = word.range.start + prefix.len();
+                ranges.push(TextRange::new(start, start + name.len()));
+            }
+        }
+    }
+    ranges
+}
+
+/// Every occurrence of a variable called `name`, with the scope each one
+/// belongs to.
+pub fn variable_occurrences(
+    tree: &SyntaxTree,
+    scopes: &ScopeMap,
+    name: &str,
+) -> Vec<(Occurrence, TextRange)> {
+    occurrences_of(tree, scopes, |other| other == name)
+        .into_iter()
+        .map(|(_, occurrence, scope)| (occurrence, scope))
+        .collect()
+}
+
+/// Every occurrence of every variable, with its name and the scope it
+/// belongs to.
+pub fn all_variable_occurrences(
+    tree: &SyntaxTree,
+    scopes: &ScopeMap,
+) -> Vec<(String, Occurrence, TextRange)> {
+    occurrences_of(tree, scopes, |_| true)
+}
+
+fn occurrences_of(
+    tree: &SyntaxTree,
+    scopes: &ScopeMap,
+    wanted: impl Fn(&str) -> bool,
+) -> Vec<(String, Occurrence, TextRange)> {
+    let src = tree.text();
+    let mut occurrences: Vec<(String, Occurrence, TextRange)> = scopes
+        .bindings
+        .iter()
+        .filter(|binding| wanted(&binding.name))
+        .map(|binding| {
+            let occurrence = Occurrence {
+                range: binding.name_range,
+                write: true,
+            };
+            (binding.name.clone(), occurrence, binding.scope)
+        })
+        .collect();
+
+    for node in tree.root().descendants() {
+        match node.kind {
+            SyntaxKind::Variable => {
+                let Some(token) = node.first_token(SyntaxKind::VariableName) else {
+                    continue;
+                };
+                let name = token.text(src);
+                if wanted(name) {
+                    let occurrence = Occurrence {
+                        range: token.range,
+                        write: false,
+                    };
+                    let scope = scopes.scope_of_use(tree, name, token.range.start);
+                    occurrences.push((name.to_string(), occurrence, scope));
+                }
+            }
+            SyntaxKind::Command => {
+                // Assignments are already bindings; this picks up the names
+                // given to `set --erase` and `set --query`.
+                let Some(set) = SetCommand::parse(node, src) else {
+                    continue;
+                };
+                if set.is_assignment() {
+                    continue;
+                }
+                let named = set.name.iter().map(|(word, _)| *word);
+                let extra = set
+                    .values
+                    .iter()
+                    .copied()
+                    .filter(|_| set.is_erase() || set.is_query());
+                for word in named.chain(extra) {
+                    let Some(value) = word_value(word, src) else {
+                        continue;
+                    };
+                    let name = value.split('[').next().unwrap_or_default();
+                    if !wanted(name) {
+                        continue;
+                    }
+                    let range = TextRange::new(word.range.start, word.range.start + name.len());
+                    let occurrence = Occurrence {
+                        range,
+                        write: set.is_erase(),
+                    };
+                    let scope = scopes.scope_of_use(tree, name, range.start);
+                    occurrences.push((name.to_string(), occurrence, scope));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    occurrences
+}
+
+/// The occurrences of a target in one file.
+pub struct FileOccurrences<'a> {
+    pub uri: &'a Url,
+    pub tree: &'a SyntaxTree,
+    /// The version of an open document; `None` for a file only indexed.
+    pub version: Option<i32>,
+    pub occurrences: Vec<Occurrence>,
+}
+
+/// The occurrences of `target`, which was found in `uri`, in every open
+/// document and indexed workspace file, starting with `uri`. Functions and
+/// global variables are shared between files; other variables only exist in
+/// `uri`.
+pub fn workspace_occurrences<'a>(
+    state: &'a State,
+    uri: &Url,
+    target: &Target,
+) -> Vec<FileOccurrences<'a>> {
+    let open = state
+        .documents
+        .iter()
+        .map(|(uri, document)| (uri, &document.tree, Some(document.version)));
+    let indexed = state
+        .workspace
+        .files
+        .iter()
+        .filter(|(uri, _)| !state.documents.contains_key(uri))
+        .map(|(uri, file)| (uri, &file.tree, None));
+    let mut files: Vec<_> = open.chain(indexed).collect();
+    files.sort_by_key(|(other, ..)| (*other != uri, other.as_str()));
+
+    let mut found = Vec::new();
+    for (other, tree, version) in files {
+        let scopes = ScopeMap::build(tree);
+        let shared = match target {
+            Target::Variable { scope, .. } => other == uri || *scope == scopes.global_scope(),
+            Target::Function(_) => true,
+        };
+        if !shared {
+            continue;
+        }
+        let occurrences = target.occurrences(tree, &scopes);
+        if !occurrences.is_empty() {
+            found.push(FileOccurrences {
+                uri: other,
+                tree,
+                version,
+                occurrences,
+            });
+        }
+    }
+    found
+}
+
+pub fn references(
+    state: &State,
+    uri: &Url,
+    position: Position,
+    include_declaration: bool,
+) -> Vec<Location> {
+    let Some(document) = state.documents.get(uri) else {
+        return Vec::new();
+    };
+    let tree = &document.tree;
+    let scopes = ScopeMap::build(tree);
+    let Some(target) = Target::at(tree, &scopes, tree.line_index().offset(position)) else {
+        return Vec::new();
+    };
+
+    workspace_occurrences(state, uri, &target)
+        .into_iter()
+        .flat_map(|file| {
+            file.occurrences
+                .into_iter()
+                .filter(|occurrence| include_declaration || !occurrence.write)
+                .map(move |occurrence| {
+                    Location::new(file.uri.clone(), file.tree.lsp_range(occurrence.range))
+                })
+        })
+        .collect()
+}
+
+pub fn document_highlights(state: &State, uri: &Url, position: Position) -> Vec<DocumentHighlight> {
+    let Some(document) = state.documents.get(uri) else {
+        return Vec::new();
+    };
+    let tree = &document.tree;
+    let scopes = ScopeMap::build(tree);
+    let Some(target) = Target::at(tree, &scopes, tree.line_index().offset(position)) else {
+        return Vec::new();
+    };
+
+    target
+        .occurrences(tree, &scopes)
+        .into_iter()
+        .map(|occurrence| DocumentHighlight {
+            range: tree.lsp_range(occurrence.range),
+            kind: Some(if occurrence.write {
+                DocumentHighlightKind::WRITE
+            } else {
+                DocumentHighlightKind::READ
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{document::Document, workspace::IndexedFile},
+    };
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file:///project/{path}")).unwrap()
+    }
+
+    fn state_with(files: &[(&str, &str)]) -> State {
+        let mut state = State::default();
+        for (path, text) in files {
+            state
+                .documents
+                .insert(uri(path), Document::new(text.to_string(), 1));
+        }
+        state
+    }
+
+    /// The files and lines of the references to the symbol at `needle` in
+    /// `path`.
+    fn references_to(state: &State, path: &str, needle: &str) -> Vec<(String, u32)> {
+        let uri = uri(path);
+        let document = &state.documents[&uri];
+        let offset = document.tree.text().find(needle).unwrap();
+        let position = document.tree.line_index().position(offset);
+        let mut found: Vec<_> = references(state, &uri, position, true)
+            .into_iter()
+            .map(|location| {
+                let file = location.uri.path().trim_start_matches("/project/");
+                (file.to_string(), location.range.start.line)
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn top_level_locals_stay_in_their_file() {
+        let state = state_with(&[
+            ("a.fish", "set -l x 1\necho $x\nset -g y 1\n"),
+            ("b.fish", "echo $x\necho $y\n"),
+        ]);
+        assert_eq!(
+            references_to(&state, "a.fish", "x"),
+            [("a.fish".into(), 0), ("a.fish".into(), 1)]
+        );
+        assert_eq!(
+            references_to(&state, "a.fish", "y"),
+            [("a.fish".into(), 2), ("b.fish".into(), 1)]
+        );
+        assert_eq!(references_to(&state, "b.fish", "x"), [("b.fish".into(), 0)]);
+    }
+
+    #[test]
+    fn indexed_files() {
+        let mut state = state_with(&[
+            ("config.fish", "greet\nset -g name world\n"),
+            (
+                "functions/greet.fish",
+                "function greet\n    echo $name\nend\n",
+            ),
+        ]);
+        for (path, text) in [
+            ("conf.d/init.fish", "greet\necho $name\n"),
+            ("completions/greet.fish", "complete -c greet -f\n"),
+            // A stale copy of an open file.
+            ("functions/greet.fish", "greet\ngreet\n"),
+        ] {
+            state
+                .workspace
+                .files
+                .insert(uri(path), IndexedFile::new(text));
+        }
+        assert_eq!(
+            references_to(&state, "config.fish", "greet"),
+            [
+                ("completions/greet.fish".into(), 0),
+                ("conf.d/init.fish".into(), 0),
+                ("config.fish".into(), 0),
+                ("functions/greet.fish".into(), 0),
+            ]
+        );
+        assert_eq!(
+            references_to(&state, "config.fish", "name"),
+            [
+                ("conf.d/init.fish".into(), 1),
+                ("config.fish".into(), 1),
+                ("functions/greet.fish".into(), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use {
+    crate::{
+        autoload::AutoloadPaths,
+        builtins::{is_builtin, is_special_variable},
+        definition::{function_definitions, load},
+        references::{workspace_occurrences, Target},
+        scopes::ScopeMap,
+        server::State,
+        symbols::is_valid_variable_name,
+    },
+    anyhow::{bail, Result},
+    std::path::PathBuf,
+    tower_lsp::lsp_types::*,
+};
+
+/// The range of the symbol under the cursor when it can be renamed.
+pub fn prepare_rename(state: &State, uri: &Url, position: Position) -> Option<Range> {
+    let document = state.documents.get(uri)?;
+    let tree = &document.tree;
+    let scopes = ScopeMap::build(tree);
+    let offset = tree.line_index().offset(position);
+    let target =
+        Target::at(tree, &scopes, offset).filter(|target| renameable(state, uri, target))?;
+
+    target
+        .occurrences(tree, &scopes)
+        .into_iter()
+        .find(|occurrence| occurrence.range.contains_inclusive(offset))
+        .map(|occurrence| tree.lsp_range(occurrence.range))
+}
+
+pub fn rename(
+    state: &State,
+    uri: &Url,
+    position: Position,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>> {
+    let Some(document) = state.documents.get(uri) else {
+        return Ok(None);
+    };
+    let tree = &document.tree;
+    let scopes = ScopeMap::build(tree);
+    let offset = tree.line_index().offset(position);
+    let Some(target) = Target::at(tree, &scopes, offset) else {
+        return Ok(None);
+    };
+    if !renameable(state, uri, &target) {
+        bail!("`{}` cannot be renamed", target.name());
+    }
+    validate_name(&target, new_name)?;
+
+    let edit = |range| TextEdit::new(range, new_name.to_string());
+    let mut edits: Vec<(Url, Option<i32>, Vec<TextEdit>)> =
+        workspace_occurrences(state, uri, &target)
+            .into_iter()
+            .map(|file| {
+                let file_edits = file
+                    .occurrences
+                    .iter()
+                    .map(|occurrence| edit(file.tree.lsp_range(occurrence.range)))
+                    .collect();
+                (file.uri.clone(), file.version, file_edits)
+            })
+            .collect();
+    let mut renames = Vec::new();
+
+    if let Target::Function(name) = &target {
+        let files = autoload_files(state, name);
+
+        // Autoloaded files that are neither open nor indexed still need
+        // their edits.
+        for path in &files {
+            let Some((file, tree)) = load(state, path) else {
+                continue;
+            };
+            if edits.iter().any(|(edited, ..)| *edited == file) {
+                continue;
+            }
+            let file_edits: Vec<_> = target
+                .occurrences(&tree, &ScopeMap::build(&tree))
+                .into_iter()
+                .map(|occurrence| edit(tree.lsp_range(occurrence.range)))
+                .collect();
+            if !file_edits.is_empty() {
+                edits.push((file, None, file_edits));
+            }
+        }
+
+        for path in files {
+            let new_path = path.with_file_name(format!("{new_name}.fish"));
+            let (Ok(old_uri), Ok(new_uri)) =
+                (Url::from_file_path(&path), Url::from_file_path(&new_path))
+            else {
+                continue;
+            };
+            renames.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+                RenameFile {
+                    old_uri,
+                    new_uri,
+                    options: None,
+                    annotation_id: None,
+                },
+            )));
+        }
+    }
+
+    // Clients that cannot rename files get the edits alone.
+    if !state.supports_resource_operation(ResourceOperationKind::Rename) {
+        let changes = edits
+            .into_iter()
+            .map(|(uri, _, file_edits)| (uri, file_edits))
+            .collect();
+        return Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }));
+    }
+
+    let mut changes: Vec<_> = edits
+        .into_iter()
+        .map(|(uri, version, file_edits)| {
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: file_edits.into_iter().map(OneOf::Left).collect(),
+            })
+        })
+        .collect();
+    changes.extend(renames);
+    Ok(Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(changes)),
+        ..Default::default()
+    }))
+}
+
+/// Builtins, special variables and functions not defined in an open file or
+/// the workspace belong to fish or the system, so they keep their names.
+fn renameable(state: &State, uri: &Url, target: &Target) -> bool {
+    match target {
+        Target::Function(name) => {
+            let Some(document) = state.documents.get(uri) else {
+                return false;
+            };
+            !is_builtin(name)
+                && function_definitions(state, uri, &document.tree, name)
+                    .iter()
+                    .any(|location| {
+                        state.documents.contains_key(&location.uri)
+                            || location.uri.to_file_path().is_ok_and(|path| {
+                                AutoloadPaths::in_workspace(&state.workspace_folders, &path)
+                            })
+                    })
+        }
+        Target::Variable { name, .. } => !is_special_variable(name),
+    }
+}
+
+fn validate_name(target: &Target, new_name: &str) -> Result<()> {
+    if new_name.is_empty() {
+        bail!("The new name is empty");
+    }
+    match target {
+        Target::Variable { .. } => {
+            if !is_valid_variable_name(new_name) {
+                bail!("`{new_name}` is not a valid variable name");
+            }
+        }
+        Target::Function(_) => {
+            if new_name.starts_with('-')
+                || new_name
+                    .chars()
+                    .any(|c| c.is_whitespace() || "$()[]{};|&<>'\"\\*?~#/".contains(c))
+            {
+                bail!("`{new_name}` is not a valid function name");
+            }
+            if is_builtin(new_name) {
+                bail!("`{new_name}` is a fish builtin");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `NAME.fish` files that autoload the function `name` or its completions,
+/// whether open, indexed or found in the workspace's function and completion
+/// directories. Files elsewhere on the search paths belong to the user or the
+/// system and are never renamed.
+fn autoload_files(state: &State, name: &str) -> Vec<PathBuf> {
+    let file_name = format!("{name}.fish");
+    let known = state.documents.keys().chain(state.workspace.files.keys());
+    let mut files: Vec<PathBuf> = known
+        .filter_map(|uri| uri.to_file_path().ok())
+        .filter(|path| {
+            path.file_name().is_some_and(|file| *file == *file_name)
+                && path
+                    .parent()
+                    .and_then(|dir| dir.file_name())
+                    .is_some_and(|dir| dir == "functions" || dir == "completions")
+        })
+        .collect();
+    let workspace_dirs = state
+        .autoload
+        .function_path
+        .iter()
+        .chain(&state.autoload.complete_path)
+        .filter(|dir| AutoloadPaths::in_workspace(&state.workspace_folders, dir));
+    for dir in workspace_dirs {
+        let path = dir.join(&file_name);
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            config::Config,
+            document::Document,
+            workspace::{fish_files, IndexedFile},
+        },
+        std::path::Path,
+    };
+
+    /// A workspace with an autoloaded function `foo`, its completions, and
+    /// scripts calling it, where only `config.fish` is open.
+    fn workspace(dir: &Path, indexed: bool) -> (State, Url) {
+        let files = [
+            ("functions/foo.fish", "function foo\n    echo foo\nend\n"),
+            ("completions/foo.fish", "complete -c foo -l verbose\n"),
+            ("conf.d/init.fish", "foo\nfoo --verbose\n"),
+            ("config.fish", "if status is-interactive\n    foo\nend\n"),
+        ];
+        for (path, text) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+
+        let mut state = State {
+            workspace_folders: vec![dir.to_path_buf()],
+            ..Default::default()
+        };
+        state.autoload = AutoloadPaths::new(&state.workspace_folders, &Config::default());
+        if indexed {
+            for path in fish_files(dir) {
+                let uri = Url::from_file_path(&path).unwrap();
+                let file = IndexedFile::read(&path).unwrap();
+                state.workspace.files.insert(uri, file);
+            }
+        }
+        let uri = Url::from_file_path(dir.join("config.fish")).unwrap();
+        let text = std::fs::read_to_string(dir.join("config.fish")).unwrap();
+        state.documents.insert(uri.clone(), Document::new(text, 1));
+        (state, uri)
+    }
+
+    fn allow_renames(state: &mut State) {
+        state.client_capabilities.workspace = Some(WorkspaceClientCapabilities {
+            workspace_edit: Some(WorkspaceEditClientCapabilities {
+                document_changes: Some(true),
+                resource_operations: Some(vec![ResourceOperationKind::Rename]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    /// The files edited, with their number of edits, and the files renamed,
+    /// relative to `dir`.
+    type Summary = (Vec<(String, usize)>, Vec<(String, String)>);
+
+    fn summarize(dir: &Path, edit: WorkspaceEdit) -> Summary {
+        let relative = |uri: &Url| {
+            let path = uri.to_file_path().unwrap();
+            let path = path.strip_prefix(dir).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let (mut edited, mut renamed) = (Vec::new(), Vec::new());
+        match (edit.changes, edit.document_changes) {
+            (Some(changes), None) => {
+                edited.extend(
+                    changes
+                        .iter()
+                        .map(|(uri, edits)| (relative(uri), edits.len())),
+                );
+                edited.sort();
+            }
+            (None, Some(DocumentChanges::Operations(operations))) => {
+                for operation in operations {
+                    match operation {
+                        DocumentChangeOperation::Edit(edit) => {
+                            edited.push((relative(&edit.text_document.uri), edit.edits.len()))
+                        }
+                        DocumentChangeOperation::Op(ResourceOp::Rename(rename)) => {
+                            renamed.push((relative(&rename.old_uri), relative(&rename.new_uri)))
+                        }
+                        other => panic!("unexpected {other:?}"),
+                    }
+                }
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        (edited, renamed)
+    }
+
+    fn rename_foo(state: &State, uri: &Url) -> WorkspaceEdit {
+        rename(state, uri, Position::new(1, 5), "bar")
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn renames_autoloaded_function_across_workspace() {
+        let dir = std::env::temp_dir().join(format!("fish-lsp-rename-{}", std::process::id()));
+        let edited = |files: &[(&str, usize)]| -> Vec<(String, usize)> {
+            files
+                .iter()
+                .map(|(path, count)| (path.to_string(), *count))
+                .collect()
+        };
+
+        for indexed in [true, false] {
+            let (mut state, uri) = workspace(&dir, indexed);
+
+            // Without file renames, only the text changes.
+            let (edits, renames) = summarize(&dir, rename_foo(&state, &uri));
+            let mut expected = edited(&[
+                ("completions/foo.fish", 1),
+                ("conf.d/init.fish", 2),
+                ("config.fish", 1),
+                ("functions/foo.fish", 1),
+            ]);
+            if !indexed {
+                // Only files on the autoload paths are found on disk.
+                expected.remove(1);
+            }
+            assert_eq!(edits, expected, "indexed: {indexed}");
+            assert_eq!(renames, []);
+
+            allow_renames(&mut state);
+            let (edits, renames) = summarize(&dir, rename_foo(&state, &uri));
+            assert_eq!(edits[0], ("config.fish".into(), 1));
+            assert_eq!(edits.len(), expected.len());
+            assert_eq!(
+                renames,
+                [
+                    ("completions/foo.fish".into(), "completions/bar.fish".into()),
+                    ("functions/foo.fish".into(), "functions/bar.fish".into()),
+                ]
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn local_variables() {
+        let uri = Url::parse("file:///project/config.fish").unwrap();
+        let mut state = State::default();
+        let text = "set -l x 1\necho $x\n";
+        state
+            .documents
+            .insert(uri.clone(), Document::new(text.into(), 1));
+        let edit = rename(&state, &uri, Position::new(1, 6), "y")
+            .unwrap()
+            .unwrap();
+        let changes = edit.changes.unwrap();
+        assert_eq!(changes[&uri].len(), 2);
+        assert!(rename(&state, &uri, Position::new(1, 6), "not valid").is_err());
+    }
+}
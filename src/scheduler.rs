@@ -0,0 +1,166 @@
+//! Bookkeeping for diagnostics runs: the run in progress for each document,
+//! so a newer edit can cancel it, and the last report computed for each
+//! document version, so pulls for an unchanged document cost nothing.
+
+use {
+    std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    },
+    tokio_util::sync::CancellationToken,
+    tower_lsp::lsp_types::{Diagnostic, Url},
+};
+
+/// How long a document must go without edits before it is checked.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    runs: Mutex<HashMap<Url, CancellationToken>>,
+    reports: Mutex<HashMap<Url, Report>>,
+    next_result: AtomicU64,
+    /// Bumped whenever something every document depends on changes, such as
+    /// the workspace index.
+    generation: AtomicU64,
+}
+
+/// The diagnostics of one version of a document.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub version: i32,
+    pub result_id: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Scheduler {
+    /// Begin a run for `uri`, cancelling the one still pending or running.
+    pub fn start(&self, uri: &Url) -> CancellationToken {
+        let token = CancellationToken::new();
+        let previous = self.runs.lock().unwrap().insert(uri.clone(), token.clone());
+        if let Some(previous) = previous {
+            previous.cancel();
+        }
+        token
+    }
+
+    /// Cancel any run for `uri` and drop its report, once it is closed.
+    pub fn forget(&self, uri: &Url) {
+        if let Some(run) = self.runs.lock().unwrap().remove(uri) {
+            run.cancel();
+        }
+        self.reports.lock().unwrap().remove(uri);
+    }
+
+    /// Drop every report, after something they all depend on changed.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.reports.lock().unwrap().clear();
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// The stored report for `version` of a document, if still valid.
+    pub fn report(&self, uri: &Url, version: i32) -> Option<Report> {
+        self.reports
+            .lock()
+            .unwrap()
+            .get(uri)
+            .filter(|report| report.version == version)
+            .cloned()
+    }
+
+    /// Remember the diagnostics computed for `version` of a document under a
+    /// fresh result id, unless the reports were invalidated since
+    /// `generation` while they were being computed.
+    pub fn store(
+        &self,
+        uri: &Url,
+        version: i32,
+        generation: u64,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Report {
+        let report = Report {
+            version,
+            result_id: self.next_result.fetch_add(1, Ordering::Relaxed).to_string(),
+            diagnostics,
+        };
+        let mut reports = self.reports.lock().unwrap();
+        if generation == self.generation() {
+            reports.insert(uri.clone(), report.clone());
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file:///project/{path}")).unwrap()
+    }
+
+    /// A debounced run like the server's: `true` when it got to check the
+    /// document, `false` when a newer edit cancelled it first.
+    async fn debounced(token: CancellationToken) -> bool {
+        tokio::select! {
+            _ = token.cancelled() => false,
+            _ = tokio::time::sleep(DEBOUNCE) => true,
+        }
+    }
+
+    #[tokio::test]
+    async fn newer_edits_cancel_runs() {
+        let scheduler = Scheduler::default();
+        let first = tokio::spawn(debounced(scheduler.start(&uri("a.fish"))));
+        let other = tokio::spawn(debounced(scheduler.start(&uri("b.fish"))));
+        tokio::time::sleep(DEBOUNCE / 4).await;
+        let second = tokio::spawn(debounced(scheduler.start(&uri("a.fish"))));
+
+        assert!(!first.await.unwrap());
+        assert!(second.await.unwrap());
+        assert!(other.await.unwrap());
+
+        let closed = scheduler.start(&uri("a.fish"));
+        scheduler.forget(&uri("a.fish"));
+        assert!(closed.is_cancelled());
+    }
+
+    #[test]
+    fn reports_by_version() {
+        let scheduler = Scheduler::default();
+        let a = uri("a.fish");
+        let first = scheduler.store(&a, 1, scheduler.generation(), Vec::new());
+        assert_eq!(scheduler.report(&a, 1).unwrap().result_id, first.result_id);
+        assert!(scheduler.report(&a, 2).is_none());
+
+        let second = scheduler.store(&a, 2, scheduler.generation(), Vec::new());
+        assert_ne!(second.result_id, first.result_id);
+        assert!(scheduler.report(&a, 1).is_none());
+
+        scheduler.forget(&a);
+        assert!(scheduler.report(&a, 2).is_none());
+    }
+
+    #[test]
+    fn invalidated_reports() {
+        let scheduler = Scheduler::default();
+        let a = uri("a.fish");
+        scheduler.store(&a, 1, scheduler.generation(), Vec::new());
+        let generation = scheduler.generation();
+        scheduler.invalidate();
+        assert!(scheduler.report(&a, 1).is_none());
+
+        // A run that started before the change is not kept.
+        scheduler.store(&a, 1, generation, Vec::new());
+        assert!(scheduler.report(&a, 1).is_none());
+        scheduler.store(&a, 1, scheduler.generation(), Vec::new());
+        assert!(scheduler.report(&a, 1).is_some());
+    }
+}
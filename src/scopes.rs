@@ -0,0 +1,357 @@
+//! Variable scoping.
+//!
+//! Fish variables live in the innermost block for `set -l`, in the enclosing
+//! function for `set -f`, and globally for `set -g`/`set -U`. A plain `set`
+//! reuses the scope of a variable that is already visible and otherwise
+//! defines a function-scoped (or, outside functions, global) variable.
+//! Function bodies do not see the locals of the blocks around them, and
+//! `set -l` outside any block defines a variable of the file alone.
+
+use crate::{
+    parser::{command_args, header_words, word_value, Node, SyntaxKind, SyntaxTree, TextRange},
+    symbols::{is_command, Scope, SetCommand},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Set,
+    Read,
+    For,
+    Argument,
+}
+
+/// One place that gives a variable a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    /// The variable name inside the defining statement.
+    pub name_range: TextRange,
+    /// The whole defining statement.
+    pub statement: TextRange,
+    /// The block the variable lives in: the root range for the locals of the
+    /// file's top level, and [`GLOBAL`] for globals.
+    pub scope: TextRange,
+}
+
+/// The scope of global and universal variables, which every file shares. No
+/// block of any file has this range.
+const GLOBAL: TextRange = TextRange {
+    start: 0,
+    end: usize::MAX,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ScopeMap {
+    pub bindings: Vec<Binding>,
+}
+
+impl ScopeMap {
+    pub fn build(tree: &SyntaxTree) -> Self {
+        let src = tree.text();
+        let mut map = ScopeMap::default();
+
+        for node in tree.root().descendants() {
+            match node.kind {
+                SyntaxKind::Command => {
+                    if let Some(set) =
+                        SetCommand::parse(node, src).filter(|set| set.is_assignment())
+                    {
+                        let (word, name) = set.name.clone().unwrap();
+                        let scope = map.scope_for(tree, set.scope(), &name, node.range.start);
+                        map.push(BindingKind::Set, name, word.range, node.range, scope);
+                    } else if is_command(node, src, "read") {
+                        map.read_bindings(tree, node);
+                    }
+                }
+                SyntaxKind::ForLoop => {
+                    if let Some(word) = header_words(node).next() {
+                        if let Some(name) = word_value(word, src) {
+                            let scope = map.scope_for(tree, None, &name, node.range.start);
+                            map.push(BindingKind::For, name, word.range, node.range, scope);
+                        }
+                    }
+                }
+                SyntaxKind::FunctionDef => {
+                    for (word, name) in function_arguments(node, src) {
+                        map.push(
+                            BindingKind::Argument,
+                            name,
+                            word.range,
+                            node.range,
+                            node.range,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        map
+    }
+
+    fn push(
+        &mut self,
+        kind: BindingKind,
+        name: String,
+        name_range: TextRange,
+        statement: TextRange,
+        scope: TextRange,
+    ) {
+        let name_range = TextRange::new(
+            name_range.start,
+            (name_range.start + name.len()).min(name_range.end),
+        );
+        self.bindings.push(Binding {
+            name,
+            kind,
+            name_range,
+            statement,
+            scope,
+        });
+    }
+
+    fn read_bindings(&mut self, tree: &SyntaxTree, command: &Node) {
+        let src = tree.text();
+        let mut scope = None;
+        let mut args = command_args(command);
+        let mut names = Vec::new();
+        while let Some(word) = args.next() {
+            let Some(value) = word_value(word, src) else {
+                continue;
+            };
+            match value.as_str() {
+                "-l" | "--local" => scope = Some(Scope::Local),
+                "-f" | "--function" => scope = Some(Scope::Function),
+                "-g" | "--global" => scope = Some(Scope::Global),
+                "-U" | "--universal" => scope = Some(Scope::Universal),
+                // Options that take a value.
+                "-c" | "--command" | "-d" | "--delimiter" | "-n" | "--nchars" | "-p"
+                | "--prompt" | "-P" | "--prompt-str" | "-R" | "--right-prompt" => {
+                    args.next();
+                }
+                option if option.starts_with('-') => {}
+                _ => names.push((word, value)),
+            }
+        }
+
+        for (word, name) in names {
+            let scope = self.scope_for(tree, scope, &name, command.range.start);
+            self.push(BindingKind::Read, name, word.range, command.range, scope);
+        }
+    }
+
+    fn scope_for(
+        &self,
+        tree: &SyntaxTree,
+        scope: Option<Scope>,
+        name: &str,
+        offset: usize,
+    ) -> TextRange {
+        let chain = scope_chain(tree, offset);
+        match scope {
+            Some(Scope::Local) => chain[0],
+            Some(Scope::Function) => enclosing_function(tree, offset).unwrap_or(GLOBAL),
+            Some(Scope::Global | Scope::Universal) => GLOBAL,
+            None => match self.resolve(tree, name, offset) {
+                Some(binding) => binding.scope,
+                None => enclosing_function(tree, offset).unwrap_or(GLOBAL),
+            },
+        }
+    }
+
+    /// The binding that a use of `name` at `offset` refers to: the nearest
+    /// preceding definition in the innermost visible scope that has one.
+    /// Functions may run after the rest of the script, so inside one a global
+    /// defined anywhere counts.
+    pub fn resolve(&self, tree: &SyntaxTree, name: &str, offset: usize) -> Option<&Binding> {
+        let mut chain = scope_chain(tree, offset);
+        chain.push(GLOBAL);
+        let in_function = enclosing_function(tree, offset).is_some();
+
+        for scope in chain {
+            let mut candidates = self
+                .bindings
+                .iter()
+                .filter(|binding| binding.name == name && binding.scope == scope);
+            let preceding = candidates
+                .clone()
+                .rev()
+                .find(|binding| binding.statement.start <= offset);
+            let later = candidates.next().filter(|_| scope == GLOBAL && in_function);
+            if let Some(binding) = preceding.or(later) {
+                return Some(binding);
+            }
+        }
+        None
+    }
+
+    /// The scope a use of `name` at `offset` belongs to, which identifies the
+    /// variable: uses with the same name and scope are the same variable.
+    pub fn scope_of_use(&self, tree: &SyntaxTree, name: &str, offset: usize) -> TextRange {
+        self.resolve(tree, name, offset)
+            .map_or(GLOBAL, |binding| binding.scope)
+    }
+
+    pub fn global_scope(&self) -> TextRange {
+        GLOBAL
+    }
+}
+
+/// Blocks enclosing `offset` whose locals are visible there, innermost
+/// first. Stops at the first function; outside functions it ends with the
+/// root, whose range is the scope of the file's top level.
+pub fn scope_chain(tree: &SyntaxTree, offset: usize) -> Vec<TextRange> {
+    let path = tree.root().path_at(offset);
+    let mut chain = Vec::new();
+    for node in path.iter().rev() {
+        if node.kind.is_block() {
+            chain.push(node.range);
+            if node.kind == SyntaxKind::FunctionDef {
+                return chain;
+            }
+        }
+    }
+    chain.push(tree.root().range);
+    chain
+}
+
+fn enclosing_function(tree: &SyntaxTree, offset: usize) -> Option<TextRange> {
+    tree.root()
+        .path_at(offset)
+        .iter()
+        .rev()
+        .find(|node| node.kind == SyntaxKind::FunctionDef)
+        .map(|node| node.range)
+}
+
+/// Names given by `function`'s `-a`/`--argument-names` and
+/// `-V`/`--inherit-variable` options.
+pub fn function_arguments<'a>(function: &'a Node, src: &str) -> Vec<(&'a Node, String)> {
+    let mut names = Vec::new();
+    let mut collecting = false;
+    let mut words = header_words(function).skip(1);
+    while let Some(word) = words.next() {
+        let Some(value) = word_value(word, src) else {
+            collecting = false;
+            continue;
+        };
+        match value.as_str() {
+            "-a" | "--argument-names" => collecting = true,
+            "-V" | "--inherit-variable" => {
+                if let Some(word) = words.next() {
+                    if let Some(name) = word_value(word, src) {
+                        names.push((word, name));
+                    }
+                }
+                collecting = false;
+            }
+            option if option.starts_with('-') => {
+                collecting = false;
+                // Options that take a value.
+                if matches!(
+                    option,
+                    "-d" | "--description"
+                        | "-w"
+                        | "--wraps"
+                        | "-e"
+                        | "--on-event"
+                        | "-v"
+                        | "--on-variable"
+                        | "-j"
+                        | "--on-job-exit"
+                        | "-p"
+                        | "--on-process-exit"
+                        | "-s"
+                        | "--on-signal"
+                ) {
+                    words.next();
+                }
+            }
+            _ if collecting => names.push((word, value)),
+            _ => {}
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    /// The scope of each binding in `text`: `file`, `global`, or the first
+    /// line of the block it belongs to.
+    fn scopes(text: &str) -> Vec<(String, String)> {
+        let tree = parse(text);
+        let map = ScopeMap::build(&tree);
+        map.bindings
+            .iter()
+            .map(|binding| {
+                let scope = if binding.scope == map.global_scope() {
+                    "global".to_string()
+                } else if binding.scope == tree.root().range {
+                    "file".to_string()
+                } else {
+                    let block = &text[binding.scope.start..binding.scope.end];
+                    block.lines().next().unwrap().to_string()
+                };
+                (binding.name.clone(), scope)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn top_level_locals_belong_to_the_file() {
+        assert_eq!(
+            scopes("set -l a 1\nset -g b 2\nset c 3\nset -U d 4\nset -l c 5\n"),
+            [
+                ("a".into(), "file".into()),
+                ("b".into(), "global".into()),
+                ("c".into(), "global".into()),
+                ("d".into(), "global".into()),
+                ("c".into(), "file".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_and_functions() {
+        let text = "\
+set -l top 1
+function f -a arg
+    set plain 1
+    if true
+        set -l inner 1
+        set top 2
+    end
+    for item in $argv
+    end
+end
+";
+        assert_eq!(
+            scopes(text),
+            [
+                ("top".into(), "file".into()),
+                ("arg".into(), "function f -a arg".into()),
+                ("plain".into(), "function f -a arg".into()),
+                ("inner".into(), "if true".into()),
+                ("top".into(), "function f -a arg".into()),
+                ("item".into(), "function f -a arg".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_through_the_file_scope() {
+        let text = "set -l x 1\nbegin\n    echo $x\nend\nset -g y 1\necho $y\n";
+        let tree = parse(text);
+        let map = ScopeMap::build(&tree);
+        let use_of = |name: &str| text.rfind(&format!("${name}")).unwrap() + 1;
+        assert_eq!(map.scope_of_use(&tree, "x", use_of("x")), tree.root().range);
+        assert_eq!(
+            map.scope_of_use(&tree, "y", use_of("y")),
+            map.global_scope()
+        );
+        assert_eq!(map.scope_of_use(&tree, "z", text.len()), map.global_scope());
+    }
+}
@@ -1,36 +1,136 @@
 use {
-    crate::cli::Cli,
+    crate::{
+        autoload::{path_executables, AutoloadPaths},
+        cli::Cli,
+        config::{Config, PROJECT_FILE},
+        document::Document,
+        fish::Version,
+        scheduler::{Report, Scheduler, DEBOUNCE},
+        workspace::Workspace,
+    },
     anyhow::Result as Anyhow,
     clap::Parser,
-    regex::Regex,
-    std::{str::FromStr, sync::Arc},
+    std::{
+        collections::{HashMap, HashSet},
+        hash::{DefaultHasher, Hash, Hasher},
+        path::PathBuf,
+        sync::Arc,
+        time::Duration,
+    },
     tokio::sync::RwLock,
-    tower_lsp::{jsonrpc::Result, lsp_types::*, Client, LanguageServer, LspService, Server},
+    tokio_util::sync::CancellationToken,
+    tower_lsp::{
+        jsonrpc::{Error, Result},
+        lsp_types::*,
+        Client, LanguageServer, LspService, Server,
+    },
 };
 
 #[derive(Debug, Clone)]
 pub struct Backend {
     client: Client,
     state: Arc<RwLock<State>>,
+    scheduler: Arc<Scheduler>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct State {
-    pub custom_funcs: Vec<String>,
-    pub custom_vars: Vec<String>,
+    pub documents: HashMap<Url, Document>,
+    pub workspace_folders: Vec<PathBuf>,
+    pub workspace: Workspace,
+    pub client_capabilities: ClientCapabilities,
+    /// The client's own settings, before project files are applied.
+    pub settings: Option<serde_json::Value>,
+    pub config: Config,
+    pub autoload: AutoloadPaths,
+    /// Executables on `$PATH` when the server started.
+    pub executables: HashSet<String>,
+    /// The fish release documents are checked against, when known.
+    pub fish_version: Option<Version>,
+}
+
+impl State {
+    /// Whether the client applies workspace edits that create, rename or
+    /// delete files, as `kind` does.
+    pub fn supports_resource_operation(&self, kind: ResourceOperationKind) -> bool {
+        let workspace_edit = self
+            .client_capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref());
+        workspace_edit.is_some_and(|edit| {
+            edit.document_changes == Some(true)
+                && edit
+                    .resource_operations
+                    .as_ref()
+                    .is_some_and(|operations| operations.contains(&kind))
+        })
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        let executables = tokio::task::spawn_blocking(path_executables)
+            .await
+            .unwrap_or_default();
+
+        let mut state = self.state.write().await;
+        state.workspace_folders = folders
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+        state.client_capabilities = params.capabilities;
+        state.settings = params.initialization_options;
+        state.executables = executables;
+        drop(state);
+        self.load_config().await;
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some(crate::diagnostics::SOURCE.to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: Default::default(),
+                    },
                 )),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
-                    trigger_characters: Some(vec![".".to_string()]),
+                    trigger_characters: Some(vec![
+                        ".".to_string(),
+                        "$".to_string(),
+                        "/".to_string(),
+                    ]),
                     ..Default::default()
                 }),
                 ..Default::default()
@@ -43,6 +143,14 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "Fish Language Server initialized!")
             .await;
+
+        crate::workspace::watch_files(&self.client, &self.state).await;
+        let folders = self.state.read().await.workspace_folders.clone();
+        let backend = self.clone();
+        tokio::spawn(async move {
+            crate::workspace::index(backend.client.clone(), backend.state.clone(), folders).await;
+            backend.check_open_documents().await;
+        });
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -50,120 +158,452 @@ impl LanguageServer for Backend {
     }
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let comps = crate::completions::get_completions(self.state.clone()).await;
+        let comps = crate::completions::get_completions(self.state.clone(), &params).await;
 
         Ok(Some(CompletionResponse::Array(comps)))
     }
 
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let state = self.state.read().await;
+
+        Ok(crate::hover::hover(
+            &state,
+            &position.text_document.uri,
+            position.position,
+        ))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let state = self.state.read().await;
+        let locations =
+            crate::definition::definition(&state, &position.text_document.uri, position.position);
+
+        Ok((!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations)))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let position = params.text_document_position;
+        let state = self.state.read().await;
+        let locations = crate::references::references(
+            &state,
+            &position.text_document.uri,
+            position.position,
+            params.context.include_declaration,
+        );
+
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let position = params.text_document_position_params;
+        let state = self.state.read().await;
+        let highlights = crate::references::document_highlights(
+            &state,
+            &position.text_document.uri,
+            position.position,
+        );
+
+        Ok(Some(highlights))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let state = self.state.read().await;
+        let symbols = state
+            .documents
+            .get(&params.text_document.uri)
+            .map(|document| crate::outline::document_symbols(&document.tree));
+
+        Ok(symbols.map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let state = self.state.read().await;
+
+        Ok(Some(crate::workspace::workspace_symbols(
+            &state,
+            &params.query,
+        )))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let state = self.state.read().await;
+        let range =
+            crate::rename::prepare_rename(&state, &params.text_document.uri, params.position);
+
+        Ok(range.map(PrepareRenameResponse::Range))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let position = params.text_document_position;
+        let state = self.state.read().await;
+
+        crate::rename::rename(
+            &state,
+            &position.text_document.uri,
+            position.position,
+            &params.new_name,
+        )
+        .map_err(|error| Error::invalid_params(error.to_string()))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let state = self.state.read().await;
+
+        Ok(Some(crate::actions::code_actions(&state, &params)))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let state = self.state.read().await;
+        let edits = state
+            .documents
+            .get(&params.text_document.uri)
+            .and_then(|document| {
+                crate::formatting::format_document(&document.tree, &params.options)
+            });
+
+        Ok(edits)
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let state = self.state.read().await;
+        let edits = state
+            .documents
+            .get(&params.text_document.uri)
+            .and_then(|document| {
+                let range = document.tree.line_index().text_range(params.range);
+                crate::formatting::format_range(&document.tree, &params.options, range)
+            });
+
+        Ok(edits)
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let params_clone = params.clone();
+        let document = params.text_document;
+        let uri = document.uri;
 
-        self.check_syntax(params.text_document.text).await;
-        self.update_custom_funcs(params_clone.text_document.text)
-            .await;
+        self.state
+            .write()
+            .await
+            .documents
+            .insert(uri.clone(), Document::new(document.text, document.version));
+        self.schedule_check(uri, Duration::ZERO).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let content = params
-            .content_changes
-            .into_iter()
-            .next()
-            .map(|change| change.text)
-            .unwrap_or_default();
+        let uri = params.text_document.uri;
 
-        let sssniperwolf = content.clone();
+        match self.state.write().await.documents.get_mut(&uri) {
+            Some(document) => {
+                document.apply_changes(params.content_changes, params.text_document.version)
+            }
+            None => return,
+        }
+        self.schedule_check(uri, DEBOUNCE).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
 
-        self.check_syntax(content).await;
-        self.update_custom_funcs(sssniperwolf).await;
+        self.state.write().await.documents.remove(&uri);
+        self.scheduler.forget(&uri);
+        crate::workspace::refresh(&self.state, &uri).await;
+        if !self.pulls_diagnostics().await {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
     }
-}
 
-impl Backend {
-    async fn check_syntax(&self, text: String) {
-        let mut diagnostics: Vec<Diagnostic> = Vec::new();
-        let function_re = Regex::new(r"(?m)^function\s+\w+\s*(?:\n.*)*?\nend\s*$").unwrap();
-        let variable_re = Regex::new(r#"(?m)^set\s+\w+(\s+\"[^\"]*\")*\s*$"#).unwrap();
-
-        for (i, line) in text.lines().enumerate() {
-            if line.starts_with("function ") && !function_re.is_match(&text) {
-                diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position {
-                            line: i as u32,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: i as u32,
-                            character: line.len() as u32,
-                        },
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let project_changed = params.changes.iter().any(|event| {
+            event
+                .uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                == Some(PROJECT_FILE)
+        });
+        crate::workspace::apply_file_events(&self.state, params.changes).await;
+        if project_changed {
+            self.load_config().await;
+        }
+        self.check_open_documents().await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Some clients nest the settings under the server's name.
+        let settings = match params.settings {
+            serde_json::Value::Object(mut settings) if settings.contains_key("fish-lsp") => {
+                settings.remove("fish-lsp")
+            }
+            settings => Some(settings),
+        };
+        self.state.write().await.settings = settings;
+        self.load_config().await;
+        self.check_open_documents().await;
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        // Pulls are not cancelled by edits; the client cancels the request
+        // itself when it no longer needs the answer.
+        let report = self
+            .analyze(&params.text_document.uri, &CancellationToken::new())
+            .await;
+        let report = match report {
+            Some(report) if params.previous_result_id.as_ref() == Some(&report.result_id) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: report.result_id,
                     },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: Some(NumberOrString::String(
-                        "INVALID_FUNCTION_DECLARATION".into(),
-                    )),
-                    source: Some("fish-lsp".into()),
-                    message: "Functions must be declared as `function <name>` followed by `end`"
-                        .into(),
-                    ..Default::default()
                 })
             }
-        }
-
-        for (i, line) in text.lines().enumerate() {
-            if line.starts_with("set ") && !variable_re.is_match(line) {
-                diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position {
-                            line: i as u32,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: i as u32,
-                            character: line.len() as u32,
-                        },
+            report => {
+                let (result_id, items) = report
+                    .map(|report| (Some(report.result_id), report.diagnostics))
+                    .unwrap_or_default();
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id,
+                        items,
                     },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: Some(NumberOrString::String(
-                        "INVALID_VARIABLE_DECLARATION".into(),
-                    )),
-                    source: Some("fish-language-server".into()),
-                    message: "Variables must be declared as `set <name> \"<value>\"`".into(),
-                    ..Default::default()
-                });
+                })
             }
+        };
+
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous: HashMap<Url, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect();
+        let open: Vec<Url> = self.state.read().await.documents.keys().cloned().collect();
+
+        let mut items = Vec::new();
+        for uri in open {
+            let Some(report) = self.analyze(&uri, &CancellationToken::new()).await else {
+                continue;
+            };
+            let version = Some(report.version as i64);
+            items.push(workspace_report(
+                uri,
+                version,
+                report.result_id,
+                report.diagnostics,
+                &previous,
+            ));
         }
 
-        self.client
-            .publish_diagnostics(
-                Url::from_str("file://dummy.fish").unwrap(),
-                diagnostics,
+        // Files that are not open are checked by the server alone, and named
+        // after their text so an unchanged file is not sent again.
+        let state = self.state.read().await;
+        let generation = self.scheduler.generation();
+        for (uri, file) in &state.workspace.files {
+            if state.documents.contains_key(uri) {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            file.tree.text().hash(&mut hasher);
+            let result_id = format!("{generation}-{:x}", hasher.finish());
+            let diagnostics = if previous.get(uri) == Some(&result_id) {
+                Vec::new()
+            } else {
+                let mut diagnostics = crate::diagnostics::diagnostics(&state, uri, &file.tree);
+                crate::suppressions::apply(&state.config, &file.tree, &mut diagnostics);
+                diagnostics
+            };
+            items.push(workspace_report(
+                uri.clone(),
                 None,
-            )
-            .await;
+                result_id,
+                diagnostics,
+                &previous,
+            ));
+        }
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
     }
+}
 
-    async fn update_custom_funcs(&self, text: String) {
-        let function_re = Regex::new(r"(?m)^function\s+(\w+)").unwrap();
-        let variable_re = Regex::new(r"(?m)^set\s+(\w+)\s+").unwrap();
+/// A full report for a document, or an unchanged one when the client already
+/// has `result_id`.
+fn workspace_report(
+    uri: Url,
+    version: Option<i64>,
+    result_id: String,
+    items: Vec<Diagnostic>,
+    previous: &HashMap<Url, String>,
+) -> WorkspaceDocumentDiagnosticReport {
+    if previous.get(&uri) == Some(&result_id) {
+        return WorkspaceDocumentDiagnosticReport::Unchanged(
+            WorkspaceUnchangedDocumentDiagnosticReport {
+                uri,
+                version,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            },
+        );
+    }
+    WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+        uri,
+        version,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+            result_id: Some(result_id),
+            items,
+        },
+    })
+}
 
-        let mut custom_functions = Vec::new();
-        let mut custom_variables = Vec::new();
+impl Backend {
+    /// Check a document once it has gone `delay` without edits, cancelling
+    /// the check still pending or running for it. Clients that pull
+    /// diagnostics ask for them instead.
+    async fn schedule_check(&self, uri: Url, delay: Duration) {
+        if self.pulls_diagnostics().await {
+            return;
+        }
 
-        for cap in function_re.captures_iter(&text) {
-            if let Some(function_name) = cap.get(1) {
-                custom_functions.push(function_name.as_str().to_string());
+        let token = self.scheduler.start(&uri);
+        let backend = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = tokio::time::sleep(delay) => {}
             }
+            if let Some(report) = backend.analyze(&uri, &token).await {
+                backend.publish_diagnostics(&uri, report, &token).await;
+            }
+        });
+    }
+
+    /// The diagnostics of the current version of a document, reusing the
+    /// stored report when nothing changed. `None` once `token` is cancelled or
+    /// the document is not open.
+    async fn analyze(&self, uri: &Url, token: &CancellationToken) -> Option<Report> {
+        let state = self.state.read().await;
+        let document = state.documents.get(uri)?;
+        let version = document.version;
+        if let Some(report) = self.scheduler.report(uri, version) {
+            return Some(report);
         }
 
-        for cap in variable_re.captures_iter(&text) {
-            if let Some(variable_name) = cap.get(1) {
-                custom_variables.push(variable_name.as_str().to_string());
-            }
+        let generation = self.scheduler.generation();
+        let mut diagnostics = crate::diagnostics::diagnostics(&state, uri, &document.tree);
+        let fish = crate::fish::fish_binary(&state.config, &state.executables);
+        let tree = document.tree.clone();
+        let config = state.config.clone();
+        drop(state);
+
+        if let Some(fish) = fish {
+            let mut found = tokio::select! {
+                _ = token.cancelled() => return None,
+                found = crate::fish::check(&fish, &tree) => found,
+            };
+            crate::lints::configure(&config, &mut found);
+            crate::fish::merge(&mut diagnostics, found);
         }
+        crate::suppressions::apply(&config, &tree, &mut diagnostics);
+        if token.is_cancelled() {
+            return None;
+        }
+        Some(self.scheduler.store(uri, version, generation, diagnostics))
+    }
 
+    /// Combine the client's settings with the project files into the
+    /// configuration, and follow any change to the autoload paths.
+    async fn load_config(&self) {
         let mut state = self.state.write().await;
-        state.custom_funcs = custom_functions;
-        state.custom_vars = custom_variables;
+        let (config, problems) = Config::load(state.settings.as_ref(), &state.workspace_folders);
+        state.config = config;
+        state.autoload = AutoloadPaths::new(&state.workspace_folders, &state.config);
+        let (config, executables) = (state.config.clone(), state.executables.clone());
+        drop(state);
+
+        for problem in problems {
+            self.client.log_message(MessageType::WARNING, problem).await;
+        }
+
+        let fish_version = crate::fish::version(&config, &executables).await;
+        self.state.write().await.fish_version = fish_version;
+    }
+
+    /// Check every open document again, after something they may depend on
+    /// such as the workspace index changed.
+    async fn check_open_documents(&self) {
+        self.scheduler.invalidate();
+        if self.pulls_diagnostics().await {
+            if self.refreshes_diagnostics().await {
+                let _ = self.client.workspace_diagnostic_refresh().await;
+            }
+            return;
+        }
+
+        let uris: Vec<Url> = self.state.read().await.documents.keys().cloned().collect();
+        for uri in uris {
+            self.schedule_check(uri, Duration::ZERO).await;
+        }
+    }
+
+    /// Whether the client asks for diagnostics rather than waiting for them to
+    /// be published.
+    async fn pulls_diagnostics(&self) -> bool {
+        let state = self.state.read().await;
+        let text_document = state.client_capabilities.text_document.as_ref();
+        text_document.is_some_and(|text_document| text_document.diagnostic.is_some())
+    }
+
+    async fn refreshes_diagnostics(&self) -> bool {
+        let state = self.state.read().await;
+        let workspace = state.client_capabilities.workspace.as_ref();
+        workspace
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false)
+    }
+
+    /// Publish a report, unless the document has been changed or closed or a
+    /// newer check started since. The state lock is held until the
+    /// notification is queued so a newer run can never be overtaken.
+    async fn publish_diagnostics(&self, uri: &Url, report: Report, token: &CancellationToken) {
+        let state = self.state.read().await;
+        let current = state.documents.get(uri).map(|document| document.version);
+        if current != Some(report.version) || token.is_cancelled() {
+            return;
+        }
+
+        self.client
+            .publish_diagnostics(uri.clone(), report.diagnostics, Some(report.version))
+            .await;
     }
 }
 
@@ -173,7 +613,11 @@ pub async fn run_server() -> Anyhow<()> {
     let stdout = tokio::io::stdout();
     let state = Arc::new(RwLock::new(State::default()));
 
-    let (service, socket) = LspService::new(|client| Backend { client, state });
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        state,
+        scheduler: Default::default(),
+    });
 
     Server::new(stdin, stdout, socket).serve(service).await;
 
@@ -0,0 +1,671 @@
+//! Comments that silence diagnostics by lint code:
+//!
+//! - `# fish-lsp: disable-line CODE` for the statement the comment ends,
+//! - `# fish-lsp: disable-next-line CODE` for the statement after it,
+//! - `# fish-lsp: disable CODE` up to `# fish-lsp: enable CODE` or the end of
+//!   the file,
+//! - `# fish-lsp: disable-file CODE` for the whole file.
+//!
+//! Without codes a comment silences everything. Text after `--` explains the
+//! suppression and is ignored. A statement spans several lines when a quoted
+//! string, a `\` continuation or a trailing `|`, `&&` or `||` carries it on.
+
+use {
+    crate::{
+        config::{Config, Severity},
+        lints::{self, INVALID_SUPPRESSION, LINTS, UNUSED_SUPPRESSION},
+        parser::{SyntaxKind, SyntaxTree, TextRange},
+    },
+    std::ops::Range,
+    tower_lsp::lsp_types::*,
+};
+
+const PREFIX: &str = "fish-lsp:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Directive {
+    DisableLine,
+    DisableNextLine,
+    Disable,
+    Enable,
+    DisableFile,
+}
+
+impl Directive {
+    fn parse(word: &str) -> Option<Self> {
+        Some(match word {
+            "disable-line" => Directive::DisableLine,
+            "disable-next-line" => Directive::DisableNextLine,
+            "disable" => Directive::Disable,
+            "enable" => Directive::Enable,
+            "disable-file" => Directive::DisableFile,
+            _ => return None,
+        })
+    }
+}
+
+/// A suppression comment. `codes` is `None` when the comment names none, and
+/// holds only the codes of known lints otherwise.
+#[derive(Debug)]
+struct Comment {
+    directive: Directive,
+    range: TextRange,
+    line: usize,
+    codes: Option<Vec<(String, TextRange)>>,
+}
+
+/// The lines one comment silences, for one code or for all of them.
+#[derive(Debug)]
+struct Rule {
+    lines: Range<usize>,
+    code: Option<String>,
+    /// What to point at when the rule silences nothing.
+    origin: TextRange,
+    used: bool,
+}
+
+/// Drop the diagnostics that suppression comments silence, and report the
+/// comments that are malformed or silence nothing.
+pub fn apply(config: &Config, tree: &SyntaxTree, diagnostics: &mut Vec<Diagnostic>) {
+    let mut problems = Vec::new();
+    let comments = comments(tree, &mut problems);
+    let mut rules = rules(tree, &comments, &mut problems);
+
+    diagnostics.retain(|diagnostic| {
+        let line = diagnostic.range.start.line as usize;
+        let code = match &diagnostic.code {
+            Some(NumberOrString::String(code)) => code.as_str(),
+            _ => "",
+        };
+        let mut suppressed = false;
+        for rule in rules.iter_mut().filter(|rule| rule.lines.contains(&line)) {
+            if rule
+                .code
+                .as_ref()
+                .is_none_or(|rule_code| rule_code.eq_ignore_ascii_case(code))
+            {
+                rule.used = true;
+                suppressed = true;
+            }
+        }
+        !suppressed
+    });
+
+    for rule in rules.iter().filter(|rule| !rule.used) {
+        // Lints turned off have nothing to silence, but the comment may still
+        // be needed by someone who turns them on.
+        let lint = rule.code.as_deref().and_then(lints::find);
+        if lint.is_some_and(|lint| config.severity(lint) == Severity::Off) {
+            continue;
+        }
+        let message = match &rule.code {
+            Some(code) => format!("Nothing here reports `{code}`"),
+            None => "This comment silences no diagnostics".to_string(),
+        };
+        problems.push(Diagnostic {
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..UNUSED_SUPPRESSION.diagnostic(tree.lsp_range(rule.origin), message)
+        });
+    }
+
+    lints::configure(config, &mut problems);
+    diagnostics.extend(problems);
+}
+
+/// Every `# fish-lsp:` comment, reporting unknown directives and codes.
+fn comments(tree: &SyntaxTree, problems: &mut Vec<Diagnostic>) -> Vec<Comment> {
+    let src = tree.text();
+    let mut comments = Vec::new();
+
+    for token in tree.root().tokens() {
+        if token.kind != SyntaxKind::Comment {
+            continue;
+        }
+        let mut words = words(token.text(src), token.range.start)
+            .into_iter()
+            .skip_while(|(word, _)| word.chars().all(|c| c == '#'));
+        let Some((prefix, prefix_range)) = words.next() else {
+            continue;
+        };
+        let Some(rest) = prefix.trim_start_matches('#').strip_prefix(PREFIX) else {
+            continue;
+        };
+        // `#fish-lsp:disable` has no space after the colon.
+        let (word, range) = match rest {
+            "" => match words.next() {
+                Some(word) => word,
+                None => continue,
+            },
+            _ => (rest, prefix_range),
+        };
+        let Some(directive) = Directive::parse(word) else {
+            problems.push(INVALID_SUPPRESSION.diagnostic(
+                tree.lsp_range(range),
+                format!(
+                    "Unknown directive `{word}`: expected disable-line, disable-next-line, \
+                     disable, enable or disable-file"
+                ),
+            ));
+            continue;
+        };
+
+        let mut codes = Vec::new();
+        let mut named = false;
+        for (code, range) in words.take_while(|(word, _)| *word != "--") {
+            named = true;
+            let Some(lint) = lints::find(code) else {
+                let suggestion = closest_code(code)
+                    .map(|closest| format!("; did you mean `{closest}`?"))
+                    .unwrap_or_default();
+                problems.push(INVALID_SUPPRESSION.diagnostic(
+                    tree.lsp_range(range),
+                    format!("Unknown lint code `{code}`{suggestion}"),
+                ));
+                continue;
+            };
+            codes.push((lint.code.to_string(), range));
+        }
+
+        comments.push(Comment {
+            directive,
+            range: token.range,
+            line: tree.line_index().line_of(token.range.start),
+            codes: named.then_some(codes),
+        });
+    }
+    comments
+}
+
+/// Turn comments into the lines they silence, pairing each `disable` with
+/// the `enable` that ends it.
+fn rules(tree: &SyntaxTree, comments: &[Comment], problems: &mut Vec<Diagnostic>) -> Vec<Rule> {
+    let line_count = tree.line_index().line_count();
+    let starts = statement_starts(tree);
+    let mut rules: Vec<Rule> = Vec::new();
+    // Regions still open, by index into `rules`.
+    let mut open: Vec<usize> = Vec::new();
+
+    for comment in comments {
+        let lines = match comment.directive {
+            Directive::DisableLine => statement_lines(&starts, comment.line),
+            Directive::DisableNextLine if comment.line + 1 < line_count => {
+                statement_lines(&starts, comment.line + 1)
+            }
+            Directive::DisableNextLine => comment.line + 1..comment.line + 1,
+            Directive::Disable => comment.line + 1..line_count,
+            Directive::DisableFile => 0..line_count,
+            Directive::Enable => {
+                let closing: Vec<usize> = match &comment.codes {
+                    None => std::mem::take(&mut open),
+                    Some(codes) => codes
+                        .iter()
+                        .filter_map(|(code, range)| {
+                            let position = open
+                                .iter()
+                                .position(|&index| rules[index].code.as_ref() == Some(code));
+                            if position.is_none() {
+                                problems.push(INVALID_SUPPRESSION.diagnostic(
+                                    tree.lsp_range(*range),
+                                    format!("`{code}` is not disabled here"),
+                                ));
+                            }
+                            position.map(|position| open.remove(position))
+                        })
+                        .collect(),
+                };
+                if comment.codes.is_none() && closing.is_empty() {
+                    problems.push(INVALID_SUPPRESSION.diagnostic(
+                        tree.lsp_range(comment.range),
+                        "No `disable` comment is open here",
+                    ));
+                }
+                for index in closing {
+                    rules[index].lines.end = comment.line;
+                }
+                continue;
+            }
+        };
+
+        let codes: Vec<(Option<String>, TextRange)> = match &comment.codes {
+            None => vec![(None, comment.range)],
+            Some(codes) => codes
+                .iter()
+                .map(|(code, range)| (Some(code.clone()), *range))
+                .collect(),
+        };
+        for (code, origin) in codes {
+            if comment.directive == Directive::Disable {
+                open.push(rules.len());
+            }
+            rules.push(Rule {
+                lines: lines.clone(),
+                code,
+                origin,
+                used: false,
+            });
+        }
+    }
+    rules
+}
+
+/// The whitespace separated words of `text` with their ranges, given that
+/// `text` starts at byte `offset`.
+fn words(text: &str, offset: usize) -> Vec<(&str, TextRange)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(index),
+            (Some(begin), true) => {
+                words.push((
+                    &text[begin..index],
+                    TextRange::new(offset + begin, offset + index),
+                ));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Whether each line starts a statement, rather than carrying one on from
+/// the line before.
+fn statement_starts(tree: &SyntaxTree) -> Vec<bool> {
+    let lines = tree.line_index();
+    let mut starts = vec![false; lines.line_count()];
+    starts[0] = true;
+    let mut last = None;
+    for token in tree.root().tokens() {
+        match token.kind {
+            SyntaxKind::Newline => {
+                let joined = matches!(
+                    last,
+                    Some(SyntaxKind::Pipe | SyntaxKind::AndAnd | SyntaxKind::OrOr)
+                );
+                if !joined {
+                    starts[lines.line_of(token.range.end)] = true;
+                }
+            }
+            kind if kind.is_trivia() => {}
+            kind => last = Some(kind),
+        }
+    }
+    starts
+}
+
+/// The lines of the statement that `line` belongs to.
+fn statement_lines(starts: &[bool], line: usize) -> Range<usize> {
+    let first = (0..=line).rev().find(|&line| starts[line]).unwrap_or(0);
+    let end = (line + 1..starts.len())
+        .find(|&line| starts[line])
+        .unwrap_or(starts.len());
+    first..end
+}
+
+/// The lint code `code` is most likely a misspelling of.
+fn closest_code(code: &str) -> Option<&'static str> {
+    let code = code.to_uppercase();
+    LINTS
+        .iter()
+        .map(|lint| (edit_distance(&code, lint.code), lint.code))
+        .filter(|(distance, _)| *distance <= 3)
+        .min()
+        .map(|(_, code)| code)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Quick fixes that silence `diagnostic`, on its line or in the whole file.
+pub fn suppress_actions(uri: &Url, tree: &SyntaxTree, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+    let Some(NumberOrString::String(code)) = &diagnostic.code else {
+        return Vec::new();
+    };
+    if [UNUSED_SUPPRESSION.code, INVALID_SUPPRESSION.code].contains(&code.as_str()) {
+        return Vec::new();
+    }
+    let lines = tree.line_index();
+    let line = diagnostic.range.start.line as usize;
+    if line >= lines.line_count() {
+        return Vec::new();
+    }
+
+    // Above the statement, where the comment cannot land inside a string or
+    // cut a continued command short. A `disable-next-line` already there
+    // would then silence the new comment instead, so end the statement with a
+    // `disable-line` in that case.
+    let statement = statement_lines(&statement_starts(tree), line);
+    let first = lines.line_range(statement.start);
+    let last = lines.line_range(statement.end - 1);
+    let text = &tree.text()[first.start..first.end];
+    let indent = &text[..text.len() - text.trim_start().len()];
+    let stacked = comments(tree, &mut Vec::new()).iter().any(|comment| {
+        comment.directive == Directive::DisableNextLine && comment.line + 1 == statement.start
+    });
+    let ends_in_comment = tree
+        .root()
+        .tokens()
+        .iter()
+        .any(|token| token.kind == SyntaxKind::Comment && token.range.end == last.end);
+    let statement_edit = if !stacked {
+        Some(TextEdit {
+            range: tree.lsp_range(TextRange::empty(first.start)),
+            new_text: format!("{indent}# {PREFIX} disable-next-line {code}\n"),
+        })
+    } else if !ends_in_comment {
+        Some(TextEdit {
+            range: tree.lsp_range(TextRange::empty(last.end)),
+            new_text: format!(" # {PREFIX} disable-line {code}"),
+        })
+    } else {
+        None
+    };
+
+    // Keep a shebang on the first line.
+    let first_line = lines.line_range(0);
+    let top = if !tree.text().starts_with("#!") {
+        0
+    } else if lines.line_count() > 1 {
+        lines.line_range(1).start
+    } else {
+        first_line.end
+    };
+    let separator = if top == first_line.end { "\n" } else { "" };
+    let file = TextEdit {
+        range: tree.lsp_range(TextRange::empty(top)),
+        new_text: format!("{separator}# {PREFIX} disable-file {code}\n"),
+    };
+
+    let statement_action =
+        statement_edit.map(|edit| (format!("Suppress {code} on this line"), edit));
+    statement_action
+        .into_iter()
+        .chain([(format!("Suppress {code} in this file"), file)])
+        .map(|(title, edit)| CodeAction {
+            title,
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(WorkspaceEdit {
+                changes: Some([(uri.clone(), vec![edit])].into_iter().collect()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            config::LintSetting,
+            lints::{Lint, UNDEFINED_VARIABLE, UNKNOWN_COMMAND},
+            parser::parse,
+        },
+    };
+
+    /// Apply the suppression comments in `text` to diagnostics on the given
+    /// lines, returning the line and code of every diagnostic left.
+    fn check_with(config: &Config, text: &str, reported: &[(u32, &Lint)]) -> Vec<(u32, String)> {
+        let tree = parse(text);
+        let mut diagnostics = reported
+            .iter()
+            .map(|(line, lint)| {
+                let position = Position::new(*line, 0);
+                lint.diagnostic(
+                    tower_lsp::lsp_types::Range::new(position, position),
+                    String::new(),
+                )
+            })
+            .collect();
+        apply(config, &tree, &mut diagnostics);
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let code = match diagnostic.code {
+                    Some(NumberOrString::String(code)) => code,
+                    _ => String::new(),
+                };
+                (diagnostic.range.start.line, code)
+            })
+            .collect()
+    }
+
+    fn check(text: &str, reported: &[(u32, &Lint)]) -> Vec<(u32, String)> {
+        check_with(&Config::default(), text, reported)
+    }
+
+    fn left(diagnostics: &[(u32, &str)]) -> Vec<(u32, String)> {
+        diagnostics
+            .iter()
+            .map(|(line, code)| (*line, code.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn line_comments() {
+        let text = "\
+legacy-tool # fish-lsp: disable-line UNKNOWN_COMMAND
+# fish-lsp: disable-next-line unknown_command -- installed by CI
+other-tool $x
+third-tool
+";
+        let reported = [
+            (0, &UNKNOWN_COMMAND),
+            (2, &UNKNOWN_COMMAND),
+            (2, &UNDEFINED_VARIABLE),
+            (3, &UNKNOWN_COMMAND),
+        ];
+        assert_eq!(
+            check(text, &reported),
+            left(&[(2, "UNDEFINED_VARIABLE"), (3, "UNKNOWN_COMMAND")])
+        );
+    }
+
+    #[test]
+    fn regions() {
+        let text = "\
+a
+#fish-lsp:disable UNKNOWN_COMMAND UNDEFINED_VARIABLE
+b
+# fish-lsp: enable UNKNOWN_COMMAND
+c
+";
+        let reported = [
+            (0, &UNKNOWN_COMMAND),
+            (2, &UNKNOWN_COMMAND),
+            (4, &UNKNOWN_COMMAND),
+            (4, &UNDEFINED_VARIABLE),
+        ];
+        assert_eq!(
+            check(text, &reported),
+            left(&[(0, "UNKNOWN_COMMAND"), (4, "UNKNOWN_COMMAND")])
+        );
+    }
+
+    #[test]
+    fn whole_file() {
+        let text = "a\nb\n# fish-lsp: disable-file\n";
+        let reported = [(0, &UNKNOWN_COMMAND), (1, &UNDEFINED_VARIABLE)];
+        assert_eq!(check(text, &reported), []);
+    }
+
+    #[test]
+    fn unused_suppressions() {
+        let text = "\
+a # fish-lsp: disable-line UNDEFINED_VARIABLE
+# fish-lsp: disable-next-line
+b
+";
+        assert_eq!(
+            check(text, &[]),
+            left(&[(0, "UNUSED_SUPPRESSION"), (1, "UNUSED_SUPPRESSION")])
+        );
+
+        // Lints turned off have nothing to silence.
+        let config = Config {
+            lints: [("UNDEFINED_VARIABLE".into(), LintSetting::Enabled(false))].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_with(
+                &config,
+                "a # fish-lsp: disable-line UNDEFINED_VARIABLE\n",
+                &[]
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn invalid_suppressions() {
+        let text = "\
+a # fish-lsp: ignore UNKNOWN_COMMAND
+# fish-lsp: disable-next-line UNKNOWN_COMAND
+b
+# fish-lsp: enable
+# fish-lsp: enable UNDEFINED_VARIABLE
+";
+        let tree = parse(text);
+        let mut diagnostics = Vec::new();
+        apply(&Config::default(), &tree, &mut diagnostics);
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Unknown directive `ignore`: expected disable-line, disable-next-line, disable, \
+                 enable or disable-file",
+                "Unknown lint code `UNKNOWN_COMAND`; did you mean `UNKNOWN_COMMAND`?",
+                "No `disable` comment is open here",
+                "`UNDEFINED_VARIABLE` is not disabled here",
+            ]
+        );
+    }
+
+    #[test]
+    fn quick_fixes() {
+        let uri = Url::parse("file:///project/script.fish").unwrap();
+        let text = "#!/usr/bin/env fish\nif true\n    tool\nend\n";
+        let tree = parse(text);
+        let position = Position::new(2, 4);
+        let diagnostic = UNKNOWN_COMMAND.diagnostic(
+            tower_lsp::lsp_types::Range::new(position, position),
+            String::new(),
+        );
+        let edits: Vec<_> = suppress_actions(&uri, &tree, &diagnostic)
+            .into_iter()
+            .map(|action| {
+                let edit = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+                (
+                    action.title,
+                    edit[0].range.start.line,
+                    edit[0].new_text.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            edits,
+            [
+                (
+                    "Suppress UNKNOWN_COMMAND on this line".to_string(),
+                    2,
+                    "    # fish-lsp: disable-next-line UNKNOWN_COMMAND\n".to_string()
+                ),
+                (
+                    "Suppress UNKNOWN_COMMAND in this file".to_string(),
+                    1,
+                    "# fish-lsp: disable-file UNKNOWN_COMMAND\n".to_string()
+                ),
+            ]
+        );
+
+        let unused =
+            UNUSED_SUPPRESSION.diagnostic(tower_lsp::lsp_types::Range::default(), String::new());
+        assert!(suppress_actions(&uri, &tree, &unused).is_empty());
+    }
+
+    /// The text after taking the "on this line" fix for `lint` on `line`.
+    fn suppress_statement(text: &str, line: u32, lint: &Lint) -> String {
+        let uri = Url::parse("file:///project/script.fish").unwrap();
+        let tree = parse(text);
+        let position = Position::new(line, 0);
+        let diagnostic = lint.diagnostic(
+            tower_lsp::lsp_types::Range::new(position, position),
+            String::new(),
+        );
+        let action = suppress_actions(&uri, &tree, &diagnostic).remove(0);
+        assert_eq!(action.title, format!("Suppress {} on this line", lint.code));
+        let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+        let offset = tree.line_index().offset(edit.range.start);
+        format!("{}{}{}", &text[..offset], edit.new_text, &text[offset..])
+    }
+
+    #[test]
+    fn quick_fixes_above_multiline_statements() {
+        let string = "echo \"first\n$undefined second\"\n";
+        let fixed = suppress_statement(string, 1, &UNDEFINED_VARIABLE);
+        assert_eq!(
+            fixed,
+            "# fish-lsp: disable-next-line UNDEFINED_VARIABLE\necho \"first\n$undefined second\"\n"
+        );
+        assert_eq!(check(&fixed, &[(2, &UNDEFINED_VARIABLE)]), []);
+
+        let continued = "function f\n    set -l x \\\n        $undefined\nend\n";
+        let fixed = suppress_statement(continued, 2, &UNDEFINED_VARIABLE);
+        assert_eq!(
+            fixed,
+            "function f\n    # fish-lsp: disable-next-line UNDEFINED_VARIABLE\n    set -l x \\\n        $undefined\nend\n"
+        );
+        assert_eq!(check(&fixed, &[(3, &UNDEFINED_VARIABLE)]), []);
+
+        let piped = "cat file |\n    tool\n";
+        let fixed = suppress_statement(piped, 1, &UNKNOWN_COMMAND);
+        assert_eq!(
+            fixed,
+            "# fish-lsp: disable-next-line UNKNOWN_COMMAND\ncat file |\n    tool\n"
+        );
+        assert_eq!(check(&fixed, &[(2, &UNKNOWN_COMMAND)]), []);
+    }
+
+    #[test]
+    fn quick_fixes_under_another_suppression() {
+        let text = "# fish-lsp: disable-next-line UNKNOWN_COMMAND\ntool \"$undefined\n\"\n";
+        let fixed = suppress_statement(text, 1, &UNDEFINED_VARIABLE);
+        assert_eq!(
+            fixed,
+            "# fish-lsp: disable-next-line UNKNOWN_COMMAND\ntool \"$undefined\n\" # fish-lsp: disable-line UNDEFINED_VARIABLE\n"
+        );
+        let reported = [(1, &UNKNOWN_COMMAND), (1, &UNDEFINED_VARIABLE)];
+        assert_eq!(check(&fixed, &reported), []);
+
+        // Nowhere to put it when the statement ends in a comment.
+        let uri = Url::parse("file:///project/script.fish").unwrap();
+        let tree = parse("# fish-lsp: disable-next-line UNKNOWN_COMMAND\ntool $undefined # note\n");
+        let position = Position::new(1, 5);
+        let diagnostic = UNDEFINED_VARIABLE.diagnostic(
+            tower_lsp::lsp_types::Range::new(position, position),
+            String::new(),
+        );
+        let titles: Vec<_> = suppress_actions(&uri, &tree, &diagnostic)
+            .into_iter()
+            .map(|action| action.title)
+            .collect();
+        assert_eq!(titles, ["Suppress UNDEFINED_VARIABLE in this file"]);
+    }
+}
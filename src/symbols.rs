@@ -0,0 +1,510 @@
+use crate::{
+    parser::{
+        command_args, command_name, header_words, word_value, Node, SyntaxKind, SyntaxTree,
+        TextRange,
+    },
+    scopes::ScopeMap,
+};
+
+/// Functions, variables, abbreviations and completions defined by one
+/// document.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    /// Functions, including those defined by `alias`.
+    pub functions: Vec<Symbol>,
+    pub variables: Vec<Symbol>,
+    /// The variables assigned in the global or universal scope.
+    pub globals: Vec<Symbol>,
+    pub abbreviations: Vec<Symbol>,
+    /// One entry per command that `complete` registers completions for.
+    pub completions: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// The whole defining statement.
+    pub range: TextRange,
+    /// Just the name inside the definition.
+    pub selection_range: TextRange,
+}
+
+impl Symbols {
+    pub fn extract(tree: &SyntaxTree) -> Self {
+        let src = tree.text();
+        let scopes = ScopeMap::build(tree);
+        let mut symbols = Symbols::default();
+
+        for node in tree.root().descendants() {
+            match node.kind {
+                SyntaxKind::FunctionDef => {
+                    if let Some((word, name)) = function_name(node, src) {
+                        symbols.functions.push(Symbol {
+                            name,
+                            range: node.range,
+                            selection_range: word.range,
+                        });
+                    }
+                }
+                SyntaxKind::ForLoop => {
+                    if let Some(word) = header_words(node).next() {
+                        if let Some(name) = word_value(word, src) {
+                            symbols.variables.push(Symbol {
+                                name,
+                                range: node.range,
+                                selection_range: word.range,
+                            });
+                        }
+                    }
+                }
+                SyntaxKind::Command => symbols.extract_command(node, src, &scopes),
+                _ => {}
+            }
+        }
+
+        symbols
+    }
+
+    fn extract_command(&mut self, command: &Node, src: &str, scopes: &ScopeMap) {
+        let set = SetCommand::parse(command, src).filter(SetCommand::is_assignment);
+        if let Some((word, name)) = set.and_then(|set| set.name) {
+            let symbol = Symbol {
+                name,
+                range: command.range,
+                selection_range: word.range,
+            };
+            let global = scopes.bindings.iter().any(|binding| {
+                binding.statement == command.range && binding.scope == scopes.global_scope()
+            });
+            if global {
+                self.globals.push(symbol.clone());
+            }
+            self.variables.push(symbol);
+        } else if let Some((word, name, _)) = abbr_definition(command, src) {
+            self.abbreviations.push(Symbol {
+                name,
+                range: command.range,
+                selection_range: word.range,
+            });
+        } else if let Some((word, name, _)) = alias_definition(command, src) {
+            self.functions.push(Symbol {
+                name,
+                range: command.range,
+                selection_range: word.range,
+            });
+        } else if let Some(complete) = CompleteCommand::parse(command, src) {
+            let (word, name) = complete.command;
+            if !self.completions.iter().any(|symbol| symbol.name == name) {
+                self.completions.push(Symbol {
+                    name,
+                    range: command.range,
+                    selection_range: word.range,
+                });
+            }
+        }
+    }
+}
+
+/// The name word of a `function` definition and its value.
+pub fn function_name<'a>(function: &'a Node, src: &str) -> Option<(&'a Node, String)> {
+    let word = header_words(function).next()?;
+    Some((word, word_value(word, src)?))
+}
+
+pub fn is_command(command: &Node, src: &str, name: &str) -> bool {
+    command_name(command)
+        .and_then(|word| word_value(word, src))
+        .is_some_and(|value| value == name)
+}
+
+/// The variable named by a `set` command, skipping its options and any index
+/// such as `list[2]`.
+pub fn set_variable_name<'a>(command: &'a Node, src: &str) -> Option<(&'a Node, String)> {
+    SetCommand::parse(command, src)?.name
+}
+
+/// Variable names consist of letters, digits and underscores.
+pub fn is_valid_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Local,
+    Function,
+    Global,
+    Universal,
+}
+
+/// A `set` invocation split into its options, variable name and values.
+#[derive(Debug, Clone)]
+pub struct SetCommand<'a> {
+    pub command: &'a Node,
+    /// Option words in order, with their unescaped text.
+    pub options: Vec<(&'a Node, String)>,
+    /// The word naming the variable, and the name without any index.
+    pub name: Option<(&'a Node, String)>,
+    /// Every word after the name. For `--erase` and `--query` these are more
+    /// variable names rather than values.
+    pub values: Vec<&'a Node>,
+}
+
+impl<'a> SetCommand<'a> {
+    pub fn parse(command: &'a Node, src: &str) -> Option<Self> {
+        if !is_command(command, src, "set") {
+            return None;
+        }
+
+        let mut options = Vec::new();
+        let mut args = command_args(command).peekable();
+        while let Some(word) = args.peek() {
+            match word_value(word, src) {
+                Some(value) if value == "--" => {
+                    args.next();
+                    break;
+                }
+                Some(value) if value.starts_with('-') && value.len() > 1 => {
+                    options.push((*word, value));
+                    args.next();
+                }
+                _ => break,
+            }
+        }
+
+        let name = args.next().and_then(|word| {
+            let value = word_value(word, src)?;
+            let name = value.split('[').next().unwrap_or_default().to_string();
+            Some((word, name))
+        });
+
+        Some(Self {
+            command,
+            options,
+            name,
+            values: args.collect(),
+        })
+    }
+
+    /// Whether the short flag `short` or the long option `--long` was given.
+    pub fn has_flag(&self, short: char, long: &str) -> bool {
+        self.options
+            .iter()
+            .any(|(_, option)| match option.strip_prefix("--") {
+                Some(name) => name.split('=').next() == Some(long),
+                None => option[1..].contains(short),
+            })
+    }
+
+    pub fn scope(&self) -> Option<Scope> {
+        if self.has_flag('l', "local") {
+            Some(Scope::Local)
+        } else if self.has_flag('f', "function") {
+            Some(Scope::Function)
+        } else if self.has_flag('g', "global") {
+            Some(Scope::Global)
+        } else if self.has_flag('U', "universal") {
+            Some(Scope::Universal)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_erase(&self) -> bool {
+        self.has_flag('e', "erase")
+    }
+
+    pub fn is_query(&self) -> bool {
+        self.has_flag('q', "query")
+    }
+
+    /// Whether this `set` assigns a value, as opposed to erasing, querying or
+    /// listing variables.
+    pub fn is_assignment(&self) -> bool {
+        self.name.is_some()
+            && !self.is_erase()
+            && !self.is_query()
+            && !self.has_flag('n', "names")
+            && !self.has_flag('S', "show")
+    }
+}
+
+/// The value of a `function`'s `-d`/`--description` option.
+pub fn function_description(function: &Node, src: &str) -> Option<String> {
+    let mut words = header_words(function).skip(1);
+    while let Some(word) = words.next() {
+        let value = word_value(word, src)?;
+        if let Some(description) = value.strip_prefix("--description=") {
+            return Some(description.to_string());
+        }
+        if value == "-d" || value == "--description" {
+            return words.next().and_then(|word| word_value(word, src));
+        }
+        if let Some(description) = value.strip_prefix("-d").filter(|rest| !rest.is_empty()) {
+            return Some(description.to_string());
+        }
+    }
+    None
+}
+
+/// The block of `#` comment lines directly above `node`, without the `#`s.
+pub fn leading_comment(tree: &SyntaxTree, node: &Node) -> Option<String> {
+    let index = tree.line_index();
+    let mut line = index.line_of(node.range.start);
+    let mut lines = Vec::new();
+
+    while line > 0 {
+        line -= 1;
+        let range = index.line_range(line);
+        let text = tree.text()[range.start..range.end].trim();
+        let Some(comment) = text.strip_prefix('#') else {
+            break;
+        };
+        lines.push(comment.strip_prefix(' ').unwrap_or(comment).trim_end());
+    }
+
+    lines.reverse();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// What the cursor is on, as far as symbol lookups are concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolRef {
+    /// A command name, a function definition's name or a keyword.
+    Command { name: String, range: TextRange },
+    /// A variable expansion or a variable named by `set`, `read` or `for`.
+    Variable { name: String, range: TextRange },
+}
+
+impl SymbolRef {
+    pub fn name(&self) -> &str {
+        match self {
+            SymbolRef::Command { name, .. } | SymbolRef::Variable { name, .. } => name,
+        }
+    }
+
+    pub fn range(&self) -> TextRange {
+        match self {
+            SymbolRef::Command { range, .. } | SymbolRef::Variable { range, .. } => *range,
+        }
+    }
+}
+
+pub fn symbol_at(tree: &SyntaxTree, offset: usize) -> Option<SymbolRef> {
+    let src = tree.text();
+    let path = tree.root().path_at(offset);
+
+    for (index, node) in path.iter().enumerate().rev() {
+        match node.kind {
+            SyntaxKind::Variable => {
+                let name = node.first_token(SyntaxKind::VariableName)?;
+                return Some(SymbolRef::Variable {
+                    name: name.text(src).to_string(),
+                    range: name.range,
+                });
+            }
+            SyntaxKind::Word => {
+                let parent = path.get(index.wrapping_sub(1))?;
+                return word_symbol(parent, node, src);
+            }
+            _ => {}
+        }
+    }
+
+    let token = tree.root().token_at(offset)?;
+    (token.kind == SyntaxKind::Keyword).then(|| SymbolRef::Command {
+        name: token.text(src).to_string(),
+        range: token.range,
+    })
+}
+
+fn word_symbol(parent: &Node, word: &Node, src: &str) -> Option<SymbolRef> {
+    let name = word_value(word, src)?;
+    match parent.kind {
+        SyntaxKind::Command if command_name(parent).is_some_and(|n| n.range == word.range) => {
+            Some(SymbolRef::Command {
+                name,
+                range: word.range,
+            })
+        }
+        SyntaxKind::Command => {
+            let set = SetCommand::parse(parent, src)?;
+            let named = set.name.iter().map(|(word, _)| *word);
+            let erased = set
+                .values
+                .iter()
+                .copied()
+                .filter(|_| set.is_erase() || set.is_query());
+            named.chain(erased).find(|w| w.range == word.range)?;
+            let name = name.split('[').next().unwrap_or_default().to_string();
+            Some(SymbolRef::Variable {
+                range: TextRange::new(word.range.start, word.range.start + name.len()),
+                name,
+            })
+        }
+        SyntaxKind::FunctionDef if header_words(parent).next() == Some(word) => {
+            Some(SymbolRef::Command {
+                name,
+                range: word.range,
+            })
+        }
+        SyntaxKind::ForLoop if header_words(parent).next() == Some(word) => {
+            Some(SymbolRef::Variable {
+                name,
+                range: word.range,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The event handler options of a `function`, e.g. `--on-event fish_prompt`,
+/// normalised to their long form.
+pub fn function_events(function: &Node, src: &str) -> Vec<String> {
+    const EVENTS: &[(&str, &str)] = &[
+        ("-e", "--on-event"),
+        ("-v", "--on-variable"),
+        ("-j", "--on-job-exit"),
+        ("-p", "--on-process-exit"),
+        ("-s", "--on-signal"),
+    ];
+
+    let mut events = Vec::new();
+    let mut words = header_words(function).skip(1);
+    while let Some(word) = words.next() {
+        let Some(value) = word_value(word, src) else {
+            continue;
+        };
+        for (short, long) in EVENTS {
+            if let Some(argument) = value
+                .strip_prefix(long)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                events.push(format!("{long} {argument}"));
+            } else if value == *short || value == *long {
+                if let Some(argument) = words.next().and_then(|word| word_value(word, src)) {
+                    events.push(format!("{long} {argument}"));
+                }
+            }
+        }
+    }
+    events
+}
+
+/// The name word of an `abbr` command that adds an abbreviation, with the
+/// name and the expansion as written.
+pub fn abbr_definition<'a>(
+    command: &'a Node,
+    src: &str,
+) -> Option<(&'a Node, String, Option<String>)> {
+    if !is_command(command, src, "abbr") {
+        return None;
+    }
+
+    let mut args = command_args(command);
+    let mut positional = Vec::new();
+    while let Some(word) = args.next() {
+        let value = word_value(word, src);
+        match value.as_deref() {
+            Some("-e" | "--erase" | "-r" | "--rename" | "-s" | "--show" | "-l" | "--list")
+            | Some("-q" | "--query") => return None,
+            Some("-p" | "--position" | "--regex" | "-f" | "--function" | "-c" | "--command") => {
+                args.next();
+            }
+            Some(option) if option.starts_with('-') => {}
+            _ => positional.push((word, value)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let (word, name) = positional.next()?;
+    let expansion: Vec<&str> = positional.map(|(word, _)| word.text(src)).collect();
+    Some((
+        word,
+        name?,
+        (!expansion.is_empty()).then(|| expansion.join(" ")),
+    ))
+}
+
+/// The name word of an `alias NAME=BODY` or `alias NAME BODY` command, with
+/// the name and body.
+pub fn alias_definition<'a>(command: &'a Node, src: &str) -> Option<(&'a Node, String, String)> {
+    if !is_command(command, src, "alias") {
+        return None;
+    }
+
+    let mut args = command_args(command)
+        .filter(|word| !word_value(word, src).is_some_and(|value| value.starts_with('-')));
+    let word = args.next()?;
+    let value = word_value(word, src)?;
+    match value.split_once('=') {
+        Some((name, body)) => Some((word, name.to_string(), body.to_string())),
+        None => {
+            let body = args
+                .next()
+                .map_or_else(String::new, |word| word.text(src).to_string());
+            Some((word, value, body))
+        }
+    }
+}
+
+/// A `complete` command that registers completions for a command.
+#[derive(Debug, Clone)]
+pub struct CompleteCommand<'a> {
+    /// The word giving the completed command, and the command's name.
+    pub command: (&'a Node, String),
+    /// The `-s`, `-l` and `-o` options being completed, as typed, e.g. `--help`.
+    pub options: Vec<String>,
+    pub description: Option<String>,
+}
+
+impl<'a> CompleteCommand<'a> {
+    pub fn parse(command: &'a Node, src: &str) -> Option<Self> {
+        if !is_command(command, src, "complete") {
+            return None;
+        }
+
+        let mut target = None;
+        let mut options = Vec::new();
+        let mut description = None;
+        let mut args = command_args(command);
+        while let Some(word) = args.next() {
+            let Some(value) = word_value(word, src) else {
+                continue;
+            };
+            let (option, inline) = match value.split_once('=') {
+                Some((option, inline)) if option.starts_with("--") => (option, Some(inline)),
+                _ => (value.as_str(), None),
+            };
+            let mut argument = || match inline {
+                Some(inline) => Some((word, inline.to_string())),
+                None => args
+                    .next()
+                    .and_then(|word| Some((word, word_value(word, src)?))),
+            };
+            match option {
+                "-c" | "--command" | "-p" | "--path" => target = target.or(argument()),
+                "-e" | "--erase" => return None,
+                "-s" | "--short-option" => {
+                    options.extend(argument().map(|(_, name)| format!("-{name}")))
+                }
+                "-o" | "--old-option" => {
+                    options.extend(argument().map(|(_, name)| format!("-{name}")))
+                }
+                "-l" | "--long-option" => {
+                    options.extend(argument().map(|(_, name)| format!("--{name}")))
+                }
+                "-d" | "--description" => description = argument().map(|(_, text)| text),
+                "-a" | "--arguments" | "-w" | "--wraps" | "-n" | "--condition" => {
+                    argument();
+                }
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            command: target?,
+            options,
+            description,
+        })
+    }
+}
@@ -0,0 +1,1220 @@
+//! Translation of bash snippets to fish, offered as a refactoring for the
+//! selected lines.
+//!
+//! Statements the translation cannot express are kept as comments under a
+//! `TODO` naming the construct, and the action's title lists them.
+
+use {
+    crate::parser::{
+        bash::{self, AndOr, Command, Item, ItemKind, Pipeline},
+        SyntaxTree, TextRange,
+    },
+    tower_lsp::lsp_types::*,
+};
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub text: String,
+    /// The constructs left for translating by hand, each once.
+    pub untranslated: Vec<&'static str>,
+}
+
+type Translated = Result<String, &'static str>;
+
+/// Translate the bash statements in `src` to fish.
+pub fn translate(src: &str) -> Translation {
+    let mut translator = Translator::new(src);
+    let mut lines = Vec::new();
+    translator.items(&bash::parse(src), 0, &mut lines);
+    Translation {
+        text: lines.join("\n"),
+        untranslated: translator.untranslated,
+    }
+}
+
+/// The fish spelling of the single bash expansion `text`, like `$?` or
+/// `${name%.txt}`, for use inside double quotes if `quoted`.
+pub fn expansion(text: &str, quoted: bool) -> Option<String> {
+    let (spelling, len, substitution) = Translator::new(text).dollar(text, quoted).ok()?;
+    if len != text.len() {
+        return None;
+    }
+    Some(match quoted && substitution {
+        true => format!("\"{spelling}\""),
+        false => spelling,
+    })
+}
+
+/// A refactoring replacing the lines `range` touches with their translation,
+/// if that changes anything.
+pub fn translate_action(uri: &Url, tree: &SyntaxTree, range: Range) -> Option<CodeAction> {
+    if range.start == range.end {
+        return None;
+    }
+    let lines = tree.line_index();
+    let last_line = lines.line_count().checked_sub(1)?;
+    let start_line = (range.start.line as usize).min(last_line);
+    let mut end_line = (range.end.line as usize).min(last_line);
+    if range.end.character == 0 && end_line > start_line {
+        end_line -= 1;
+    }
+    let span = TextRange::new(
+        lines.line_range(start_line).start,
+        lines.line_range(end_line).end,
+    );
+    let src = &tree.text()[span.start..span.end];
+    let indent = &src[..src.len() - src.trim_start().len()];
+    let indent = &indent[indent.rfind('\n').map_or(0, |newline| newline + 1)..];
+
+    let translation = translate(src);
+    let new_text = translation
+        .text
+        .lines()
+        .map(|line| match line {
+            "" => String::new(),
+            line => format!("{indent}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Offer nothing for text that is fish already or has nothing to translate.
+    let original: Vec<&str> = src.lines().map(str::trim).collect();
+    let changed = translation
+        .text
+        .lines()
+        .map(str::trim)
+        .any(|line| !line.is_empty() && !line.starts_with("# ") && !original.contains(&line));
+    if !changed {
+        return None;
+    }
+
+    let title = match translation.untranslated.as_slice() {
+        [] => "Translate bash to fish".to_string(),
+        untranslated => format!(
+            "Translate bash to fish (could not translate: {})",
+            untranslated.join(", ")
+        ),
+    };
+    let edit = TextEdit {
+        range: tree.lsp_range(span),
+        new_text,
+    };
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some([(uri.clone(), vec![edit])].into_iter().collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+struct Translator<'a> {
+    src: &'a str,
+    untranslated: Vec<&'static str>,
+    /// Whether the statements being translated are in a function body, where
+    /// bash assignments still set globals.
+    in_function: bool,
+    /// The variables declared `local` in that function.
+    locals: Vec<String>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            untranslated: Vec::new(),
+            in_function: false,
+            locals: Vec::new(),
+        }
+    }
+
+    fn items(&mut self, items: &[Item], depth: usize, out: &mut Vec<String>) {
+        let indent = INDENT.repeat(depth);
+        for item in items {
+            let translated = match &item.kind {
+                ItemKind::Blank => Ok(String::new()),
+                ItemKind::Comment(comment) => Ok(comment.clone()),
+                ItemKind::Unsupported(what) => Err(*what),
+                ItemKind::List(list) => self.and_or(list, depth),
+            };
+            match translated {
+                Ok(text) if text.is_empty() => out.push(text),
+                Ok(text) => out.push(format!("{indent}{text}")),
+                Err(what) => self.untranslatable(item, what, &indent, out),
+            }
+        }
+    }
+
+    /// Keep `item` as a comment for translating by hand.
+    fn untranslatable(
+        &mut self,
+        item: &Item,
+        what: &'static str,
+        indent: &str,
+        out: &mut Vec<String>,
+    ) {
+        if !self.untranslated.contains(&what) {
+            self.untranslated.push(what);
+        }
+        out.push(format!("{indent}# TODO: translate by hand ({what}):"));
+        let text = &self.src[item.span.clone()];
+        let margin = text
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        for (index, line) in text.lines().enumerate() {
+            let line = match index {
+                0 => line.trim_start(),
+                _ => &line[margin.min(line.len() - line.trim_start().len())..],
+            };
+            out.push(format!("{indent}# {line}").trim_end().to_string());
+        }
+    }
+
+    /// The body of a block, one line per statement, each starting with a
+    /// newline.
+    fn block(&mut self, items: &[Item], depth: usize) -> String {
+        let mut lines = Vec::new();
+        self.items(items, depth, &mut lines);
+        lines.iter().map(|line| format!("\n{line}")).collect()
+    }
+
+    fn and_or(&mut self, list: &AndOr, depth: usize) -> Translated {
+        let mut text = self.pipeline(&list.first, depth)?;
+        for (op, pipeline) in &list.rest {
+            text = format!("{text} {op} {}", self.pipeline(pipeline, depth)?);
+        }
+        if list.background {
+            text.push_str(" &");
+        }
+        if let Some(comment) = &list.comment {
+            text = format!("{text}  {comment}");
+        }
+        Ok(text)
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline, depth: usize) -> Translated {
+        let mut text = String::new();
+        if pipeline.negated {
+            text.push_str("not ");
+        }
+        for (command, op) in &pipeline.commands {
+            text.push_str(&self.command(command, depth)?);
+            match op {
+                Some("|&") => text.push_str(" &| "),
+                Some(_) => text.push_str(" | "),
+                None => {}
+            }
+        }
+        Ok(text)
+    }
+
+    fn command(&mut self, command: &Command, depth: usize) -> Translated {
+        let indent = INDENT.repeat(depth);
+        Ok(match command {
+            Command::Simple {
+                assignments,
+                words,
+                redirects,
+            } => self.simple(assignments, words, redirects, depth)?,
+            Command::Test(words) => self.test(words)?,
+            Command::If {
+                branches,
+                otherwise,
+            } => {
+                let mut text = String::new();
+                for (index, (condition, body)) in branches.iter().enumerate() {
+                    if index > 0 {
+                        text.push_str(&format!("\n{indent}else "));
+                    }
+                    text.push_str(&format!("if {}", self.condition(condition, depth)?));
+                    text.push_str(&self.block(body, depth + 1));
+                }
+                if let Some(otherwise) = otherwise {
+                    text.push_str(&format!("\n{indent}else"));
+                    text.push_str(&self.block(otherwise, depth + 1));
+                }
+                format!("{text}\n{indent}end")
+            }
+            Command::For {
+                variable,
+                items,
+                body,
+            } => {
+                let items = match items {
+                    Some(items) => self.words(items)?.join(" "),
+                    None => "$argv".to_string(),
+                };
+                let body = self.block(body, depth + 1);
+                format!("for {variable} in {items}{body}\n{indent}end")
+            }
+            Command::While {
+                until,
+                condition,
+                body,
+            } => {
+                let mut condition_text = self.condition(condition, depth)?;
+                if *until {
+                    let single = match condition.as_slice() {
+                        [Item {
+                            kind: ItemKind::List(list),
+                            ..
+                        }] => list.rest.is_empty(),
+                        _ => true,
+                    };
+                    condition_text = match single {
+                        true => format!("not {condition_text}"),
+                        false => format!("not begin; {condition_text}; end"),
+                    };
+                }
+                let body = self.block(body, depth + 1);
+                format!("while {condition_text}{body}\n{indent}end")
+            }
+            Command::Case { word, arms } => {
+                let mut text = format!("switch {}", self.word(word)?);
+                for (patterns, body) in arms {
+                    let patterns = patterns
+                        .iter()
+                        .map(|pattern| self.pattern(pattern))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    text.push_str(&format!("\n{indent}{INDENT}case {}", patterns.join(" ")));
+                    text.push_str(&self.block(body, depth + 2));
+                }
+                format!("{text}\n{indent}end")
+            }
+            Command::Function { name, body } => {
+                let in_function = std::mem::replace(&mut self.in_function, true);
+                let locals = std::mem::take(&mut self.locals);
+                let body = self.block(body, depth + 1);
+                self.in_function = in_function;
+                self.locals = locals;
+                format!("function {name}{body}\n{indent}end")
+            }
+            Command::Group(body) => {
+                format!("begin{}\n{indent}end", self.block(body, depth + 1))
+            }
+            Command::Subshell(_) => return Err("subshell `( )`"),
+            Command::Redirected { command, redirects } => {
+                let command = self.command(command, depth)?;
+                format!("{command}{}", self.redirections(redirects)?)
+            }
+        })
+    }
+
+    /// The condition of an `if` or `while`, as a single job.
+    fn condition(&mut self, items: &[Item], depth: usize) -> Translated {
+        let mut jobs = Vec::new();
+        for item in items {
+            match &item.kind {
+                ItemKind::List(list) => jobs.push(self.and_or(list, depth)?),
+                ItemKind::Unsupported(what) => return Err(what),
+                ItemKind::Comment(_) | ItemKind::Blank => {}
+            }
+        }
+        match jobs.as_slice() {
+            [] => Err("empty condition"),
+            [job] => Ok(job.clone()),
+            jobs => Ok(format!("begin; {}; end", jobs.join("; "))),
+        }
+    }
+
+    fn simple(
+        &mut self,
+        assignments: &[String],
+        words: &[String],
+        redirects: &[(String, String)],
+        depth: usize,
+    ) -> Translated {
+        let redirections = self.redirections(redirects)?;
+        let Some((name, args)) = words.split_first() else {
+            if assignments.is_empty() {
+                return Err("redirection without a command");
+            }
+            let sets = assignments
+                .iter()
+                .map(|assignment| {
+                    let name = assignment.split(['=', '+', '[']).next().unwrap_or_default();
+                    let scope = match self.in_function && !self.locals.iter().any(|l| l == name) {
+                        true => "-g",
+                        false => "",
+                    };
+                    self.assignment(assignment, scope)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(sets.join("; "));
+        };
+
+        let command = match name.as_str() {
+            "export" => self.export(args)?,
+            "local" | "declare" | "typeset" | "readonly" => self.declare(name, args)?,
+            "unset" => self.unset(args)?,
+            "source" | "." => self.source(args, depth)?,
+            "read" => self.read(args)?,
+            "set" if !args.is_empty() => return Err("`set` of shell options"),
+            "shopt" => return Err("`shopt`"),
+            "let" => return Err("`let` arithmetic"),
+            ":" => {
+                let mut words = vec!["true".to_string()];
+                words.extend(self.words(args)?);
+                words.join(" ")
+            }
+            "[" | "test" => {
+                let words = args
+                    .iter()
+                    .map(|word| match word.as_str() {
+                        "==" => Ok("=".to_string()),
+                        word => self.word(word),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("{name} {}", words.join(" "))
+            }
+            _ => self.words(words)?.join(" "),
+        };
+
+        let mut text = String::new();
+        if !assignments.is_empty() {
+            text.push_str("env ");
+            for assignment in assignments {
+                text.push_str(&self.word(assignment)?);
+                text.push(' ');
+            }
+        }
+        text.push_str(&command);
+        text.push_str(&redirections);
+        Ok(text)
+    }
+
+    /// Redirections to append to a command, each with a leading space.
+    fn redirections(&mut self, redirects: &[(String, String)]) -> Translated {
+        let mut text = String::new();
+        for (op, target) in redirects {
+            let op = match op.as_str() {
+                ">|" => ">",
+                op if op.ends_with("<>") => return Err("read-write redirection `<>`"),
+                op => op,
+            };
+            let separator = if op.ends_with('&') { "" } else { " " };
+            text.push_str(&format!(" {op}{separator}{}", self.word(target)?));
+        }
+        Ok(text)
+    }
+
+    /// `set` for an assignment `NAME=value`, with `scope` options if any.
+    fn assignment(&mut self, assignment: &str, scope: &str) -> Translated {
+        let (target, value) = match assignment.split_once('=') {
+            Some((target, value)) => (target, Some(value)),
+            None => (assignment, None),
+        };
+        let mut options = scope.to_string();
+        let target = match target.strip_suffix('+') {
+            Some(target) => {
+                options.push_str(" -a");
+                target
+            }
+            None => target,
+        };
+        let target = match target.split_once('[') {
+            Some((name, index)) => {
+                let index: usize = index
+                    .trim_end_matches(']')
+                    .parse()
+                    .map_err(|_| "array index that is not a number")?;
+                format!("{name}[{}]", index + 1)
+            }
+            None => target.to_string(),
+        };
+        let options = options.trim();
+        let set = match options {
+            "" => "set".to_string(),
+            options => format!("set {options}"),
+        };
+        Ok(match value {
+            Some(value) => format!("{set} {target} {}", self.value(value)?),
+            None => format!("{set} {target}"),
+        })
+    }
+
+    /// The value of an assignment as arguments to `set`.
+    fn value(&mut self, value: &str) -> Translated {
+        if value.is_empty() {
+            return Ok("\"\"".into());
+        }
+        if value.starts_with('(') {
+            let len = enclosed(value)?;
+            return Ok(self.words(&bash::words(&value[1..len - 1]))?.join(" "));
+        }
+        // Bash never expands globs in assignments; fish does in `set`.
+        if !value.contains(['\'', '"', '$', '`', '\\']) && value.contains(['*', '?', '[']) {
+            return Ok(single_quoted(value));
+        }
+        self.word(value)
+    }
+
+    fn export(&mut self, args: &[String]) -> Translated {
+        if args.iter().any(|arg| arg.starts_with('-')) {
+            return Err("`export` option");
+        }
+        if args.is_empty() {
+            return Ok("set -x".into());
+        }
+        let sets = args
+            .iter()
+            .map(|arg| match arg.contains('=') {
+                true => self.assignment(arg, "-gx"),
+                false => Ok(format!("set -gx {arg} ${arg}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sets.join("; "))
+    }
+
+    fn declare(&mut self, name: &str, args: &[String]) -> Translated {
+        let mut exported = false;
+        let mut global = false;
+        let mut names = Vec::new();
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(options) => {
+                    for option in options.chars() {
+                        match option {
+                            'x' => exported = true,
+                            'g' => global = true,
+                            'A' => return Err("associative array"),
+                            'f' | 'F' => return Err("`declare -f`"),
+                            'n' => return Err("name reference"),
+                            'p' => return Err("`declare -p`"),
+                            _ => {}
+                        }
+                    }
+                }
+                None => names.push(arg),
+            }
+        }
+        if names.is_empty() {
+            return Err("listing variables with `declare`");
+        }
+        let global = global || !self.in_function;
+        if !global {
+            self.locals.extend(names.iter().map(|name| {
+                name.split(['=', '+', '['])
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            }));
+        }
+        let scope = match (global, exported) {
+            (true, true) => "-gx",
+            (true, false) => "-g",
+            (false, true) => "-lx",
+            (false, false) => "-l",
+        };
+        let sets = names
+            .into_iter()
+            .map(|name| self.assignment(name, scope))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sets.join("; "))
+    }
+
+    fn unset(&mut self, args: &[String]) -> Translated {
+        let mut functions = false;
+        let mut names = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "-f" => functions = true,
+                "-v" => functions = false,
+                arg => names.push(self.word(arg)?),
+            }
+        }
+        let erase = if functions { "functions -e" } else { "set -e" };
+        Ok(names
+            .iter()
+            .map(|name| format!("{erase} {name}"))
+            .collect::<Vec<_>>()
+            .join("; "))
+    }
+
+    /// `source`, reading env files of `NAME=value` lines into exported
+    /// variables since fish cannot run them.
+    fn source(&mut self, args: &[String], depth: usize) -> Translated {
+        let Some((file, rest)) = args.split_first() else {
+            return Err("`source` without a file");
+        };
+        let path = file.trim_matches(['\'', '"']);
+        let base = path.rsplit('/').next().unwrap_or(path);
+        let file = self.word(file)?;
+        if base == ".env" || base.starts_with(".env.") || base.ends_with(".env") {
+            let indent = INDENT.repeat(depth);
+            return Ok(format!(
+                "for line in (string match -rv '^\\s*(#|$)' < {file})\n\
+                 {indent}{INDENT}set -l pair (string split -m 1 = -- (string replace -r '^export\\s+' '' -- $line))\n\
+                 {indent}{INDENT}set -gx $pair[1] (string trim -c '\\'\"' -- $pair[2])\n\
+                 {indent}end"
+            ));
+        }
+        if base.ends_with(".sh") || base.ends_with(".bash") {
+            return Err("sourcing a bash script");
+        }
+        let mut words = vec!["source".to_string(), file];
+        words.extend(self.words(rest)?);
+        Ok(words.join(" "))
+    }
+
+    fn read(&mut self, args: &[String]) -> Translated {
+        let mut words = vec!["read".to_string()];
+        let mut names = 0;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(options) = arg.strip_prefix('-').filter(|options| !options.is_empty()) else {
+                words.push(self.word(arg)?);
+                names += 1;
+                continue;
+            };
+            let mut translated = String::new();
+            let mut takes_value = false;
+            for option in options.chars() {
+                match option {
+                    'r' | 'e' => {}
+                    'p' => {
+                        translated.push('P');
+                        takes_value = true;
+                    }
+                    'n' | 'd' => {
+                        translated.push(option);
+                        takes_value = true;
+                    }
+                    'a' | 's' => translated.push(option),
+                    't' => return Err("`read -t` timeout"),
+                    _ => return Err("`read` option"),
+                }
+            }
+            if !translated.is_empty() {
+                words.push(format!("-{translated}"));
+            }
+            if takes_value {
+                if let Some(value) = args.next() {
+                    words.push(self.word(value)?);
+                }
+            }
+        }
+        if names == 0 {
+            words.push("REPLY".into());
+        }
+        Ok(words.join(" "))
+    }
+
+    /// `[[ ... ]]` as `test`, or `string match` for pattern and regex
+    /// matches. Tests joined by `&&` and `||` become separate commands.
+    fn test(&mut self, words: &[String]) -> Translated {
+        if words.iter().any(|word| word == "(" || word == ")") {
+            if words.iter().any(|word| word == "=~" || has_glob(word)) {
+                return Err("pattern match in a grouped `[[ ]]`");
+            }
+            let words = words
+                .iter()
+                .map(|word| match word.as_str() {
+                    "&&" => Ok("-a".to_string()),
+                    "||" => Ok("-o".to_string()),
+                    "(" => Ok("\\(".to_string()),
+                    ")" => Ok("\\)".to_string()),
+                    word => self.test_operand(word),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(format!("test {}", words.join(" ")));
+        }
+        let mut parts = Vec::new();
+        let mut words = words;
+        while let Some(split) = words.iter().position(|word| word == "&&" || word == "||") {
+            parts.push(self.single_test(&words[..split])?);
+            parts.push(words[split].clone());
+            words = &words[split + 1..];
+        }
+        parts.push(self.single_test(words)?);
+        Ok(parts.join(" "))
+    }
+
+    fn single_test(&mut self, words: &[String]) -> Translated {
+        let (negated, words) = match words.split_first() {
+            Some((not, words)) if not == "!" => (true, words),
+            _ => (false, words),
+        };
+        let not = if negated { "not " } else { "" };
+        if let [left, op, right] = words {
+            match op.as_str() {
+                "=~" => {
+                    let left = self.test_operand(left)?;
+                    // A bare `$` in a regex is an anchor, not an expansion.
+                    let expands = right.match_indices('$').any(|(index, _)| {
+                        right[index + 1..]
+                            .starts_with(|c: char| c.is_ascii_alphanumeric() || "_{(".contains(c))
+                    });
+                    let regex = match expands || right.contains(['\'', '"', '`']) {
+                        true => self.word(right)?,
+                        false => single_quoted(right),
+                    };
+                    return Ok(format!("{not}string match -qr -- {regex} {left}"));
+                }
+                "==" | "=" | "!=" if has_glob(right) => {
+                    let left = self.test_operand(left)?;
+                    let pattern = self.pattern(right)?;
+                    let not = if negated != (op == "!=") { "not " } else { "" };
+                    return Ok(format!("{not}string match -q -- {pattern} {left}"));
+                }
+                _ => {}
+            }
+        }
+        let words = words
+            .iter()
+            .map(|word| match word.as_str() {
+                "==" => Ok("=".to_string()),
+                word => self.test_operand(word),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("{not}test {}", words.join(" ")))
+    }
+
+    /// A word in `[[ ]]`, quoting expansions that bash would not split.
+    fn test_operand(&mut self, word: &str) -> Translated {
+        match word.starts_with('$') && !word.contains(['\'', '"']) {
+            true => self.double_quoted(word),
+            false => self.word(word),
+        }
+    }
+
+    /// A `case` or `[[ ]]` pattern, quoted so fish matches rather than
+    /// expands it.
+    fn pattern(&mut self, pattern: &str) -> Translated {
+        if pattern.contains(['\'', '"', '$', '`']) {
+            return self.word(pattern);
+        }
+        Ok(
+            match has_glob(pattern) || pattern.contains(['(', ')', '|', '{', '}']) {
+                true => single_quoted(pattern),
+                false => pattern.to_string(),
+            },
+        )
+    }
+
+    fn words(&mut self, words: &[String]) -> Result<Vec<String>, &'static str> {
+        words.iter().map(|word| self.word(word)).collect()
+    }
+
+    fn word(&mut self, word: &str) -> Translated {
+        let mut text = String::new();
+        let mut rest = word;
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                '\'' => {
+                    let len = enclosed(rest)?;
+                    text.push_str(&rest[..len]);
+                    len
+                }
+                '"' => {
+                    let len = enclosed(rest)?;
+                    let inner = &rest[1..len.max(2) - 1];
+                    text.push_str(&self.double_quoted(inner)?);
+                    len
+                }
+                '`' => {
+                    let len = enclosed(rest)?;
+                    let inner = rest[1..len.max(2) - 1].replace("\\`", "`");
+                    text.push_str(&format!("({})", self.inline(&inner)?));
+                    len
+                }
+                '$' if rest.starts_with("$'") => return Err("`$'...'` quoting"),
+                '$' if rest.starts_with("$\"") => 1,
+                '$' => {
+                    let (expansion, len, _) = self.dollar(rest, false)?;
+                    text.push_str(&expansion);
+                    len
+                }
+                '\\' => {
+                    let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                    text.push_str(&rest[..len]);
+                    len
+                }
+                c => {
+                    text.push(c);
+                    c.len_utf8()
+                }
+            };
+            rest = &rest[len..];
+        }
+        Ok(text)
+    }
+
+    /// A double quoted string, closing the quotes around command
+    /// substitutions since fish does not expand them inside quotes.
+    fn double_quoted(&mut self, inner: &str) -> Translated {
+        // `"$@"` and `"${array[@]}"` expand to one argument per element, like
+        // an unquoted list in fish.
+        if inner == "$@" || inner == "${@}" {
+            return Ok("$argv".into());
+        }
+        let array = inner
+            .strip_prefix("${")
+            .and_then(|inner| inner.strip_suffix("[@]}"));
+        if let Some(array) = array.filter(|array| bash::is_name(array)) {
+            return Ok(format!("${array}"));
+        }
+        let mut text = String::from("\"");
+        let mut rest = inner;
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                '\\' => {
+                    let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                    match &rest[..len] {
+                        "\\`" => text.push('`'),
+                        escaped => text.push_str(escaped),
+                    }
+                    len
+                }
+                '`' => {
+                    let len = enclosed(rest)?;
+                    let inner = rest[1..len.max(2) - 1].replace("\\`", "`");
+                    text.push_str(&format!("\"({})\"", self.inline(&inner)?));
+                    len
+                }
+                '$' => {
+                    let (expansion, len, substitution) = self.dollar(rest, true)?;
+                    if substitution {
+                        text.push_str(&format!("\"{expansion}\""));
+                    } else {
+                        text.push_str(&expansion);
+                        // `"${name}s"` cannot become `"$names"`.
+                        let next = rest[len..].chars().next();
+                        if next.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+                            && expansion.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                        {
+                            text.push_str("\"\"");
+                        }
+                    }
+                    len
+                }
+                c => {
+                    text.push(c);
+                    c.len_utf8()
+                }
+            };
+            rest = &rest[len..];
+        }
+        text.push('"');
+
+        let mut text = text.as_str();
+        if text.len() > 2 && text.starts_with("\"\"") {
+            text = &text[2..];
+        }
+        if text.len() > 2 && text.ends_with("\"\"") && !text.ends_with("\\\"\"") {
+            text = &text[..text.len() - 2];
+        }
+        Ok(text.to_string())
+    }
+
+    /// The expansion starting with `$` at the start of `text`: its fish
+    /// spelling, the bytes it takes up, and whether that spelling is a command
+    /// substitution.
+    fn dollar(&mut self, text: &str, quoted: bool) -> Result<(String, usize, bool), &'static str> {
+        let rest = &text[1..];
+        if rest.starts_with("((") {
+            let len = 1 + enclosed(rest)?;
+            let expression = text[3..len.saturating_sub(2).max(3)].trim();
+            return Ok((format!("(math \"{}\")", arithmetic(expression)?), len, true));
+        }
+        if rest.starts_with('(') {
+            let len = 1 + enclosed(rest)?;
+            let inner = &text[2..len.max(3) - 1];
+            return Ok((format!("({})", self.inline(inner)?), len, true));
+        }
+        if rest.starts_with('{') {
+            let len = 1 + enclosed(rest)?;
+            let inner = &text[2..len.max(3) - 1];
+            let (expansion, substitution) = self.braced(inner, quoted)?;
+            return Ok((expansion, len, substitution));
+        }
+        let name_len = match rest.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len()),
+            Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => 1,
+            // A `$` bash leaves alone.
+            _ => return Ok(("\\$".into(), 1, false)),
+        };
+        let (expansion, substitution) = special(&rest[..name_len])?;
+        Ok((expansion, 1 + name_len, substitution))
+    }
+
+    /// `${...}`: returns the fish spelling and whether it is a command
+    /// substitution.
+    fn braced(&mut self, inner: &str, quoted: bool) -> Result<(String, bool), &'static str> {
+        const UNSUPPORTED: &str = "parameter expansion `${...}`";
+        if let Some(name) = inner.strip_prefix('#') {
+            let array = name
+                .strip_suffix("[@]")
+                .or_else(|| name.strip_suffix("[*]"));
+            return match (name, array) {
+                ("" | "@" | "*", _) => Ok(("(count $argv)".into(), true)),
+                (_, Some(array)) if bash::is_name(array) => Ok((format!("(count ${array})"), true)),
+                (name, None) if bash::is_name(name) => {
+                    Ok((format!("(string length -- \"${name}\")"), true))
+                }
+                _ => Err(UNSUPPORTED),
+            };
+        }
+        let name_len = inner
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(inner.len());
+        let (name, operator) = inner.split_at(name_len);
+        if name.is_empty() {
+            return match inner {
+                "@" | "*" | "?" | "$" | "!" => special(inner),
+                _ => Err(UNSUPPORTED),
+            };
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            return match operator {
+                "" => Ok((format!("$argv[{name}]"), false)),
+                _ => Err(UNSUPPORTED),
+            };
+        }
+        let (variable, substitution) = special(name)?;
+        match operator {
+            "" if substitution || quoted => Ok((variable, substitution)),
+            "" => Ok((format!("{{{variable}}}"), false)),
+            "[@]" | "[*]" => Ok((variable, substitution)),
+            _ if operator.starts_with('[') && operator.ends_with(']') => {
+                let index: usize = operator[1..operator.len() - 1]
+                    .parse()
+                    .map_err(|_| "array index that is not a number")?;
+                Ok((format!("{variable}[{}]", index + 1), substitution))
+            }
+            _ if substitution => Err(UNSUPPORTED),
+            _ => Ok((self.operation(&variable, operator)?, true)),
+        }
+    }
+
+    /// A command substitution for `${name<operator>}`, like `${name:-word}`
+    /// or `${name%pattern}`, with `variable` the fish spelling of `$name`.
+    fn operation(&mut self, variable: &str, operator: &str) -> Translated {
+        const UNSUPPORTED: &str = "parameter expansion `${...}`";
+        if let Some(default) = operator.strip_prefix(":-") {
+            let default = self.word(default)?;
+            return Ok(format!(
+                "(test -n \"{variable}\"; and echo {variable}; or echo {default})"
+            ));
+        }
+        let split = operator
+            .find(|c: char| !"%#/:".contains(c))
+            .unwrap_or(operator.len());
+        let (op, operand) = operator.split_at(split.min(2));
+        if operand.contains(['$', '\'', '"', '`', '\\', '[']) {
+            return Err(UNSUPPORTED);
+        }
+        Ok(match op {
+            "%" | "%%" => {
+                let capture = if op == "%" { "(.*)" } else { "(.*?)" };
+                let regex = format!("^{capture}{}$", glob_regex(operand, true));
+                format!(
+                    "(string replace -r -- {} '$1' \"{variable}\")",
+                    single_quoted(&regex)
+                )
+            }
+            "#" | "##" => {
+                let regex = format!("^{}(.*)$", glob_regex(operand, op == "##"));
+                format!(
+                    "(string replace -r -- {} '$1' \"{variable}\")",
+                    single_quoted(&regex)
+                )
+            }
+            "/" | "//" => {
+                let (pattern, replacement) = operand.split_once('/').unwrap_or((operand, ""));
+                if pattern.is_empty() || has_glob(pattern) || pattern.starts_with(['#', '%']) {
+                    return Err(UNSUPPORTED);
+                }
+                let all = if op == "//" { "-a " } else { "" };
+                format!(
+                    "(string replace {all}-- {} {} \"{variable}\")",
+                    single_quoted(pattern),
+                    single_quoted(replacement)
+                )
+            }
+            ":" => {
+                let (offset, length) = match operand.split_once(':') {
+                    Some((offset, length)) => (offset, Some(length)),
+                    None => (operand, None),
+                };
+                let offset: usize = offset.trim().parse().map_err(|_| UNSUPPORTED)?;
+                let length = match length {
+                    Some(length) => {
+                        let length: usize = length.trim().parse().map_err(|_| UNSUPPORTED)?;
+                        format!(" -l {length}")
+                    }
+                    None => String::new(),
+                };
+                format!("(string sub -s {}{length} -- \"{variable}\")", offset + 1)
+            }
+            _ => return Err(UNSUPPORTED),
+        })
+    }
+
+    /// Statements to run in a command substitution, on one line.
+    fn inline(&mut self, src: &str) -> Translated {
+        let mut jobs = Vec::new();
+        for item in bash::parse(src) {
+            match &item.kind {
+                ItemKind::List(list) => {
+                    let text = self.and_or(list, 0)?;
+                    jobs.extend(text.lines().map(|line| line.trim().to_string()));
+                }
+                ItemKind::Unsupported(what) => return Err(what),
+                ItemKind::Comment(_) | ItemKind::Blank => {}
+            }
+        }
+        Ok(jobs.join("; "))
+    }
+}
+
+/// The fish spelling of the variable `name` and whether it is a command
+/// substitution.
+fn special(name: &str) -> Result<(String, bool), &'static str> {
+    Ok(match name {
+        "@" | "*" => ("$argv".into(), false),
+        "#" => ("(count $argv)".into(), true),
+        "?" => ("$status".into(), false),
+        "$" => ("$fish_pid".into(), false),
+        "!" => ("$last_pid".into(), false),
+        "0" | "BASH_SOURCE" => ("(status filename)".into(), true),
+        "-" => return Err("`$-`"),
+        "RANDOM" => ("(random)".into(), true),
+        "PIPESTATUS" => ("$pipestatus".into(), false),
+        "FUNCNAME" => ("(status function)".into(), true),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => (format!("$argv[{name}]"), false),
+        name => (format!("${name}"), false),
+    })
+}
+
+/// A bash arithmetic expression for `math`, which has no assignments or
+/// comparisons and needs `$` on variables.
+fn arithmetic(expression: &str) -> Translated {
+    const UNSUPPORTED: &[&str] = &["=", "++", "--", "?", "<", ">", "&&", "||", "!", ","];
+    if UNSUPPORTED.iter().any(|op| expression.contains(op)) {
+        return Err("arithmetic assignment or comparison");
+    }
+    let mut text = String::new();
+    let mut rest = expression;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '$' => {
+                let name_len = rest[1..]
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len() - 1);
+                let name = &rest[1..1 + name_len];
+                match name.parse::<usize>() {
+                    Ok(_) => text.push_str(&format!("$argv[{name}]")),
+                    Err(_) => text.push_str(&rest[..1 + name_len]),
+                }
+                1 + name_len
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                text.push_str(&format!("${}", &rest[..len]));
+                len
+            }
+            '*' if rest.starts_with("**") => {
+                text.push('^');
+                2
+            }
+            '"' | '`' => return Err("arithmetic with quotes or substitutions"),
+            c => {
+                text.push(c);
+                c.len_utf8()
+            }
+        };
+        rest = &rest[len..];
+    }
+    Ok(text)
+}
+
+/// The length of the quoted or substituted text at the start of `text`.
+fn enclosed(text: &str) -> Result<usize, &'static str> {
+    let len = bash::matching(text);
+    let close = match text.chars().next() {
+        Some('(') => ')',
+        Some('{') => '}',
+        Some(open) => open,
+        None => return Ok(0),
+    };
+    match len >= 2 && text[..len].ends_with(close) {
+        true => Ok(len),
+        false => Err("unclosed quote or substitution"),
+    }
+}
+
+/// A regex for a bash glob, matching as much as possible if `greedy`.
+fn glob_regex(glob: &str, greedy: bool) -> String {
+    let mut regex = String::new();
+    for c in glob.chars() {
+        match c {
+            '*' if greedy => regex.push_str(".*"),
+            '*' => regex.push_str(".*?"),
+            '?' => regex.push('.'),
+            c if "\\.^$|()[]{}+".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex
+}
+
+pub fn single_quoted(text: &str) -> String {
+    let mut quoted = String::from("'");
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        // Fish only unescapes `\\` and `\'` in single quotes.
+        if c == '\'' || c == '\\' && matches!(chars.peek(), None | Some('\\' | '\'')) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Whether `word` has glob characters outside quotes.
+fn has_glob(word: &str) -> bool {
+    let mut rest = word;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '\'' | '"' => bash::matching(rest),
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            '*' | '?' | '[' => return true,
+            c => c.len_utf8(),
+        };
+        rest = &rest[len..];
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::parse};
+
+    fn check(bash: &str, fish: &str) {
+        let translation = translate(bash);
+        assert_eq!(translation.text, fish);
+        assert_eq!(translation.untranslated, [] as [&str; 0]);
+    }
+
+    #[test]
+    fn assignments() {
+        check(
+            "export PATH=\"$HOME/bin:$PATH\"\nname=world\necho \"hello $name\"",
+            "set -gx PATH \"$HOME/bin:$PATH\"\nset name world\necho \"hello $name\"",
+        );
+        check("x=$((1 + 2))", "set x (math \"1 + 2\")");
+        check(
+            "arr=(a b c)\necho ${arr[0]} ${#arr[@]}",
+            "set arr a b c\necho $arr[1] (count $arr)",
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        check(
+            "if [ -f \"$file\" ]; then\n  echo yes\nelif [[ $x == a* ]]; then\n  echo glob\nelse\n  echo no\nfi",
+            "if [ -f \"$file\" ]\n    echo yes\nelse if string match -q -- 'a*' \"$x\"\n    echo glob\nelse\n    echo no\nend",
+        );
+        check(
+            "for f in *.txt; do\n  echo \"${f%.txt}\"\ndone",
+            "for f in *.txt\n    echo (string replace -r -- '^(.*)\\.txt$' '$1' \"$f\")\nend",
+        );
+        check(
+            "while read -r line; do echo $line; done < input",
+            "while read line\n    echo $line\nend < input",
+        );
+        check(
+            "case $1 in\n  start|run) echo go ;;\n  *) echo other ;;\nesac",
+            "switch $argv[1]\n    case start run\n        echo go\n    case '*'\n        echo other\nend",
+        );
+        check("cmd && echo ok || echo fail", "cmd && echo ok || echo fail");
+    }
+
+    #[test]
+    fn functions() {
+        check(
+            "greet() {\n  local who=$1\n  echo \"hi $who\"\n}",
+            "function greet\n    set -l who $argv[1]\n    echo \"hi $who\"\nend",
+        );
+    }
+
+    #[test]
+    fn expansions() {
+        check(
+            "echo $(date) `whoami` $? $# $@",
+            "echo (date) (whoami) $status (count $argv) $argv",
+        );
+        assert_eq!(expansion("$?", false).as_deref(), Some("$status"));
+        assert_eq!(
+            expansion("${#x}", false).as_deref(),
+            Some("(string length -- \"$x\")")
+        );
+        assert_eq!(expansion("$(date)", true).as_deref(), Some("\"(date)\""));
+        assert_eq!(expansion("$HOME/x", false), None);
+    }
+
+    #[test]
+    fn untranslated_constructs() {
+        let translation = translate("(cd /tmp; ls)\ndeclare -A map\n(cd /tmp)\necho $'a\\tb'");
+        assert_eq!(
+            translation.text,
+            "\
+# TODO: translate by hand (subshell `( )`):
+# (cd /tmp; ls)
+# TODO: translate by hand (associative array):
+# declare -A map
+# TODO: translate by hand (subshell `( )`):
+# (cd /tmp)
+# TODO: translate by hand (`$'...'` quoting):
+# echo $'a\\tb'"
+        );
+        assert_eq!(
+            translation.untranslated,
+            ["subshell `( )`", "associative array", "`$'...'` quoting"]
+        );
+    }
+
+    #[test]
+    fn code_actions() {
+        let uri = Url::parse("file:///project/script.fish").unwrap();
+        let tree = parse("function f\n    x=1\n    shopt -s extglob\nend\n");
+        let range = Range::new(Position::new(1, 0), Position::new(3, 0));
+        let action = translate_action(&uri, &tree, range).unwrap();
+        assert_eq!(
+            action.title,
+            "Translate bash to fish (could not translate: `shopt`)"
+        );
+        let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(1, 0), Position::new(2, 20))
+        );
+        assert_eq!(
+            edit.new_text,
+            "    set x 1\n    # TODO: translate by hand (`shopt`):\n    # shopt -s extglob"
+        );
+
+        // Nothing to offer for fish, or without a selection.
+        let tree = parse("set x 1\necho $x\n");
+        let all = Range::new(Position::new(0, 0), Position::new(2, 0));
+        assert_eq!(translate_action(&uri, &tree, all), None);
+        let empty = Range::new(Position::new(0, 0), Position::new(0, 0));
+        assert_eq!(translate_action(&uri, &tree, empty), None);
+    }
+}
@@ -0,0 +1,422 @@
+use {
+    crate::{
+        config::PROJECT_FILE,
+        parser::{parse, SyntaxTree},
+        server::State,
+        symbols::{Symbol, Symbols},
+    },
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::{
+        sync::{RwLock, Semaphore},
+        task::JoinSet,
+    },
+    tower_lsp::{
+        lsp_types::{notification::Progress, request::WorkDoneProgressCreate, *},
+        Client,
+    },
+};
+
+/// Directories that never hold fish configuration worth indexing.
+const SKIPPED_DIRS: &[&str] = &[".git", ".hg", ".svn", "node_modules", "target"];
+
+/// The most files parsed at the same time while indexing.
+const MAX_WORKERS: usize = 8;
+
+const PROGRESS_TOKEN: &str = "fish-lsp/indexing";
+
+/// How many results a workspace symbol search returns at most.
+const MAX_SYMBOLS: usize = 512;
+
+/// Every fish file found in the workspace folders, parsed. Open documents
+/// take precedence over these copies, which follow the files on disk.
+#[derive(Debug, Default, Clone)]
+pub struct Workspace {
+    pub files: HashMap<Url, IndexedFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexedFile {
+    pub tree: SyntaxTree,
+    pub symbols: Symbols,
+}
+
+impl IndexedFile {
+    pub fn new(text: &str) -> Self {
+        let tree = parse(text);
+        let symbols = Symbols::extract(&tree);
+        Self { tree, symbols }
+    }
+
+    pub fn read(path: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(path).ok()?;
+        Some(Self::new(&text))
+    }
+}
+
+/// All `*.fish` files below `folder`, without following symlinked
+/// directories.
+pub fn fish_files(folder: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![folder.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                let skipped = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| SKIPPED_DIRS.contains(&name));
+                if !skipped {
+                    pending.push(path);
+                }
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "fish")
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Parse every fish file in `folders` on a bounded pool of blocking workers,
+/// adding each to the workspace index as soon as it is parsed and reporting
+/// progress to the client when it supports work done progress.
+pub async fn index(client: Client, state: Arc<RwLock<State>>, folders: Vec<PathBuf>) {
+    let progress = state
+        .read()
+        .await
+        .client_capabilities
+        .window
+        .as_ref()
+        .and_then(|window| window.work_done_progress)
+        .unwrap_or(false);
+    let token = NumberOrString::String(PROGRESS_TOKEN.to_string());
+    let progress = progress
+        && client
+            .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .is_ok();
+    let report = |value: WorkDoneProgress| {
+        let client = client.clone();
+        let token = token.clone();
+        async move {
+            if progress {
+                client
+                    .send_notification::<Progress>(ProgressParams {
+                        token,
+                        value: ProgressParamsValue::WorkDone(value),
+                    })
+                    .await;
+            }
+        }
+    };
+
+    report(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+        title: "Indexing fish files".to_string(),
+        cancellable: Some(false),
+        message: None,
+        percentage: Some(0),
+    }))
+    .await;
+
+    let files = tokio::task::spawn_blocking(move || {
+        folders
+            .iter()
+            .flat_map(|folder| fish_files(folder))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get().min(MAX_WORKERS));
+    let permits = Arc::new(Semaphore::new(workers));
+    let mut tasks = JoinSet::new();
+    let total = files.len();
+    for path in files {
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await.ok()?;
+            let uri = Url::from_file_path(&path).ok()?;
+            let file = tokio::task::spawn_blocking(move || IndexedFile::read(&path))
+                .await
+                .ok()??;
+            Some((uri, file))
+        });
+    }
+
+    let mut done = 0;
+    let mut reported = 0;
+    while let Some(result) = tasks.join_next().await {
+        done += 1;
+        if let Ok(Some((uri, file))) = result {
+            state.write().await.workspace.files.insert(uri, file);
+        }
+
+        let percentage = (done * 100 / total) as u32;
+        if percentage >= reported + 5 {
+            reported = percentage;
+            report(WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(format!("{done}/{total} files")),
+                percentage: Some(percentage),
+            }))
+            .await;
+        }
+    }
+
+    report(WorkDoneProgress::End(WorkDoneProgressEnd {
+        message: Some(format!("Indexed {total} fish files")),
+    }))
+    .await;
+}
+
+/// Ask the client to notify the server about changes to fish files and
+/// project settings on disk.
+pub async fn watch_files(client: &Client, state: &RwLock<State>) {
+    let dynamic = state
+        .read()
+        .await
+        .client_capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|watched| watched.dynamic_registration)
+        .unwrap_or(false);
+    if !dynamic {
+        return;
+    }
+
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.fish".to_string()),
+                kind: None,
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{PROJECT_FILE}")),
+                kind: None,
+            },
+        ],
+    };
+    let registration = Registration {
+        id: "fish-lsp/watched-files".to_string(),
+        method: "workspace/didChangeWatchedFiles".to_string(),
+        register_options: serde_json::to_value(options).ok(),
+    };
+    if let Err(error) = client.register_capability(vec![registration]).await {
+        client
+            .log_message(
+                MessageType::WARNING,
+                format!("Could not watch fish files: {error}"),
+            )
+            .await;
+    }
+}
+
+/// Bring the index up to date with files created, changed or deleted on disk.
+pub async fn apply_file_events(state: &RwLock<State>, events: Vec<FileEvent>) {
+    for event in events {
+        let Ok(path) = event.uri.to_file_path() else {
+            continue;
+        };
+        if path.extension().is_none_or(|extension| extension != "fish") {
+            continue;
+        }
+
+        let file = match event.typ {
+            FileChangeType::DELETED => None,
+            _ => tokio::task::spawn_blocking(move || IndexedFile::read(&path))
+                .await
+                .ok()
+                .flatten(),
+        };
+        let files = &mut state.write().await.workspace.files;
+        match file {
+            Some(file) => files.insert(event.uri, file),
+            None => files.remove(&event.uri),
+        };
+    }
+}
+
+/// Re-read a file from disk after it was closed, dropping any unsaved
+/// changes the index may not know about. Files outside the workspace folders
+/// are left out of the index.
+pub async fn refresh(state: &RwLock<State>, uri: &Url) {
+    let Ok(path) = uri.to_file_path() else {
+        return;
+    };
+    let in_workspace = state
+        .read()
+        .await
+        .workspace_folders
+        .iter()
+        .any(|folder| path.starts_with(folder));
+    if !in_workspace || path.extension().is_none_or(|extension| extension != "fish") {
+        return;
+    }
+
+    let file = tokio::task::spawn_blocking(move || IndexedFile::read(&path))
+        .await
+        .ok()
+        .flatten();
+    let files = &mut state.write().await.workspace.files;
+    match file {
+        Some(file) => files.insert(uri.clone(), file),
+        None => files.remove(uri),
+    };
+}
+
+/// Functions, global variables, abbreviations and completions across the
+/// workspace whose names fuzzily match `query`, best matches first.
+#[allow(deprecated)]
+pub fn workspace_symbols(state: &State, query: &str) -> Vec<SymbolInformation> {
+    let open = state
+        .documents
+        .iter()
+        .map(|(uri, document)| (uri, &document.tree, &document.symbols));
+    let indexed = state
+        .workspace
+        .files
+        .iter()
+        .filter(|(uri, _)| !state.documents.contains_key(uri))
+        .map(|(uri, file)| (uri, &file.tree, &file.symbols));
+
+    let mut matches = Vec::new();
+    for (uri, tree, symbols) in open.chain(indexed) {
+        let container = uri
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .map(str::to_string);
+        let groups: [(&[Symbol], SymbolKind, &str); 4] = [
+            (&symbols.functions, SymbolKind::FUNCTION, ""),
+            (&symbols.globals, SymbolKind::VARIABLE, ""),
+            (&symbols.abbreviations, SymbolKind::CONSTANT, ""),
+            (&symbols.completions, SymbolKind::KEY, "complete "),
+        ];
+        for (group, kind, prefix) in groups {
+            for symbol in group {
+                let Some(score) = fuzzy_score(query, &symbol.name) else {
+                    continue;
+                };
+                let information = SymbolInformation {
+                    name: format!("{prefix}{}", symbol.name),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri.clone(), tree.lsp_range(symbol.selection_range)),
+                    container_name: container.clone(),
+                };
+                matches.push((score, information));
+            }
+        }
+    }
+
+    matches.sort_by(|(a, a_info), (b, b_info)| {
+        b.cmp(a)
+            .then_with(|| a_info.name.cmp(&b_info.name))
+            .then_with(|| {
+                a_info
+                    .location
+                    .uri
+                    .as_str()
+                    .cmp(b_info.location.uri.as_str())
+            })
+    });
+    matches.truncate(MAX_SYMBOLS);
+    matches
+        .into_iter()
+        .map(|(_, information)| information)
+        .collect()
+}
+
+/// How well `candidate` matches `query` when the query's characters appear in
+/// it in order, ignoring case and whitespace. Exact and prefix matches,
+/// consecutive characters and characters at the start of a word score
+/// higher. `None` when it does not match at all.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: String = query.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = candidate.chars().enumerate();
+    let mut before = None;
+
+    for wanted in query.chars() {
+        loop {
+            let (index, c) = chars.next()?;
+            let boundary = match before {
+                None => true,
+                Some(before) => matches!(before, '_' | '-' | '.' | '/' | ':'),
+            };
+            before = Some(c);
+            if !c.to_lowercase().eq(wanted.to_lowercase()) {
+                continue;
+            }
+
+            score += 1;
+            if boundary {
+                score += 8;
+            }
+            if previous.is_some_and(|previous| previous + 1 == index) {
+                score += 5;
+            }
+            if c == wanted {
+                score += 1;
+            }
+            previous = Some(index);
+            break;
+        }
+    }
+
+    let query = query.to_lowercase();
+    let candidate_lower = candidate.to_lowercase();
+    if candidate_lower == query {
+        score += 20;
+    } else if candidate_lower.starts_with(&query) {
+        score += 10;
+    }
+
+    Some(score * 16 - candidate.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_scores() {
+        assert_eq!(fuzzy_score("gt", "git"), fuzzy_score("g t", "git"));
+        assert_eq!(fuzzy_score("foo ", "foobar"), fuzzy_score("foo", "foobar"));
+        assert_eq!(fuzzy_score(" Foo", "foo"), fuzzy_score("Foo", "foo"));
+        assert_eq!(fuzzy_score("xyz", "foo"), None);
+        assert_eq!(fuzzy_score("oof", "foo"), None);
+
+        let ranked = |query: &str, candidates: &[&'static str]| {
+            let mut ranked = candidates.to_vec();
+            ranked.sort_by_key(|candidate| std::cmp::Reverse(fuzzy_score(query, candidate)));
+            ranked
+        };
+        assert_eq!(
+            ranked("foo ", &["afoo", "foobar", "foo"]),
+            ["foo", "foobar", "afoo"]
+        );
+        assert_eq!(
+            ranked("gc", &["xgxc", "git_checkout", "gc_old"]),
+            ["gc_old", "git_checkout", "xgxc"]
+        );
+    }
+}
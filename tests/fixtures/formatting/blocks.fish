@@ -0,0 +1,23 @@
+function greet --description 'Say hello'
+echo hello $argv
+  if test (count $argv) -gt 1; echo many
+else if test (count $argv) -eq 1
+      echo one
+else
+echo none
+    end
+end
+
+for file in *.fish
+echo $file
+end
+while read -l line; echo $line; end
+begin; echo a; echo b; end
+switch $argv[1]
+case start
+    echo starting
+case 'stop' kill
+echo stopping
+  case '*'
+echo unknown
+end
@@ -0,0 +1,11 @@
+# A leading comment
+# indented comment
+function f # trailing comment
+    # inside
+    echo a # after a command
+end
+switch $x
+    # before a case
+    case a
+        echo a
+end
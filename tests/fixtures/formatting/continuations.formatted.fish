@@ -0,0 +1,10 @@
+set -l options \
+    --long \
+    --other
+echo one |
+    cat |
+    sort
+if test -n "$a"
+    and test -n "$b"
+    echo both
+end
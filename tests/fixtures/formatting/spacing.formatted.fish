@@ -0,0 +1,11 @@
+echo a b c
+cat file | grep foo | sort
+test -f a && echo yes || echo no
+echo out >out.txt 2>&1
+echo err 2>/dev/null
+cat <input.txt >>output.txt
+sleep 10 &
+not test -d dir
+set -l x (string join , a b)
+
+echo after blank lines
@@ -0,0 +1,8 @@
+if test -n "$HOME"; then
+    echo ${HOME} $?
+fi
+export EDITOR=vim
+[[ -n $EDITOR ]]
+cat <<EOF
+hello
+EOF
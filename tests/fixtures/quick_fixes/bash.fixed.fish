@@ -0,0 +1,6 @@
+if test -n "$HOME"
+    echo {$HOME} $status
+end
+set -gx EDITOR vim
+test -n $EDITOR
+printf '%s\n' "hello" | cat
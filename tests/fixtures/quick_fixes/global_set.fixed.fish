@@ -0,0 +1,6 @@
+set -g count 0
+
+function count_args
+    set -l count (count $argv)
+    echo $count
+end
@@ -0,0 +1,3 @@
+function greet
+    if test -n "$argv"
+        echo hello $argv
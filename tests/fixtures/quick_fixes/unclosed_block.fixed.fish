@@ -0,0 +1,5 @@
+function greet
+    if test -n "$argv"
+        echo hello $argv
+    end
+end